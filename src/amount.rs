@@ -0,0 +1,183 @@
+// Amounts in Norwegian document text, read to the øre as integers. Handles
+// the usual ways of printing kroner: "1 245,50", "1.245,50", "1245.50",
+// "kr 245,-", "245:-" and "NOK 63,40".
+//
+// A receipt holds many amounts, so every candidate is ranked by the label on
+// its line: Å BETALE over TOTALT/SUM over the card payment line over item
+// lines. MVA summaries, discounts, cash tendered and change are never totals.
+
+use crate::money::{Money, MAX_KRONER};
+use regex::Regex;
+
+lazy_static::lazy_static! {
+    // The integer part either groups thousands with a space or dot ("1 245",
+    // "1.245") or is plain digits; decimals are two digits or a dash
+    static ref AMOUNT: Regex = Regex::new(
+        r"(?i)(?P<prefix>\bkr\.?|\bnok)?\s?(?P<sign>-)?(?P<kroner>\d{1,3}(?:[ \u{a0}.]\d{3})+|\d+)(?:[.,](?P<ore>\d{2})|(?P<dash>[.,:]-))?(?P<suffix>\s?(?:kr\b|nok\b|,-))?"
+    ).unwrap();
+}
+
+// Line labels by how surely they mark the amount paid
+const PAYABLE_LABELS: &[&str] = &["Å BETALE", "A BETALE", "TIL BETALING"];
+const TOTAL_LABELS: &[&str] = &["TOTALT", "TOTAL", "TOTALSUM", "SUM", "SUMMA", "BELØP"];
+const PAYMENT_LABELS: &[&str] = &["BETALT", "KORT", "BANKAXEPT", "BANKKORT", "VISA", "MASTERCARD", "VIPPS"];
+// Amounts on these lines are parts of the purchase or of the payment, not its total
+const EXCLUDED_LABELS: &[&str] = &[
+    "MVA", "HERAV", "GRUNNLAG", "NETTO", "RABATT", "AVSLAG", "SPART", "KONTANT", "VEKSEL", "TILBAKE",
+    "DELSUM", "SUBTOTAL", "MELLOMSUM", "PANT",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Priority {
+    Unmarked, // decimals only, e.g. an item price
+    Currency, // printed with kr, NOK or ,-
+    Payment,
+    Total,
+    Payable,
+}
+
+#[derive(Clone)]
+pub struct ExtractedAmount {
+    pub amount: Money,
+    pub line: usize, // 1-based
+}
+
+struct Candidate {
+    amount: ExtractedAmount,
+    priority: Priority,
+}
+
+// The amount the document is about: its total when one is labelled, else the
+// largest amount printed with decimals or a currency
+pub fn extract_amount(text: &str) -> Option<ExtractedAmount> {
+    let mut candidates = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let upper = line.to_uppercase();
+        let label = [(PAYABLE_LABELS, Priority::Payable), (TOTAL_LABELS, Priority::Total), (PAYMENT_LABELS, Priority::Payment)]
+            .into_iter()
+            .find(|(labels, _)| has_label(&upper, labels))
+            .map(|(_, priority)| priority);
+        if label.is_none() && has_label(&upper, EXCLUDED_LABELS) {
+            continue;
+        }
+
+        for found in amounts_in(line) {
+            let priority = match label {
+                Some(priority) if found.has_ore || found.currency => priority,
+                // "SUM 245" counts, but not above "KORT 63,40" when the
+                // labelled integer is an item count ("SUM 2 varer")
+                Some(_) => Priority::Currency,
+                None if found.currency => Priority::Currency,
+                None if found.has_ore => Priority::Unmarked,
+                // A bare integer is a quantity, a date part or a phone number
+                None => continue,
+            };
+            candidates.push(Candidate { amount: ExtractedAmount { amount: Money::from_ore(found.ore), line: index + 1 }, priority });
+        }
+    }
+
+    let best_priority = candidates.iter().map(|candidate| candidate.priority).max()?;
+    let ranked = candidates.into_iter().filter(|candidate| candidate.priority == best_priority);
+    if best_priority >= Priority::Payment {
+        // The last total printed is the final one
+        ranked.max_by_key(|candidate| candidate.amount.line)
+    } else {
+        ranked.max_by_key(|candidate| candidate.amount.amount)
+    }.map(|candidate| candidate.amount)
+}
+
+// A single amount such as "1 245,50", "kr 245,-" or "63.40"
+pub fn parse_amount(text: &str) -> Option<Money> {
+    let found = amounts_in(text.trim());
+    match found.as_slice() {
+        [only] => Some(Money::from_ore(only.ore)),
+        _ => None,
+    }
+}
+
+struct FoundAmount {
+    ore: i64,
+    has_ore: bool,
+    currency: bool,
+}
+
+fn amounts_in(line: &str) -> Vec<FoundAmount> {
+    let mut found = Vec::new();
+    for captures in AMOUNT.captures_iter(line) {
+        let whole = captures.get(0).unwrap();
+        let number = captures.name("kroner").unwrap();
+        // Part of a date, time, account number or word: "17.05.2024", "A4"
+        let start = captures.name("sign").unwrap_or(number).start();
+        let before = line[..start].chars().next_back();
+        let after = &line[whole.end()..];
+        if before.is_some_and(|c| c.is_alphanumeric() || c == '.' || c == ',' || c == ':' || c == '/')
+            || after.starts_with(|c: char| c.is_alphanumeric())
+            || after.starts_with(|c: char| matches!(c, '.' | ',' | ':' | '/' | '-') && after[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            continue;
+        }
+
+        let digits: String = number.as_str().chars().filter(char::is_ascii_digit).collect();
+        let Ok(kroner) = digits.parse::<i64>() else { continue };
+        // A KID, account number or OCR noise rather than an amount; the cap
+        // also keeps the øre below overflow
+        if kroner > MAX_KRONER {
+            continue;
+        }
+        let ore = captures.name("ore").and_then(|ore| ore.as_str().parse::<i64>().ok());
+        let sign = if captures.name("sign").is_some() { -1 } else { 1 };
+        found.push(FoundAmount {
+            ore: sign * (kroner * 100 + ore.unwrap_or(0)),
+            has_ore: ore.is_some(),
+            currency: captures.name("prefix").is_some() || captures.name("suffix").is_some() || captures.name("dash").is_some(),
+        });
+    }
+    found
+}
+
+fn has_label(upper: &str, labels: &[&str]) -> bool {
+    labels.iter().any(|label| {
+        upper.match_indices(label).any(|(start, _)| {
+            let before = upper[..start].chars().next_back();
+            let after = upper[start + label.len()..].chars().next();
+            !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn norwegian_formats_are_parsed() {
+        assert_eq!(parse_amount("1 245,50"), Some(Money::from_ore(124550)));
+        assert_eq!(parse_amount("1.245,50"), Some(Money::from_ore(124550)));
+        assert_eq!(parse_amount("1245.50"), Some(Money::from_ore(124550)));
+        assert_eq!(parse_amount("kr 245,-"), Some(Money::nok(245)));
+        assert_eq!(parse_amount("245:-"), Some(Money::nok(245)));
+        assert_eq!(parse_amount("NOK 63,40"), Some(Money::from_ore(6340)));
+        assert_eq!(parse_amount("-12,90"), Some(Money::from_ore(-1290)));
+    }
+
+    #[test]
+    fn dates_and_several_amounts_are_not_an_amount() {
+        assert_eq!(parse_amount("17.05.2024"), None);
+        assert_eq!(parse_amount("12,90 24,50"), None);
+    }
+
+    #[test]
+    fn numbers_beyond_the_cap_are_skipped_without_overflow() {
+        assert_eq!(parse_amount("99999999999999999,00"), None);
+        assert_eq!(parse_amount("99999999999999999999999"), None);
+        let found = amounts_in("KID 92233720368547758 kr 245,-");
+        assert_eq!(found.iter().map(|found| found.ore).collect::<Vec<_>>(), [24500]);
+    }
+
+    #[test]
+    fn labelled_total_wins_over_larger_amounts() {
+        let extracted = extract_amount("MELK 21,90\nGAVEKORT 500,00\nSUM 2 varer\nKORT 63,40\nMVA 15% 8,27").unwrap();
+        assert_eq!(extracted.amount, Money::from_ore(6340));
+        assert_eq!(extracted.line, 4);
+    }
+}
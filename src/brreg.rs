@@ -0,0 +1,570 @@
+// Local index of the Brønnøysund Register Centre's Enhetsregisteret, so
+// sellers outside the built-in merchant database can be resolved by their
+// organisation number (name, NACE industry code, VAT registration, address)
+// without network access.
+//
+// BRREG_INDEX_PATH=/data/enhetsregisteret.sqlite  SQLite file holding the index
+//     (kept in memory when unset)
+// BRREG_IMPORT=/data/enheter_alle.json.gz  bulk dump to import on startup, as
+//     downloaded from data.brreg.no: JSON or CSV, optionally gzipped. An
+//     unchanged file is not imported twice into the same index
+// BRREG_API_URL=https://data.brreg.no/enhetsregisteret/api  live API for
+//     numbers missing from the index (or a local stand-in); off when unset.
+//     At most MAX_LOOKUPS_PER_REQUEST numbers are looked up per request, all
+//     at once, so a request waits for one timeout at worst
+// BRREG_TIMEOUT_SECS=5  timeout for live API lookups
+
+use flate2::read::MultiGzDecoder;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+// Numbers one request may ask the live API for; the rest wait for a later request
+const MAX_LOOKUPS_PER_REQUEST: usize = 5;
+
+// Numbers the API did not know that are remembered before the list starts over
+const MAX_UNKNOWN_NUMBERS: usize = 10_000;
+
+#[derive(Debug)]
+pub struct RegisterError(String);
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<rusqlite::Error> for RegisterError {
+    fn from(error: rusqlite::Error) -> Self {
+        RegisterError(error.to_string())
+    }
+}
+
+impl From<std::io::Error> for RegisterError {
+    fn from(error: std::io::Error) -> Self {
+        RegisterError(error.to_string())
+    }
+}
+
+impl From<csv::Error> for RegisterError {
+    fn from(error: csv::Error) -> Self {
+        RegisterError(error.to_string())
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct RegisteredEntity {
+    pub org_number: String,
+    pub name: String,
+    pub organization_form: Option<String>, // AS, ENK, ASA, ...
+    pub nace_code: Option<String>, // e.g. 47.111
+    pub nace_description: Option<String>,
+    pub vat_registered: bool, // in Merverdiavgiftsregisteret
+    pub address: Option<String>,
+    pub municipality: Option<String>,
+    pub bankrupt: bool,
+    pub source: String, // snapshot or api
+}
+
+// One entity as it appears in the JSON dump and in API responses
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EntityRecord {
+    organisasjonsnummer: String,
+    navn: Option<String>,
+    organisasjonsform: Option<CodeRecord>,
+    naeringskode1: Option<CodeRecord>,
+    #[serde(default)]
+    registrert_i_mvaregisteret: bool,
+    forretningsadresse: Option<AddressRecord>,
+    postadresse: Option<AddressRecord>,
+    #[serde(default)]
+    konkurs: bool,
+}
+
+#[derive(Deserialize)]
+struct CodeRecord {
+    kode: String,
+    beskrivelse: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AddressRecord {
+    #[serde(default)]
+    adresse: Vec<String>,
+    postnummer: Option<String>,
+    poststed: Option<String>,
+    kommune: Option<String>,
+}
+
+impl AddressRecord {
+    // "Biskop Gunnerus' gate 14A, 0185 OSLO"
+    fn formatted(&self) -> Option<String> {
+        let mut parts: Vec<String> = self.adresse.iter().filter(|line| !line.trim().is_empty()).cloned().collect();
+        let place = [self.postnummer.as_deref(), self.poststed.as_deref()].into_iter().flatten().collect::<Vec<_>>().join(" ");
+        if !place.is_empty() {
+            parts.push(place);
+        }
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+}
+
+impl EntityRecord {
+    fn into_entity(self, source: &str) -> Option<RegisteredEntity> {
+        let org_number: String = self.organisasjonsnummer.chars().filter(char::is_ascii_digit).collect();
+        if org_number.len() != 9 {
+            return None;
+        }
+        // Sole proprietorships often have only a postal address
+        let address = self.forretningsadresse.or(self.postadresse);
+        Some(RegisteredEntity {
+            org_number,
+            name: self.navn?,
+            organization_form: self.organisasjonsform.map(|form| form.kode),
+            nace_code: self.naeringskode1.as_ref().map(|nace| nace.kode.clone()),
+            nace_description: self.naeringskode1.and_then(|nace| nace.beskrivelse),
+            vat_registered: self.registrert_i_mvaregisteret,
+            address: address.as_ref().and_then(AddressRecord::formatted),
+            municipality: address.and_then(|address| address.kommune),
+            bankrupt: self.konkurs,
+            source: source.to_string(),
+        })
+    }
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS entities (
+        org_number TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        organization_form TEXT,
+        nace_code TEXT,
+        nace_description TEXT,
+        vat_registered INTEGER NOT NULL,
+        address TEXT,
+        municipality TEXT,
+        bankrupt INTEGER NOT NULL,
+        source TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS imports (
+        path TEXT NOT NULL,
+        modified INTEGER NOT NULL,
+        entities INTEGER NOT NULL,
+        imported_at TEXT NOT NULL
+    );
+";
+
+pub struct BusinessRegister {
+    connection: Mutex<Connection>,
+    api: Option<ApiClient>,
+    // Numbers the live API did not know, so they are not asked for again
+    unknown: Mutex<HashSet<String>>,
+}
+
+struct ApiClient {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl BusinessRegister {
+    pub fn from_env() -> Self {
+        let path = env::var("BRREG_INDEX_PATH").ok();
+        let connection = match &path {
+            Some(path) => Connection::open(path),
+            None => Connection::open_in_memory(),
+        };
+        let connection = match connection.and_then(|connection| connection.execute_batch(SCHEMA).map(|_| connection)) {
+            Ok(connection) => connection,
+            Err(e) => panic!("❌ Could not open the Enhetsregisteret index at {}: {}", path.as_deref().unwrap_or(":memory:"), e),
+        };
+
+        let api = env::var("BRREG_API_URL").ok().filter(|url| !url.is_empty()).map(|url| {
+            let timeout_secs = env::var("BRREG_TIMEOUT_SECS").ok().and_then(|value| value.parse().ok()).unwrap_or(5);
+            ApiClient {
+                base_url: url.trim_end_matches('/').to_string(),
+                client: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(timeout_secs))
+                    .build()
+                    .unwrap_or_else(|_| reqwest::Client::new()),
+            }
+        });
+
+        let register = BusinessRegister { connection: Mutex::new(connection), api, unknown: Mutex::new(HashSet::new()) };
+
+        if let Ok(dump) = env::var("BRREG_IMPORT") {
+            // The dump was asked for explicitly, so a broken one stops the boot
+            match register.import_if_changed(&dump) {
+                Ok(Some(count)) => println!("   - Imported {} entities from {}", count, dump),
+                Ok(None) => println!("   - {} is unchanged since the last import", dump),
+                Err(e) => panic!("❌ Could not import Enhetsregisteret data from {}: {}", dump, e),
+            }
+        }
+
+        println!("   - {} entities in {}", register.len(), path.as_deref().unwrap_or("an in-memory index"));
+        match &register.api {
+            Some(api) => println!("   - Live lookups of unknown numbers via {}", api.base_url),
+            None => println!("   - Offline, numbers missing from the index are not looked up"),
+        }
+        register
+    }
+
+    pub fn len(&self) -> usize {
+        self.connection.lock().ok()
+            .and_then(|connection| connection.query_row("SELECT COUNT(*) FROM entities", [], |row| row.get::<_, i64>(0)).ok())
+            .unwrap_or(0) as usize
+    }
+
+    // Entity from the local index only; never touches the network
+    pub fn lookup(&self, org_number: &str) -> Option<RegisteredEntity> {
+        let connection = self.connection.lock().ok()?;
+        connection.query_row(
+            "SELECT org_number, name, organization_form, nace_code, nace_description, vat_registered,
+                    address, municipality, bankrupt, source
+             FROM entities WHERE org_number = ?1",
+            params![org_number],
+            |row| Ok(RegisteredEntity {
+                org_number: row.get(0)?,
+                name: row.get(1)?,
+                organization_form: row.get(2)?,
+                nace_code: row.get(3)?,
+                nace_description: row.get(4)?,
+                vat_registered: row.get(5)?,
+                address: row.get(6)?,
+                municipality: row.get(7)?,
+                bankrupt: row.get(8)?,
+                source: row.get(9)?,
+            }),
+        ).optional().unwrap_or_else(|e| {
+            println!("⚠️  Enhetsregisteret lookup of {} failed: {}", org_number, e);
+            None
+        })
+    }
+
+    // Asks the live API for numbers the index does not have and keeps the
+    // answers, so the synchronous lookups during analysis find them
+    pub async fn fetch_missing(&self, org_numbers: &[String]) {
+        let Some(api) = &self.api else { return };
+        let mut seen = HashSet::new();
+        let missing: Vec<&String> = org_numbers.iter()
+            .filter(|org_number| seen.insert(org_number.as_str()))
+            .filter(|org_number| crate::orgnr::is_valid(org_number) && !self.is_known(org_number))
+            .collect();
+        if missing.len() > MAX_LOOKUPS_PER_REQUEST {
+            println!("⚠️  Looking up {} of {} organisation numbers missing from the index", MAX_LOOKUPS_PER_REQUEST, missing.len());
+        }
+
+        let lookups = missing.into_iter().take(MAX_LOOKUPS_PER_REQUEST)
+            .map(|org_number| async move { (org_number, api.entity(org_number).await) });
+        for (org_number, result) in futures_util::future::join_all(lookups).await {
+            match result {
+                Ok(Some(entity)) => {
+                    println!("🏢 Fetched {} ({}) from Enhetsregisteret", entity.name, entity.org_number);
+                    if let Err(e) = self.insert(&[entity]) {
+                        println!("⚠️  Could not store Enhetsregisteret entity {}: {}", org_number, e);
+                    }
+                },
+                Ok(None) => self.remember_unknown(org_number),
+                // Analysis goes on without the register when it is unreachable
+                Err(e) => println!("⚠️  Enhetsregisteret lookup of {} failed: {}", org_number, e),
+            }
+        }
+    }
+
+    fn is_known(&self, org_number: &str) -> bool {
+        self.lookup(org_number).is_some()
+            || self.unknown.lock().map(|unknown| unknown.contains(org_number)).unwrap_or(false)
+    }
+
+    // The list is forgotten all at once when full, so made-up numbers cannot
+    // grow it without bound
+    fn remember_unknown(&self, org_number: &str) {
+        if let Ok(mut unknown) = self.unknown.lock() {
+            if unknown.len() >= MAX_UNKNOWN_NUMBERS {
+                unknown.clear();
+            }
+            unknown.insert(org_number.to_string());
+        }
+    }
+
+    // Imports a bulk dump unless this exact file was imported before;
+    // returns the number of entities read
+    fn import_if_changed(&self, path: &str) -> Result<Option<usize>, RegisterError> {
+        let modified = fs::metadata(path)?.modified()?
+            .duration_since(UNIX_EPOCH).map(|age| age.as_secs() as i64).unwrap_or_default();
+        {
+            let connection = self.connection()?;
+            let imported: Option<i64> = connection.query_row(
+                "SELECT modified FROM imports WHERE path = ?1 ORDER BY imported_at DESC LIMIT 1",
+                params![path],
+                |row| row.get(0),
+            ).optional()?;
+            if imported == Some(modified) {
+                return Ok(None);
+            }
+        }
+
+        let mut reader: Box<dyn Read> = Box::new(BufReader::new(File::open(path)?));
+        if path.ends_with(".gz") {
+            reader = Box::new(BufReader::new(MultiGzDecoder::new(reader)));
+        }
+        let name = path.trim_end_matches(".gz");
+
+        // Entities are written in batches so a million-row dump never sits in memory
+        let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+        let mut count = 0;
+        let mut flush = |batch: &mut Vec<RegisteredEntity>| -> Result<(), RegisterError> {
+            count += batch.len();
+            self.insert(batch)?;
+            batch.clear();
+            Ok(())
+        };
+        let mut add = |entity: RegisteredEntity| -> Result<(), RegisterError> {
+            batch.push(entity);
+            if batch.len() >= IMPORT_BATCH_SIZE {
+                flush(&mut batch)?;
+            }
+            Ok(())
+        };
+
+        if name.ends_with(".csv") {
+            read_csv(reader, &mut add)?;
+        } else {
+            let mut deserializer = serde_json::Deserializer::from_reader(reader);
+            de::Deserializer::deserialize_seq(&mut deserializer, RecordSink(&mut add))
+                .map_err(|e| RegisterError(e.to_string()))?;
+        }
+        flush(&mut batch)?;
+
+        self.connection()?.execute(
+            "INSERT INTO imports (path, modified, entities, imported_at) VALUES (?1, ?2, ?3, ?4)",
+            params![path, modified, count as i64, chrono::Utc::now().to_rfc3339()],
+        )?;
+        Ok(Some(count))
+    }
+
+    fn insert(&self, entities: &[RegisteredEntity]) -> Result<(), RegisterError> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction()?;
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO entities
+                     (org_number, name, organization_form, nace_code, nace_description, vat_registered,
+                      address, municipality, bankrupt, source, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            let now = chrono::Utc::now().to_rfc3339();
+            for entity in entities {
+                statement.execute(params![
+                    entity.org_number, entity.name, entity.organization_form, entity.nace_code,
+                    entity.nace_description, entity.vat_registered, entity.address, entity.municipality,
+                    entity.bankrupt, entity.source, now,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn connection(&self) -> Result<std::sync::MutexGuard<'_, Connection>, RegisterError> {
+        self.connection.lock().map_err(|_| RegisterError("Enhetsregisteret index lock poisoned".to_string()))
+    }
+}
+
+const IMPORT_BATCH_SIZE: usize = 10_000;
+
+impl ApiClient {
+    // GET /enheter/{orgnr}; None for numbers that do not exist or were deleted
+    async fn entity(&self, org_number: &str) -> Result<Option<RegisteredEntity>, RegisterError> {
+        let response = self.client.get(format!("{}/enheter/{}", self.base_url, org_number))
+            .header("Accept", "application/json")
+            .send().await
+            .map_err(|e| RegisterError(e.to_string()))?;
+        match response.status().as_u16() {
+            200 => {
+                let record: EntityRecord = response.json().await.map_err(|e| RegisterError(e.to_string()))?;
+                Ok(record.into_entity("api"))
+            },
+            404 | 410 => Ok(None),
+            status => Err(RegisterError(format!("HTTP {}", status))),
+        }
+    }
+}
+
+// Streams the top-level JSON array of the dump one entity at a time
+struct RecordSink<'a>(&'a mut dyn FnMut(RegisteredEntity) -> Result<(), RegisterError>);
+
+impl<'de> Visitor<'de> for RecordSink<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an array of Enhetsregisteret entities")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(record) = seq.next_element::<EntityRecord>()? {
+            if let Some(entity) = record.into_entity("snapshot") {
+                (self.0)(entity).map_err(de::Error::custom)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The CSV dump flattens the JSON fields into dotted column names
+fn read_csv(reader: Box<dyn Read>, add: &mut dyn FnMut(RegisteredEntity) -> Result<(), RegisterError>) -> Result<(), RegisterError> {
+    let mut csv = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let headers = csv.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let Some(org_number_column) = column("organisasjonsnummer") else {
+        return Err(RegisterError("the CSV file has no organisasjonsnummer column".to_string()));
+    };
+    let columns = [
+        column("navn"), column("organisasjonsform.kode"), column("naeringskode1.kode"),
+        column("naeringskode1.beskrivelse"), column("registrertIMvaregisteret"),
+        column("forretningsadresse.adresse"), column("forretningsadresse.postnummer"),
+        column("forretningsadresse.poststed"), column("forretningsadresse.kommune"), column("konkurs"),
+    ];
+
+    for row in csv.records() {
+        let row = row?;
+        let field = |index: Option<usize>| index.and_then(|index| row.get(index)).map(str::trim).filter(|value| !value.is_empty());
+        let [name, form, nace_code, nace_description, vat, street, postcode, place, municipality, bankrupt] = columns.map(field);
+        let record = EntityRecord {
+            organisasjonsnummer: row.get(org_number_column).unwrap_or_default().to_string(),
+            navn: name.map(str::to_string),
+            organisasjonsform: form.map(|kode| CodeRecord { kode: kode.to_string(), beskrivelse: None }),
+            naeringskode1: nace_code.map(|kode| CodeRecord { kode: kode.to_string(), beskrivelse: nace_description.map(str::to_string) }),
+            registrert_i_mvaregisteret: vat.is_some_and(|value| value.eq_ignore_ascii_case("true")),
+            forretningsadresse: Some(AddressRecord {
+                adresse: street.map(|street| vec![street.to_string()]).unwrap_or_default(),
+                postnummer: postcode.map(str::to_string),
+                poststed: place.map(str::to_string),
+                kommune: municipality.map(str::to_string),
+            }),
+            postadresse: None,
+            konkurs: bankrupt.is_some_and(|value| value.eq_ignore_ascii_case("true")),
+        };
+        if let Some(entity) = record.into_entity("snapshot") {
+            add(entity)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register() -> BusinessRegister {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+        BusinessRegister { connection: Mutex::new(connection), api: None, unknown: Mutex::new(HashSet::new()) }
+    }
+
+    fn record(json: &str) -> EntityRecord {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn csv_columns_are_mapped_by_name() {
+        let csv = "konkurs,navn,organisasjonsnummer,naeringskode1.kode,naeringskode1.beskrivelse,registrertIMvaregisteret,\
+                   forretningsadresse.adresse,forretningsadresse.postnummer,forretningsadresse.poststed,forretningsadresse.kommune,\
+                   organisasjonsform.kode,hjemmeside\n\
+                   false,REMA 1000 NORGE AS,911 614 530,47.111,Butikkhandel med bredt vareutvalg,true,\
+                   Gneisveien 2,1400,SKI,NORDRE FOLLO,AS,rema.no\n\
+                   true,,123456789,,,,,,,,,\n\
+                   TRUE,Kari Konkurs ENK,974760673,,,false,,,,,ENK,\n";
+        let mut entities = Vec::new();
+        read_csv(Box::new(csv.as_bytes()), &mut |entity| {
+            entities.push(entity);
+            Ok(())
+        }).unwrap();
+
+        // The nameless row is left out
+        assert_eq!(entities.len(), 2);
+        let rema = &entities[0];
+        assert_eq!(rema.org_number, "911614530");
+        assert_eq!(rema.name, "REMA 1000 NORGE AS");
+        assert_eq!(rema.organization_form.as_deref(), Some("AS"));
+        assert_eq!(rema.nace_code.as_deref(), Some("47.111"));
+        assert_eq!(rema.nace_description.as_deref(), Some("Butikkhandel med bredt vareutvalg"));
+        assert!(rema.vat_registered && !rema.bankrupt);
+        assert_eq!(rema.address.as_deref(), Some("Gneisveien 2, 1400 SKI"));
+        assert_eq!(rema.municipality.as_deref(), Some("NORDRE FOLLO"));
+        assert_eq!(rema.source, "snapshot");
+
+        let sole = &entities[1];
+        assert!(sole.bankrupt && !sole.vat_registered);
+        assert!(sole.address.is_none() && sole.nace_code.is_none());
+    }
+
+    #[test]
+    fn csv_without_an_org_number_column_is_rejected() {
+        let result = read_csv(Box::new("navn\nREMA 1000\n".as_bytes()), &mut |_| Ok(()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn the_postal_address_stands_in_for_a_missing_business_address() {
+        let postal = r#"{"adresse": ["Postboks 1"], "postnummer": "0101", "poststed": "OSLO", "kommune": "OSLO"}"#;
+        let business = r#"{"adresse": ["Storgata 1", ""], "postnummer": "0155", "poststed": "OSLO", "kommune": "OSLO"}"#;
+
+        let entity = record(&format!(r#"{{"organisasjonsnummer": "974760673", "navn": "Kari ENK", "postadresse": {}}}"#, postal))
+            .into_entity("api").unwrap();
+        assert_eq!(entity.address.as_deref(), Some("Postboks 1, 0101 OSLO"));
+        assert_eq!(entity.municipality.as_deref(), Some("OSLO"));
+
+        let entity = record(&format!(
+            r#"{{"organisasjonsnummer": "974760673", "navn": "Kari ENK", "forretningsadresse": {}, "postadresse": {}}}"#,
+            business, postal,
+        )).into_entity("api").unwrap();
+        assert_eq!(entity.address.as_deref(), Some("Storgata 1, 0155 OSLO"));
+
+        let entity = record(r#"{"organisasjonsnummer": "974760673", "navn": "Kari ENK"}"#).into_entity("api").unwrap();
+        assert!(entity.address.is_none() && entity.municipality.is_none());
+
+        assert!(record(r#"{"organisasjonsnummer": "97476067", "navn": "Kari ENK"}"#).into_entity("api").is_none());
+        assert!(record(r#"{"organisasjonsnummer": "974760673"}"#).into_entity("api").is_none());
+    }
+
+    #[test]
+    fn an_unchanged_dump_is_not_imported_twice() {
+        let path = env::temp_dir().join(format!("brreg-{}.json", uuid::Uuid::new_v4().simple()));
+        fs::write(&path, r#"[
+            {"organisasjonsnummer": "911614530", "navn": "REMA 1000 NORGE AS", "registrertIMvaregisteret": true},
+            {"organisasjonsnummer": "974760673", "navn": "Registerenheten i Brønnøysund"}
+        ]"#).unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let register = register();
+        let first = register.import_if_changed(&path).map_err(|e| e.to_string());
+        let second = register.import_if_changed(&path).map_err(|e| e.to_string());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(first, Ok(Some(2)));
+        assert_eq!(second, Ok(None));
+        assert_eq!(register.len(), 2);
+        let rema = register.lookup("911614530").unwrap();
+        assert!(rema.vat_registered);
+        assert_eq!(rema.source, "snapshot");
+    }
+
+    #[test]
+    fn the_unknown_numbers_are_bounded() {
+        let register = register();
+        for number in 0..MAX_UNKNOWN_NUMBERS + 1 {
+            register.remember_unknown(&format!("{:09}", number));
+        }
+        assert_eq!(register.unknown.lock().unwrap().len(), 1);
+        assert!(register.is_known(&format!("{:09}", MAX_UNKNOWN_NUMBERS)));
+        assert!(!register.is_known("000000000"));
+    }
+}
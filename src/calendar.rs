@@ -0,0 +1,285 @@
+// Norwegian holidays and the shopping around them, computed for any year.
+//
+// Movable feasts follow Easter Sunday, from the anonymous Gregorian computus
+// (Meeus/Jones/Butcher): skjærtorsdag and langfredag before it, 2. påskedag
+// after, Kristi himmelfartsdag 39 days and pinse 49 days after. Fixed days
+// are nyttår, 1. mai, 17. mai, sankthans and jul.
+//
+// Every event has a shopping window: the days before it when stores see the
+// extra trade (påskemat, flags and pølser for 17. mai, grillmat for
+// sankthans). On a day inside several windows the nearest event wins.
+
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    NewYear,
+    Easter,
+    LabourDay,
+    ConstitutionDay,
+    AscensionDay,
+    Whitsun,
+    Midsummer,
+    Christmas,
+}
+
+// What the event means for spending, as reported in the seasonal context
+pub struct EventInfo {
+    pub season: &'static str,
+    pub cultural_event: &'static str,
+    pub typical_purchases: &'static [&'static str],
+    pub price_expectations: &'static str,
+}
+
+impl Event {
+    pub fn info(&self) -> EventInfo {
+        match self {
+            Event::NewYear => EventInfo {
+                season: "Nyttår (New Year)",
+                cultural_event: "Norwegian New Year's Eve",
+                typical_purchases: &["Fyrverkeri", "Champagne", "Kalkun", "Pinnekjøtt", "Kransekake"],
+                price_expectations: "Premium pricing for fireworks and party food",
+            },
+            Event::Easter => EventInfo {
+                season: "Påske (Easter)",
+                cultural_event: "Norwegian Easter",
+                typical_purchases: &["Egg", "Lam", "Kvikk Lunsj", "Påskeegg", "Sjokolade", "Appelsin"],
+                price_expectations: "Elevated prices for Easter chocolate and lamb",
+            },
+            Event::LabourDay => EventInfo {
+                season: "1. mai (Labour Day)",
+                cultural_event: "International Workers' Day",
+                typical_purchases: &["Grillmat", "Brus", "Is"],
+                price_expectations: "Regular pricing; shops are closed on the day",
+            },
+            Event::ConstitutionDay => EventInfo {
+                season: "17. mai (Constitution Day)",
+                cultural_event: "Norwegian National Day",
+                typical_purchases: &["Flagg", "Korv", "Brus", "Is", "Bunad tilbehør"],
+                price_expectations: "Premium pricing for patriotic items",
+            },
+            Event::AscensionDay => EventInfo {
+                season: "Kristi himmelfart (Ascension Day)",
+                cultural_event: "Ascension Day long weekend",
+                typical_purchases: &["Grillmat", "Brus", "Is", "Hytteproviant"],
+                price_expectations: "Long-weekend demand for grill and cabin food",
+            },
+            Event::Whitsun => EventInfo {
+                season: "Pinse (Whitsun)",
+                cultural_event: "Whitsun long weekend",
+                typical_purchases: &["Grillmat", "Blomster", "Hagemøbler", "Is"],
+                price_expectations: "Long-weekend demand for grill and garden goods",
+            },
+            Event::Midsummer => EventInfo {
+                season: "Sankthans (Midsummer)",
+                cultural_event: "Midsummer Eve bonfires",
+                typical_purchases: &["Grillmat", "Pølser", "Brus", "Øl", "Engangsgrill"],
+                price_expectations: "Grill and outdoor food at summer pricing",
+            },
+            Event::Christmas => EventInfo {
+                season: "Jul (Christmas)",
+                cultural_event: "Norwegian Christmas",
+                typical_purchases: &["Ribbe", "Pinnekjøtt", "Lutefisk", "Lefse", "Krumkake", "Julepresanger"],
+                price_expectations: "High seasonal pricing for traditional foods",
+            },
+        }
+    }
+
+    // The shopping window leading up to the event in `year`, inclusive
+    pub fn window(&self, year: i32) -> (NaiveDate, NaiveDate) {
+        let easter = easter_sunday(year);
+        match self {
+            Event::NewYear => (date(year - 1, 12, 27), date(year, 1, 1)),
+            // From the Monday before palmesøndag to 2. påskedag
+            Event::Easter => (easter - Duration::days(13), easter + Duration::days(1)),
+            Event::LabourDay => (date(year, 4, 30), date(year, 5, 1)),
+            Event::ConstitutionDay => (date(year, 5, 10), date(year, 5, 17)),
+            Event::AscensionDay => (easter + Duration::days(38), easter + Duration::days(39)),
+            // Friday before pinsedag to 2. pinsedag
+            Event::Whitsun => (easter + Duration::days(47), easter + Duration::days(50)),
+            Event::Midsummer => (date(year, 6, 20), date(year, 6, 23)),
+            Event::Christmas => (date(year, 12, 1), date(year, 12, 26)),
+        }
+    }
+
+    // The day the window leads up to
+    fn main_day(&self, year: i32) -> NaiveDate {
+        let easter = easter_sunday(year);
+        match self {
+            Event::NewYear => date(year, 1, 1),
+            Event::Easter => easter,
+            Event::LabourDay => date(year, 5, 1),
+            Event::ConstitutionDay => date(year, 5, 17),
+            Event::AscensionDay => easter + Duration::days(39),
+            Event::Whitsun => easter + Duration::days(49),
+            Event::Midsummer => date(year, 6, 23),
+            Event::Christmas => date(year, 12, 24),
+        }
+    }
+}
+
+// Listed by priority: 17. mai wins when Kristi himmelfart falls on it
+pub const EVENTS: [Event; 8] = [
+    Event::ConstitutionDay,
+    Event::Christmas,
+    Event::Easter,
+    Event::NewYear,
+    Event::Whitsun,
+    Event::AscensionDay,
+    Event::LabourDay,
+    Event::Midsummer,
+];
+
+#[derive(Serialize, Clone)]
+pub struct Holiday {
+    pub date: NaiveDate,
+    pub name: &'static str,
+    pub english_name: &'static str,
+    pub public_holiday: bool, // offentlig høytidsdag or helligdag; shops are closed
+    pub event: Event,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ShoppingWindow {
+    pub event: Event,
+    pub season: &'static str,
+    pub from: NaiveDate,
+    pub to: NaiveDate, // inclusive
+    pub typical_purchases: &'static [&'static str],
+}
+
+// Where a date falls in the holiday year
+pub struct SeasonOnDate {
+    pub event: Event,
+    pub holiday: Option<Holiday>, // the date itself is a holiday
+    pub days_until: i64, // until the event's main day, 0 on it
+}
+
+// Easter Sunday in the Gregorian calendar
+pub fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    date(year, month as u32, day as u32)
+}
+
+// Holidays and holiday eves of `year`, in date order
+pub fn holidays(year: i32) -> Vec<Holiday> {
+    let easter = easter_sunday(year);
+    let holiday = |date, name, english_name, public_holiday, event| Holiday { date, name, english_name, public_holiday, event };
+    let mut holidays = vec![
+        holiday(date(year, 1, 1), "1. nyttårsdag", "New Year's Day", true, Event::NewYear),
+        holiday(easter - Duration::days(7), "Palmesøndag", "Palm Sunday", false, Event::Easter),
+        holiday(easter - Duration::days(3), "Skjærtorsdag", "Maundy Thursday", true, Event::Easter),
+        holiday(easter - Duration::days(2), "Langfredag", "Good Friday", true, Event::Easter),
+        holiday(easter, "1. påskedag", "Easter Sunday", true, Event::Easter),
+        holiday(easter + Duration::days(1), "2. påskedag", "Easter Monday", true, Event::Easter),
+        holiday(date(year, 5, 1), "Offentlig høytidsdag", "Labour Day", true, Event::LabourDay),
+        holiday(date(year, 5, 17), "Grunnlovsdag", "Constitution Day", true, Event::ConstitutionDay),
+        holiday(easter + Duration::days(39), "Kristi himmelfartsdag", "Ascension Day", true, Event::AscensionDay),
+        holiday(easter + Duration::days(49), "1. pinsedag", "Whit Sunday", true, Event::Whitsun),
+        holiday(easter + Duration::days(50), "2. pinsedag", "Whit Monday", true, Event::Whitsun),
+        holiday(date(year, 6, 23), "Sankthansaften", "Midsummer Eve", false, Event::Midsummer),
+        holiday(date(year, 12, 24), "Julaften", "Christmas Eve", false, Event::Christmas),
+        holiday(date(year, 12, 25), "1. juledag", "Christmas Day", true, Event::Christmas),
+        holiday(date(year, 12, 26), "2. juledag", "Boxing Day", true, Event::Christmas),
+        holiday(date(year, 12, 31), "Nyttårsaften", "New Year's Eve", false, Event::NewYear),
+    ];
+    holidays.sort_by_key(|holiday| holiday.date);
+    holidays
+}
+
+// Shopping windows of the events in `year`, in date order; the New Year
+// window starts in the last days of the year before
+pub fn shopping_windows(year: i32) -> Vec<ShoppingWindow> {
+    let mut windows: Vec<ShoppingWindow> = EVENTS.iter().map(|event| {
+        let (from, to) = event.window(year);
+        let info = event.info();
+        ShoppingWindow { event: *event, season: info.season, from, to, typical_purchases: info.typical_purchases }
+    }).collect();
+    windows.sort_by_key(|window| window.from);
+    windows
+}
+
+// The holiday or shopping window `date` falls in, if any
+pub fn season_on(date: NaiveDate) -> Option<SeasonOnDate> {
+    // A holiday on the day itself, by event priority
+    let holidays = holidays(date.year());
+    let holiday = EVENTS.iter()
+        .find_map(|event| holidays.iter().find(|holiday| holiday.date == date && holiday.event == *event))
+        .cloned();
+
+    let in_window = window_containing(date);
+    let days_until = |event: Event, year| (event.main_day(year) - date).num_days().max(0);
+    match (holiday, in_window) {
+        (Some(holiday), window) => {
+            // Nyttårsaften leads up to next year's 1. januar
+            let year = window.filter(|(event, _)| *event == holiday.event).map_or(date.year(), |(_, year)| year);
+            Some(SeasonOnDate { event: holiday.event, days_until: days_until(holiday.event, year), holiday: Some(holiday) })
+        },
+        (None, Some((event, year))) => Some(SeasonOnDate { event, holiday: None, days_until: days_until(event, year) }),
+        (None, None) => None,
+    }
+}
+
+// The event and year of the shopping window `date` falls in; where windows
+// overlap, the one whose event is nearest. New Year's window reaches back into
+// December, so 30. desember 2024 is in the window of 2025.
+pub fn window_containing(date: NaiveDate) -> Option<(Event, i32)> {
+    [date.year(), date.year() + 1].into_iter()
+        .flat_map(|year| EVENTS.iter().map(move |event| (*event, year)))
+        .filter(|(event, year)| {
+            let (from, to) = event.window(*year);
+            from <= date && date <= to
+        })
+        .min_by_key(|(event, year)| (event.main_day(*year) - date).num_days().abs())
+}
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easter_sunday_is_computed() {
+        assert_eq!(easter_sunday(2019), date(2019, 4, 21));
+        assert_eq!(easter_sunday(2024), date(2024, 3, 31));
+        assert_eq!(easter_sunday(2025), date(2025, 4, 20));
+        assert_eq!(easter_sunday(2038), date(2038, 4, 25));
+    }
+
+    #[test]
+    fn moving_holidays_follow_easter() {
+        let holidays = holidays(2025);
+        assert_eq!(holidays.len(), 16);
+        assert!(holidays.windows(2).all(|pair| pair[0].date <= pair[1].date));
+        let on = |name| holidays.iter().find(|holiday| holiday.name == name).map(|holiday| holiday.date);
+        assert_eq!(on("Langfredag"), Some(date(2025, 4, 18)));
+        assert_eq!(on("Kristi himmelfartsdag"), Some(date(2025, 5, 29)));
+        assert_eq!(on("2. pinsedag"), Some(date(2025, 6, 9)));
+    }
+
+    #[test]
+    fn new_years_eve_leads_up_to_next_year() {
+        let season = season_on(date(2024, 12, 31)).unwrap();
+        assert_eq!(season.event, Event::NewYear);
+        assert_eq!(season.days_until, 1);
+        assert_eq!(window_containing(date(2024, 12, 30)), Some((Event::NewYear, 2025)));
+    }
+}
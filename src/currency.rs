@@ -0,0 +1,323 @@
+// Receipts from abroad: the currency a receipt is in, and Norges Bank's
+// exchange rates to convert its total to NOK on the transaction date.
+//
+// The currency is read from the text: an ISO code (SEK, DKK, EUR) or "€"
+// first, then the VAT summary ("Varav moms" and the 12%/6% food rates are
+// Swedish, "Heraf moms" and a CVR number Danish, both print amounts in "kr"),
+// then the country of the recognised merchant. Everything else is NOK.
+//
+// EXCHANGE_RATES_FILE=/path/EXR.csv  exchange rates as exported from
+//     https://data.norges-bank.no (dataset EXR, e.g. B.EUR+SEK+DKK.NOK.SP,
+//     "Last ned CSV"); re-read whenever the file changes
+//
+// Norges Bank quotes business days only, so a weekend or holiday receipt uses
+// the last rate before it, at most MAX_RATE_AGE_DAYS old. Without the file,
+// foreign totals are reported in their own currency but not converted.
+
+use crate::money::{Currency, Money};
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+const MAX_RATE_AGE_DAYS: i64 = 7;
+
+lazy_static::lazy_static! {
+    static ref CURRENCY_CODE: Regex = Regex::new(r"(?i)\b(?P<code>SEK|DKK|EUR|NOK)\b|(?P<euro>€)").unwrap();
+    // Swedish VAT summaries list 25, 12 and 6% as "Moms 12%"
+    static ref SWEDISH_VAT: Regex = Regex::new(r"(?i)\bvarav\s+moms\b|\bmoms\b.*\b(?:12|6)(?:[.,]0+)?\s*%").unwrap();
+    static ref DANISH_VAT: Regex = Regex::new(r"(?i)\bheraf\s+moms\b|\bcvr\b").unwrap();
+    // "Org.nr 556012-5790"
+    static ref SWEDISH_ORG_NUMBER: Regex = Regex::new(r"(?i)\borg\.?\s*nr\.?:?\s*\d{6}-\d{4}\b").unwrap();
+}
+
+#[derive(Debug)]
+pub struct ExchangeRateError(String);
+
+impl fmt::Display for ExchangeRateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Clone)]
+pub struct DetectedCurrency {
+    pub currency: Currency,
+    // currency_code, euro_sign, swedish_vat, danish_vat, org_number, merchant_country
+    // or default; document_currency_code for EHF invoices
+    pub detected_from: &'static str,
+}
+
+pub fn detect_currency(text: &str, merchant_country: Option<&str>) -> DetectedCurrency {
+    let detected = |currency, detected_from| DetectedCurrency { currency, detected_from };
+
+    // A foreign code wins over NOK, which may only be the card's currency
+    let mut counts: Vec<(Currency, usize, &'static str)> = Vec::new();
+    for captures in CURRENCY_CODE.captures_iter(text) {
+        let (currency, detected_from) = match captures.name("code") {
+            Some(code) => (Currency::parse(code.as_str()).unwrap_or_default(), "currency_code"),
+            None => (Currency::EUR, "euro_sign"),
+        };
+        match counts.iter_mut().find(|(counted, _, _)| *counted == currency) {
+            Some((_, count, _)) => *count += 1,
+            None => counts.push((currency, 1, detected_from)),
+        }
+    }
+    // The most frequent, the first seen on a tie
+    let foreign = counts.iter().rev()
+        .filter(|(currency, _, _)| *currency != Currency::NOK)
+        .max_by_key(|(_, count, _)| *count);
+    if let Some(&(currency, _, detected_from)) = foreign {
+        return detected(currency, detected_from);
+    }
+    if !counts.is_empty() {
+        return detected(Currency::NOK, "currency_code");
+    }
+
+    if SWEDISH_VAT.is_match(text) {
+        return detected(Currency::SEK, "swedish_vat");
+    }
+    if DANISH_VAT.is_match(text) {
+        return detected(Currency::DKK, "danish_vat");
+    }
+    if SWEDISH_ORG_NUMBER.is_match(text) {
+        return detected(Currency::SEK, "org_number");
+    }
+    match merchant_country.and_then(country_currency) {
+        Some(currency) => detected(currency, "merchant_country"),
+        None => detected(Currency::NOK, "default"),
+    }
+}
+
+// Currency of a country the clubs travel to, by ISO 3166 code
+fn country_currency(country: &str) -> Option<Currency> {
+    const EURO_AREA: &[&str] = &[
+        "AT", "BE", "HR", "CY", "EE", "FI", "FR", "DE", "GR", "IE", "IT", "LV", "LT", "LU", "MT", "NL", "PT", "SK", "SI", "ES",
+    ];
+    let code = match country.to_uppercase().as_str() {
+        "NO" => "NOK",
+        "SE" => "SEK",
+        "DK" => "DKK",
+        "IS" => "ISK",
+        "GB" => "GBP",
+        "PL" => "PLN",
+        "CH" => "CHF",
+        "US" => "USD",
+        country if EURO_AREA.contains(&country) => "EUR",
+        _ => return None,
+    };
+    Currency::parse(code)
+}
+
+#[derive(Clone, Copy)]
+pub struct ExchangeRate {
+    pub date: NaiveDate, // the business day Norges Bank quoted
+    pub nok_per_unit: f64,
+}
+
+impl ExchangeRate {
+    // `amount` in NOK
+    pub fn convert(&self, amount: Money) -> Money {
+        amount.scale(self.nok_per_unit).in_currency(Currency::NOK)
+    }
+}
+
+pub struct ExchangeRateTable {
+    path: Option<String>,
+    state: RwLock<TableState>,
+}
+
+struct TableState {
+    rates: RateMap,
+    modified: Option<SystemTime>,
+}
+
+impl ExchangeRateTable {
+    pub fn from_env() -> Self {
+        let path = env::var("EXCHANGE_RATES_FILE").ok();
+        let (rates, modified) = match &path {
+            Some(path) => match read_file(path) {
+                Ok(loaded) => loaded,
+                // Converting with a half-read table would misstate foreign totals
+                Err(e) => panic!("❌ Could not load exchange rates from {}: {}", path, e),
+            },
+            None => (HashMap::new(), None),
+        };
+
+        match &path {
+            Some(path) => {
+                let mut currencies: Vec<String> = rates.iter()
+                    .map(|(currency, by_date)| format!("{} {} days to {}", currency,
+                        by_date.len(),
+                        by_date.keys().next_back().map(NaiveDate::to_string).unwrap_or_default()))
+                    .collect();
+                currencies.sort();
+                println!("   - Norges Bank rates from {}: {}", path, currencies.join(", "));
+            },
+            None => println!("   - No EXCHANGE_RATES_FILE; foreign receipts are not converted to NOK"),
+        }
+        ExchangeRateTable { path, state: RwLock::new(TableState { rates, modified }) }
+    }
+
+    // NOK per unit of `currency` on `date`, or on the last business day before it
+    pub fn rate_on(&self, currency: Currency, date: NaiveDate) -> Option<ExchangeRate> {
+        if currency == Currency::NOK {
+            return Some(ExchangeRate { date, nok_per_unit: 1.0 });
+        }
+        self.reload_if_changed();
+        let state = self.state.read().ok()?;
+        let (&rate_date, &nok_per_unit) = state.rates.get(&currency)?.range(..=date).next_back()?;
+        ((date - rate_date).num_days() <= MAX_RATE_AGE_DAYS).then_some(ExchangeRate { date: rate_date, nok_per_unit })
+    }
+
+    fn reload_if_changed(&self) {
+        let Some(path) = &self.path else { return };
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let current = self.state.read().ok().and_then(|state| state.modified);
+        if modified.is_none() || modified == current {
+            return;
+        }
+
+        match read_file(path) {
+            Ok((rates, modified)) => {
+                println!("💱 Reloaded exchange rates for {} currencies from {}", rates.len(), path);
+                if let Ok(mut state) = self.state.write() {
+                    *state = TableState { rates, modified };
+                }
+            },
+            Err(e) => {
+                println!("⚠️  Keeping the previous exchange rates, {} is invalid: {}", path, e);
+                // Remember the broken version so it is not re-read on every request
+                if let Ok(mut state) = self.state.write() {
+                    state.modified = modified;
+                }
+            },
+        }
+    }
+}
+
+type RateMap = HashMap<Currency, BTreeMap<NaiveDate, f64>>;
+
+fn read_file(path: &str) -> Result<(RateMap, Option<SystemTime>), ExchangeRateError> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let text = fs::read_to_string(path).map_err(|e| ExchangeRateError(e.to_string()))?;
+    Ok((parse_rates(&text)?, modified))
+}
+
+// Norges Bank's CSV: one observation per row, with the currency in BASE_CUR,
+// the day in TIME_PERIOD and OBS_VALUE NOK per 10^UNIT_MULT units (100 SEK,
+// 1 EUR). The Norwegian export separates with ';' and uses decimal commas.
+fn parse_rates(text: &str) -> Result<RateMap, ExchangeRateError> {
+    let delimiter = if text.lines().next().unwrap_or_default().contains(';') { b';' } else { b',' };
+    let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).flexible(true).from_reader(text.as_bytes());
+    let headers = reader.headers().map_err(|e| ExchangeRateError(e.to_string()))?.clone();
+    let column = |name: &str| headers.iter().position(|header| header.trim().trim_start_matches('\u{feff}') == name);
+    let (Some(base), Some(period), Some(value)) = (column("BASE_CUR"), column("TIME_PERIOD"), column("OBS_VALUE")) else {
+        return Err(ExchangeRateError("expected Norges Bank columns BASE_CUR, TIME_PERIOD and OBS_VALUE".to_string()));
+    };
+    let (quote, unit_mult) = (column("QUOTE_CUR"), column("UNIT_MULT"));
+
+    let mut rates: RateMap = HashMap::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| ExchangeRateError(e.to_string()))?;
+        let field = |column: usize| record.get(column).map(str::trim).unwrap_or_default();
+        // Rows quoted against anything but NOK, and days without a value, are skipped
+        if quote.is_some_and(|quote| field(quote) != "NOK") || field(value).is_empty() || field(value) == "NaN" {
+            continue;
+        }
+        let row = index + 2;
+        let currency = Currency::parse(field(base))
+            .ok_or_else(|| ExchangeRateError(format!("row {}: '{}' is not a currency code", row, field(base))))?;
+        let date = NaiveDate::parse_from_str(field(period), "%Y-%m-%d")
+            .map_err(|_| ExchangeRateError(format!("row {}: '{}' is not a YYYY-MM-DD date", row, field(period))))?;
+        let observed: f64 = field(value).replace(',', ".").parse()
+            .map_err(|_| ExchangeRateError(format!("row {}: '{}' is not a rate", row, field(value))))?;
+        let exponent: i32 = match unit_mult.map(field).filter(|text| !text.is_empty()) {
+            Some(text) => text.parse().map_err(|_| ExchangeRateError(format!("row {}: UNIT_MULT '{}' is not a number", row, text)))?,
+            None => 0,
+        };
+        if observed <= 0.0 {
+            return Err(ExchangeRateError(format!("row {}: rate {} for {} is not positive", row, observed, currency)));
+        }
+        rates.entry(currency).or_default().insert(date, observed / 10f64.powi(exponent));
+    }
+
+    if rates.is_empty() {
+        return Err(ExchangeRateError("no NOK rates found".to_string()));
+    }
+    Ok(rates)
+}
+
+#[derive(Serialize, Clone)]
+pub struct CurrencyConversion {
+    pub original_currency: Currency,
+    pub original_amount: Option<Money>, // in original_currency
+    pub detected_from: String,
+    pub amount_nok: Option<Money>, // None when no rate is known for the date
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exchange_rate: Option<f64>, // NOK per unit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_date: Option<String>, // Norges Bank business day the rate is from
+    pub rate_source: String,
+}
+
+impl CurrencyConversion {
+    pub fn new(detected: &DetectedCurrency, original_amount: Option<Money>, rate: Option<ExchangeRate>) -> Self {
+        CurrencyConversion {
+            original_currency: detected.currency,
+            original_amount: original_amount.map(|amount| amount.in_currency(detected.currency)),
+            detected_from: detected.detected_from.to_string(),
+            amount_nok: original_amount.zip(rate).map(|(amount, rate)| rate.convert(amount)),
+            exchange_rate: rate.map(|rate| rate.nok_per_unit),
+            rate_date: rate.map(|rate| rate.date.to_string()),
+            rate_source: if rate.is_some() { "norges_bank" } else { "unavailable" }.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn norwegian_export_is_read_per_unit() {
+        let rates = parse_rates(
+            "\u{feff}FREQ;BASE_CUR;QUOTE_CUR;TENOR;DECIMALS;CALCULATED;UNIT_MULT;COLLECTION;TIME_PERIOD;OBS_VALUE\n\
+             B;EUR;NOK;SP;4;false;0;C;2024-06-14;11,4515\n\
+             B;SEK;NOK;SP;4;false;2;C;2024-06-14;101,62\n\
+             B;SEK;NOK;SP;4;false;2;C;2024-06-15;NaN\n",
+        ).unwrap();
+        assert_eq!(rates[&Currency::EUR][&date("2024-06-14")], 11.4515);
+        assert!((rates[&Currency::SEK][&date("2024-06-14")] - 1.0162).abs() < 1e-9);
+        assert_eq!(rates[&Currency::SEK].len(), 1);
+    }
+
+    #[test]
+    fn comma_separated_export_is_read() {
+        let rates = parse_rates("BASE_CUR,QUOTE_CUR,TIME_PERIOD,OBS_VALUE\nDKK,NOK,2024-06-14,1.5353\nDKK,SEK,2024-06-14,1.5210\n").unwrap();
+        assert_eq!(rates[&Currency::DKK].values().copied().collect::<Vec<_>>(), [1.5353]);
+    }
+
+    #[test]
+    fn files_without_usable_rates_are_rejected() {
+        assert!(parse_rates("CURRENCY;DATE;RATE\nEUR;2024-06-14;11,45\n").is_err());
+        assert!(parse_rates("BASE_CUR;QUOTE_CUR;TIME_PERIOD;OBS_VALUE\nEUR;SEK;2024-06-14;11,45\n").is_err());
+        assert!(parse_rates("BASE_CUR;TIME_PERIOD;OBS_VALUE\nEUR;2024-06-14;0\n").is_err());
+        assert!(parse_rates("BASE_CUR;TIME_PERIOD;OBS_VALUE\nEUR;14.06.2024;11,45\n").is_err());
+    }
+
+    #[test]
+    fn conversion_is_rounded_to_the_ore() {
+        let rate = ExchangeRate { date: date("2024-06-14"), nok_per_unit: 11.4515 };
+        assert_eq!(rate.convert(Money::nok(600).in_currency(Currency::EUR)), Money::from_ore(687090));
+    }
+}
@@ -0,0 +1,481 @@
+// EHF e-invoices: PEPPOL BIS Billing 3.0 invoices and credit notes in UBL 2.1
+// XML, as exchanged by Norwegian public bodies and B2B suppliers. The XML is
+// read into the invoice fields the analysis needs: supplier org number, lines,
+// the VAT breakdown per tax category, totals and due date.
+//
+// Tax categories used in Norway: S standard rate, H reduced rate (food),
+// AA low rate (transport, cinema, hotel), E exempt, Z zero rated, plus
+// K, G, O and AE for intra-community, export, outside scope and reverse charge.
+
+use crate::money::{Currency, Money, MAX_KRONER};
+use serde::Serialize;
+use std::fmt;
+
+const CBC: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2";
+const CAC: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2";
+const INVOICE_NS: &str = "urn:oasis:names:specification:ubl:schema:xsd:Invoice-2";
+const CREDIT_NOTE_NS: &str = "urn:oasis:names:specification:ubl:schema:xsd:CreditNote-2";
+
+// ISO 6523 scheme for Norwegian organisation numbers
+const NORWEGIAN_ORG_SCHEME: &str = "0192";
+
+// Rounding differences tolerated between declared and computed sums
+const AMOUNT_TOLERANCE: Money = Money::from_ore(5);
+
+#[derive(Debug)]
+pub enum EhfError {
+    InvalidXml(String),
+    NotAnInvoice(String),
+    MissingField(&'static str),
+    MixedCurrencies { document: Currency, found: Currency },
+    AmountsOutOfRange,
+}
+
+impl fmt::Display for EhfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EhfError::InvalidXml(e) => write!(f, "invoice_xml is not well-formed XML: {}", e),
+            EhfError::NotAnInvoice(root) => write!(f, "Expected a UBL Invoice or CreditNote, found <{}>", root),
+            EhfError::MissingField(field) => write!(f, "Invoice is missing {}", field),
+            EhfError::MixedCurrencies { document, found } => {
+                write!(f, "Invoice amounts must be in the document currency {}, found {}", document, found)
+            },
+            EhfError::AmountsOutOfRange => write!(f, "Invoice amounts add up to more than {} kroner", MAX_KRONER),
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct EhfParty {
+    pub name: String,
+    pub org_number: Option<String>, // 9 digits
+    pub mva_registered: bool, // tax scheme VAT with an ...MVA company id
+}
+
+#[derive(Serialize, Clone)]
+pub struct EhfInvoiceLine {
+    pub id: String,
+    pub description: String,
+    pub quantity: f32,
+    pub unit_code: Option<String>,
+    pub net_amount: Money, // line extension amount, excluding VAT
+    pub vat_category: String,
+    pub vat_percent: f32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct EhfVatSubtotal {
+    pub category: String,
+    pub percent: f32,
+    pub taxable_amount: Money,
+    pub tax_amount: Money,
+}
+
+#[derive(Serialize, Clone)]
+pub struct EhfTotals {
+    pub line_extension_amount: Money,
+    pub tax_exclusive_amount: Money,
+    pub tax_amount: Money,
+    pub tax_inclusive_amount: Money,
+    pub prepaid_amount: Money,
+    pub payable_amount: Money,
+}
+
+#[derive(Serialize, Clone)]
+pub struct EhfInvoice {
+    pub document_type: String, // invoice or credit_note
+    pub invoice_number: String,
+    pub issue_date: String,
+    pub due_date: Option<String>,
+    pub currency: String,
+    pub buyer_reference: Option<String>,
+    pub supplier: EhfParty,
+    pub customer: Option<EhfParty>,
+    pub lines: Vec<EhfInvoiceLine>,
+    pub vat_breakdown: Vec<EhfVatSubtotal>,
+    pub totals: EhfTotals,
+}
+
+type Node<'a, 'input> = roxmltree::Node<'a, 'input>;
+
+pub fn parse_invoice(xml: &str) -> Result<EhfInvoice, EhfError> {
+    let document = roxmltree::Document::parse(xml).map_err(|e| EhfError::InvalidXml(e.to_string()))?;
+    let root = document.root_element();
+
+    let (document_type, line_tag, quantity_tag) = if root.has_tag_name((INVOICE_NS, "Invoice")) {
+        ("invoice", "InvoiceLine", "InvoicedQuantity")
+    } else if root.has_tag_name((CREDIT_NOTE_NS, "CreditNote")) {
+        ("credit_note", "CreditNoteLine", "CreditedQuantity")
+    } else {
+        return Err(EhfError::NotAnInvoice(root.tag_name().name().to_string()));
+    };
+
+    let supplier = find_path(root, &[(CAC, "AccountingSupplierParty"), (CAC, "Party")])
+        .map(read_party)
+        .ok_or(EhfError::MissingField("AccountingSupplierParty"))?;
+    let customer = find_path(root, &[(CAC, "AccountingCustomerParty"), (CAC, "Party")]).map(read_party);
+    let currency = child_text(root, CBC, "DocumentCurrencyCode").unwrap_or_else(|| "NOK".to_string());
+    let document_currency = Currency::parse(&currency).unwrap_or_default();
+    // BIS 3.0 allows one other currency: the VAT total in TaxCurrencyCode, a
+    // TaxTotal holding only its TaxAmount, which is never summed with the rest.
+    // The TaxAmount of a TaxTotal with subtotals must be in the document currency.
+    let is_tax_currency_total = |node: &Node| node.has_tag_name((CBC, "TaxAmount")) && node.parent().is_some_and(|total| {
+        total.has_tag_name((CAC, "TaxTotal")) && child(total, CAC, "TaxSubtotal").is_none()
+    });
+    for node in root.descendants().filter(|node| !is_tax_currency_total(node)) {
+        if let Some(found) = node.attribute("currencyID").and_then(Currency::parse).filter(|found| *found != document_currency) {
+            return Err(EhfError::MixedCurrencies { document: document_currency, found });
+        }
+    }
+    let money = |node: Node| monetary_amount(node, document_currency);
+    let zero = Money::zero(document_currency);
+
+    let lines: Vec<EhfInvoiceLine> = children(root, CAC, line_tag).map(|line| {
+        let quantity = child(line, CBC, quantity_tag);
+        let tax_category = find_path(line, &[(CAC, "Item"), (CAC, "ClassifiedTaxCategory")]);
+        EhfInvoiceLine {
+            id: child_text(line, CBC, "ID").unwrap_or_default(),
+            description: find_path(line, &[(CAC, "Item"), (CBC, "Name")])
+                .or_else(|| find_path(line, &[(CAC, "Item"), (CBC, "Description")]))
+                .and_then(text)
+                .unwrap_or_default(),
+            quantity: quantity.and_then(amount).unwrap_or(1.0),
+            unit_code: quantity.and_then(|node| node.attribute("unitCode")).map(str::to_string),
+            net_amount: child(line, CBC, "LineExtensionAmount").and_then(money).unwrap_or(zero),
+            vat_category: tax_category.and_then(|node| child_text(node, CBC, "ID")).unwrap_or_default(),
+            vat_percent: tax_category.and_then(|node| child(node, CBC, "Percent")).and_then(amount).unwrap_or(0.0),
+        }
+    }).collect();
+
+    // A second TaxTotal without subtotals carries the VAT in accounting currency
+    let tax_total = children(root, CAC, "TaxTotal")
+        .find(|total| child(*total, CAC, "TaxSubtotal").is_some())
+        .or_else(|| children(root, CAC, "TaxTotal").find(|total| {
+            child(*total, CBC, "TaxAmount").and_then(money).is_some_and(|tax| tax.currency() == document_currency)
+        }));
+    let vat_breakdown: Vec<EhfVatSubtotal> = tax_total.map(|total| children(total, CAC, "TaxSubtotal").map(|subtotal| {
+        let category = child(subtotal, CAC, "TaxCategory");
+        EhfVatSubtotal {
+            category: category.and_then(|node| child_text(node, CBC, "ID")).unwrap_or_default(),
+            percent: category.and_then(|node| child(node, CBC, "Percent")).and_then(amount).unwrap_or(0.0),
+            taxable_amount: child(subtotal, CBC, "TaxableAmount").and_then(money).unwrap_or(zero),
+            tax_amount: child(subtotal, CBC, "TaxAmount").and_then(money).unwrap_or(zero),
+        }
+    }).collect()).unwrap_or_default();
+
+    let monetary_total = child(root, CAC, "LegalMonetaryTotal").ok_or(EhfError::MissingField("LegalMonetaryTotal"))?;
+    let total = |name| child(monetary_total, CBC, name).and_then(money);
+    let totals = EhfTotals {
+        line_extension_amount: total("LineExtensionAmount").unwrap_or(zero),
+        tax_exclusive_amount: total("TaxExclusiveAmount").unwrap_or(zero),
+        tax_amount: tax_total.and_then(|node| child(node, CBC, "TaxAmount")).and_then(money).unwrap_or(zero),
+        tax_inclusive_amount: total("TaxInclusiveAmount").unwrap_or(zero),
+        prepaid_amount: total("PrepaidAmount").unwrap_or(zero),
+        payable_amount: total("PayableAmount").ok_or(EhfError::MissingField("PayableAmount"))?,
+    };
+    // Lines and subtotals are summed per rate and checked against the totals
+    let amounts = lines.iter().map(|line| line.net_amount)
+        .chain(vat_breakdown.iter().flat_map(|subtotal| [subtotal.taxable_amount, subtotal.tax_amount]))
+        .chain([totals.line_extension_amount, totals.tax_exclusive_amount, totals.tax_amount, totals.tax_inclusive_amount, totals.prepaid_amount, totals.payable_amount]);
+    if Money::checked_sum(amounts.map(Money::abs)).is_none() {
+        return Err(EhfError::AmountsOutOfRange);
+    }
+
+    Ok(EhfInvoice {
+        document_type: document_type.to_string(),
+        invoice_number: child_text(root, CBC, "ID").ok_or(EhfError::MissingField("ID"))?,
+        issue_date: child_text(root, CBC, "IssueDate").ok_or(EhfError::MissingField("IssueDate"))?,
+        // Credit notes and EHF 2.0 invoices carry the due date under PaymentMeans
+        due_date: child_text(root, CBC, "DueDate")
+            .or_else(|| find_path(root, &[(CAC, "PaymentMeans"), (CBC, "PaymentDueDate")]).and_then(text)),
+        currency,
+        buyer_reference: child_text(root, CBC, "BuyerReference"),
+        supplier,
+        customer,
+        lines,
+        vat_breakdown,
+        totals,
+    })
+}
+
+impl EhfInvoice {
+    // Line descriptions, used as the item text for VAT analysis
+    pub fn item_text(&self) -> String {
+        self.lines.iter().map(|line| line.description.as_str()).collect::<Vec<_>>().join("\n")
+    }
+
+    // VAT rate carrying the largest taxable amount
+    pub fn dominant_vat_percent(&self) -> Option<f32> {
+        self.vat_breakdown.iter()
+            .max_by_key(|subtotal| subtotal.taxable_amount.abs())
+            .map(|subtotal| subtotal.percent)
+    }
+
+    // Declared sums that do not add up, e.g. a VAT total that differs from its subtotals
+    pub fn consistency_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let differs = |a: Money, b: Money| (a - b).abs() > AMOUNT_TOLERANCE;
+
+        let line_sum: Money = self.lines.iter().map(|line| line.net_amount).sum();
+        if !self.lines.is_empty() && differs(line_sum, self.totals.line_extension_amount) {
+            warnings.push(format!("Invoice lines sum to {}, LineExtensionAmount is {}",
+                line_sum, self.totals.line_extension_amount));
+        }
+
+        let vat_sum: Money = self.vat_breakdown.iter().map(|subtotal| subtotal.tax_amount).sum();
+        if !self.vat_breakdown.is_empty() && differs(vat_sum, self.totals.tax_amount) {
+            warnings.push(format!("VAT subtotals sum to {}, TaxAmount is {}", vat_sum, self.totals.tax_amount));
+        }
+
+        for subtotal in &self.vat_breakdown {
+            let expected = subtotal.taxable_amount.scale(subtotal.percent as f64 / 100.0);
+            if differs(expected, subtotal.tax_amount) {
+                warnings.push(format!("VAT category {} at {}% of {} should be {}, invoice states {}",
+                    subtotal.category, subtotal.percent, subtotal.taxable_amount, expected, subtotal.tax_amount));
+            }
+            if matches!(subtotal.category.as_str(), "E" | "Z") && subtotal.percent != 0.0 {
+                warnings.push(format!("VAT category {} must have 0%, invoice states {}%", subtotal.category, subtotal.percent));
+            }
+        }
+
+        if differs(self.totals.tax_exclusive_amount + self.totals.tax_amount, self.totals.tax_inclusive_amount) {
+            warnings.push(format!("TaxExclusiveAmount {} plus VAT {} does not match TaxInclusiveAmount {}",
+                self.totals.tax_exclusive_amount, self.totals.tax_amount, self.totals.tax_inclusive_amount));
+        }
+
+        if self.supplier.org_number.is_none() {
+            warnings.push("Supplier has no Norwegian organisation number".to_string());
+        }
+
+        warnings
+    }
+}
+
+fn read_party(party: Node) -> EhfParty {
+    let name = find_path(party, &[(CAC, "PartyLegalEntity"), (CBC, "RegistrationName")])
+        .or_else(|| find_path(party, &[(CAC, "PartyName"), (CBC, "Name")]))
+        .and_then(text)
+        .unwrap_or_default();
+
+    let vat_id = children(party, CAC, "PartyTaxScheme")
+        .filter(|scheme| find_path(*scheme, &[(CAC, "TaxScheme"), (CBC, "ID")]).and_then(text).as_deref() == Some("VAT"))
+        .find_map(|scheme| child_text(scheme, CBC, "CompanyID"));
+
+    // Legal entity id first, then the PEPPOL endpoint, then the "NO...MVA" VAT id
+    let org_number = find_path(party, &[(CAC, "PartyLegalEntity"), (CBC, "CompanyID")])
+        .filter(|node| node.attribute("schemeID").is_none_or(|scheme| scheme == NORWEGIAN_ORG_SCHEME))
+        .and_then(text)
+        .or_else(|| child(party, CBC, "EndpointID")
+            .filter(|node| node.attribute("schemeID") == Some(NORWEGIAN_ORG_SCHEME))
+            .and_then(text))
+        .or_else(|| vat_id.clone())
+        .and_then(|id| normalize_org_number(&id));
+
+    EhfParty {
+        name,
+        org_number,
+        mva_registered: vat_id.is_some_and(|id| id.to_uppercase().ends_with("MVA")),
+    }
+}
+
+// "NO 999 208 372 MVA" -> "999208372"
+fn normalize_org_number(id: &str) -> Option<String> {
+    let digits: String = id.chars().filter(char::is_ascii_digit).collect();
+    (digits.len() == 9).then_some(digits)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, namespace: &str, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name((namespace, name)))
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, namespace: &'a str, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |child| child.has_tag_name((namespace, name)))
+}
+
+fn find_path<'a, 'input>(node: Node<'a, 'input>, path: &[(&str, &str)]) -> Option<Node<'a, 'input>> {
+    path.iter().try_fold(node, |node, (namespace, name)| child(node, namespace, name))
+}
+
+fn child_text(node: Node, namespace: &str, name: &str) -> Option<String> {
+    child(node, namespace, name).and_then(text)
+}
+
+fn text(node: Node) -> Option<String> {
+    node.text().map(str::trim).filter(|text| !text.is_empty()).map(str::to_string)
+}
+
+fn amount(node: Node) -> Option<f32> {
+    node.text()?.trim().parse().ok()
+}
+
+// A monetary amount in its currencyID, else the document currency
+fn monetary_amount(node: Node, document_currency: Currency) -> Option<Money> {
+    let kroner: f64 = node.text()?.trim().parse().ok()?;
+    let currency = node.attribute("currencyID").and_then(Currency::parse).unwrap_or(document_currency);
+    Money::from_kroner(kroner, currency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUPPLIER: &str = "<cac:PartyLegalEntity><cbc:RegistrationName>Notehuset AS</cbc:RegistrationName><cbc:CompanyID>984661185</cbc:CompanyID></cac:PartyLegalEntity>";
+
+    fn document(root: &str, supplier: &str, parts: &[String]) -> String {
+        format!(
+            r#"<{root} xmlns="urn:oasis:names:specification:ubl:schema:xsd:{root}-2" xmlns:cac="{CAC}" xmlns:cbc="{CBC}">
+            <cbc:ID>INV-1</cbc:ID><cbc:IssueDate>2024-03-01</cbc:IssueDate><cbc:DocumentCurrencyCode>NOK</cbc:DocumentCurrencyCode>
+            <cac:AccountingSupplierParty><cac:Party>{supplier}</cac:Party></cac:AccountingSupplierParty>{}</{root}>"#,
+            parts.concat()
+        )
+    }
+
+    fn tax_total(currency: &str, tax: &str, subtotals: &[String]) -> String {
+        format!(r#"<cac:TaxTotal><cbc:TaxAmount currencyID="{currency}">{tax}</cbc:TaxAmount>{}</cac:TaxTotal>"#, subtotals.concat())
+    }
+
+    fn subtotal(category: &str, percent: &str, taxable: &str, tax: &str) -> String {
+        format!(
+            r#"<cac:TaxSubtotal><cbc:TaxableAmount currencyID="NOK">{taxable}</cbc:TaxableAmount><cbc:TaxAmount currencyID="NOK">{tax}</cbc:TaxAmount>
+            <cac:TaxCategory><cbc:ID>{category}</cbc:ID><cbc:Percent>{percent}</cbc:Percent></cac:TaxCategory></cac:TaxSubtotal>"#
+        )
+    }
+
+    fn totals(line_extension: &str, tax_exclusive: &str, tax_inclusive: &str) -> String {
+        format!(
+            r#"<cac:LegalMonetaryTotal><cbc:LineExtensionAmount currencyID="NOK">{line_extension}</cbc:LineExtensionAmount>
+            <cbc:TaxExclusiveAmount currencyID="NOK">{tax_exclusive}</cbc:TaxExclusiveAmount>
+            <cbc:TaxInclusiveAmount currencyID="NOK">{tax_inclusive}</cbc:TaxInclusiveAmount>
+            <cbc:PayableAmount currencyID="NOK">{tax_inclusive}</cbc:PayableAmount></cac:LegalMonetaryTotal>"#
+        )
+    }
+
+    fn line(tag: &str, quantity_tag: &str, quantity: &str, amount: &str, category: &str, percent: &str) -> String {
+        format!(
+            r#"<cac:{tag}><cbc:ID>1</cbc:ID><cbc:{quantity_tag} unitCode="EA">{quantity}</cbc:{quantity_tag}>
+            <cbc:LineExtensionAmount currencyID="NOK">{amount}</cbc:LineExtensionAmount>
+            <cac:Item><cbc:Name>Notestativ</cbc:Name><cac:ClassifiedTaxCategory><cbc:ID>{category}</cbc:ID><cbc:Percent>{percent}</cbc:Percent></cac:ClassifiedTaxCategory></cac:Item></cac:{tag}>"#
+        )
+    }
+
+    // 100 at 25% and 100 at 15%, with the given VAT total and monetary totals
+    fn invoice(tax_total: String, totals: String) -> EhfInvoice {
+        parse_invoice(&document("Invoice", SUPPLIER, &[
+            tax_total,
+            totals,
+            line("InvoiceLine", "InvoicedQuantity", "2", "100.00", "S", "25"),
+            line("InvoiceLine", "InvoicedQuantity", "10", "100.00", "H", "15"),
+        ])).unwrap()
+    }
+
+    fn subtotals() -> Vec<String> {
+        vec![subtotal("S", "25", "100.00", "25.00"), subtotal("H", "15", "100.00", "15.00")]
+    }
+
+    fn supplier_org_number(party: &str) -> Option<String> {
+        let xml = document("Invoice", party, &[totals("0", "0", "100.00")]);
+        parse_invoice(&xml).unwrap().supplier.org_number
+    }
+
+    #[test]
+    fn invoices_and_credit_notes_are_read() {
+        let invoice = invoice(tax_total("NOK", "40.00", &subtotals()), totals("200.00", "200.00", "240.00"));
+        assert_eq!(invoice.document_type, "invoice");
+        assert_eq!(invoice.lines.len(), 2);
+        assert_eq!(invoice.lines[0].quantity, 2.0);
+        assert_eq!(invoice.totals.tax_amount, Money::nok(40));
+        assert_eq!(invoice.totals.payable_amount, Money::nok(240));
+        assert!(invoice.consistency_warnings().is_empty());
+
+        let credit_note = parse_invoice(&document("CreditNote", SUPPLIER, &[
+            "<cac:PaymentMeans><cbc:PaymentDueDate>2024-03-15</cbc:PaymentDueDate></cac:PaymentMeans>".to_string(),
+            totals("100.00", "100.00", "100.00"),
+            line("CreditNoteLine", "CreditedQuantity", "3", "100.00", "Z", "0"),
+        ])).unwrap();
+        assert_eq!(credit_note.document_type, "credit_note");
+        assert_eq!(credit_note.lines[0].quantity, 3.0);
+        assert_eq!(credit_note.due_date.as_deref(), Some("2024-03-15"));
+
+        let order = document("Order", SUPPLIER, &[]);
+        assert!(matches!(parse_invoice(&order), Err(EhfError::NotAnInvoice(root)) if root == "Order"));
+    }
+
+    #[test]
+    fn org_number_falls_back_to_endpoint_then_vat_id() {
+        assert_eq!(supplier_org_number(SUPPLIER).as_deref(), Some("984661185"));
+        // A Swedish legal entity id is not an organisation number
+        let endpoint = r#"<cbc:EndpointID schemeID="0192">971425831</cbc:EndpointID>
+            <cac:PartyLegalEntity><cbc:CompanyID schemeID="0007">5560125790</cbc:CompanyID></cac:PartyLegalEntity>"#;
+        assert_eq!(supplier_org_number(endpoint).as_deref(), Some("971425831"));
+        let vat_id = r#"<cbc:EndpointID schemeID="0088">7080000000000</cbc:EndpointID>
+            <cac:PartyTaxScheme><cbc:CompanyID>NO 999 208 372 MVA</cbc:CompanyID><cac:TaxScheme><cbc:ID>VAT</cbc:ID></cac:TaxScheme></cac:PartyTaxScheme>"#;
+        assert_eq!(supplier_org_number(vat_id).as_deref(), Some("999208372"));
+        assert_eq!(supplier_org_number("<cac:PartyName><cbc:Name>Notehuset</cbc:Name></cac:PartyName>"), None);
+    }
+
+    #[test]
+    fn vat_registration_is_read_from_the_tax_scheme() {
+        let registered = r#"<cac:PartyTaxScheme><cbc:CompanyID>NO984661185MVA</cbc:CompanyID><cac:TaxScheme><cbc:ID>VAT</cbc:ID></cac:TaxScheme></cac:PartyTaxScheme>"#;
+        let xml = document("Invoice", &format!("{}{}", SUPPLIER, registered), &[totals("0", "0", "100.00")]);
+        assert!(parse_invoice(&xml).unwrap().supplier.mva_registered);
+        let xml = document("Invoice", SUPPLIER, &[totals("0", "0", "100.00")]);
+        assert!(!parse_invoice(&xml).unwrap().supplier.mva_registered);
+    }
+
+    #[test]
+    fn every_inconsistent_sum_is_warned_about() {
+        let warnings = |invoice: EhfInvoice| invoice.consistency_warnings();
+
+        let lines = warnings(invoice(tax_total("NOK", "40.00", &subtotals()), totals("190.00", "190.00", "230.00")));
+        assert_eq!(lines, ["Invoice lines sum to 200.00 NOK, LineExtensionAmount is 190.00 NOK"]);
+
+        let vat_total = warnings(invoice(tax_total("NOK", "45.00", &subtotals()), totals("200.00", "200.00", "245.00")));
+        assert_eq!(vat_total, ["VAT subtotals sum to 40.00 NOK, TaxAmount is 45.00 NOK"]);
+
+        let rate = warnings(invoice(
+            tax_total("NOK", "40.00", &[subtotal("S", "25", "100.00", "30.00"), subtotal("H", "15", "100.00", "10.00")]),
+            totals("200.00", "200.00", "240.00"),
+        ));
+        assert_eq!(rate, [
+            "VAT category S at 25% of 100.00 NOK should be 25.00 NOK, invoice states 30.00 NOK",
+            "VAT category H at 15% of 100.00 NOK should be 15.00 NOK, invoice states 10.00 NOK",
+        ]);
+
+        let exempt = warnings(invoice(
+            tax_total("NOK", "25.00", &[subtotal("S", "25", "100.00", "25.00"), subtotal("E", "15", "100.00", "0.00")]),
+            totals("200.00", "200.00", "225.00"),
+        ));
+        assert_eq!(exempt, [
+            "VAT category E at 15% of 100.00 NOK should be 15.00 NOK, invoice states 0.00 NOK",
+            "VAT category E must have 0%, invoice states 15%",
+        ]);
+
+        let inclusive = warnings(invoice(tax_total("NOK", "40.00", &subtotals()), totals("200.00", "200.00", "250.00")));
+        assert_eq!(inclusive, ["TaxExclusiveAmount 200.00 NOK plus VAT 40.00 NOK does not match TaxInclusiveAmount 250.00 NOK"]);
+
+        let xml = document("Invoice", "<cac:PartyName><cbc:Name>Notehuset</cbc:Name></cac:PartyName>", &[totals("0", "0", "0")]);
+        assert_eq!(parse_invoice(&xml).unwrap().consistency_warnings(), ["Supplier has no Norwegian organisation number"]);
+    }
+
+    #[test]
+    fn vat_total_in_tax_currency_is_only_allowed_without_subtotals() {
+        let with_accounting_total = invoice(
+            format!("{}{}", tax_total("NOK", "40.00", &subtotals()), tax_total("SEK", "39.20", &[])),
+            totals("200.00", "200.00", "240.00"),
+        );
+        assert_eq!(with_accounting_total.totals.tax_amount, Money::nok(40));
+
+        let xml = document("Invoice", SUPPLIER, &[tax_total("SEK", "40.00", &subtotals()), totals("200.00", "200.00", "240.00")]);
+        assert!(matches!(
+            parse_invoice(&xml),
+            Err(EhfError::MixedCurrencies { document: Currency::NOK, found: Currency::SEK })
+        ));
+    }
+
+    #[test]
+    fn amounts_that_cannot_be_summed_are_rejected() {
+        let lines = vec![line("InvoiceLine", "InvoicedQuantity", "1", "9000000000000.00", "S", "25"); 10_000];
+        let xml = document("Invoice", SUPPLIER, &[&[totals("0", "0", "0")][..], &lines].concat());
+        assert!(matches!(parse_invoice(&xml), Err(EhfError::AmountsOutOfRange)));
+    }
+}
@@ -0,0 +1,697 @@
+// CPU sentence embeddings. Sentence-transformer models (BERT encoders such as
+// all-MiniLM-L6-v2 or multilingual-e5-small) are loaded from safetensors weights
+// on disk and run with mean pooling and L2 normalisation.
+//
+// EMBEDDING_MODELS=name=/path/to/model,other=/path  model directories holding
+//     config.json, model.safetensors and vocab.txt
+// EMBEDDING_DEFAULT_MODEL   model used when a request names none
+// EMBEDDING_DIMENSIONS      default output size (vectors are truncated and re-normalised)
+// EMBEDDING_MAX_BATCH_SIZE  most inputs accepted in one batch request (default 2048)
+//
+// A feature-hashing character n-gram embedder is always available, so the
+// service still returns meaningful vectors when no weights are installed.
+
+use safetensors::{Dtype, SafeTensors};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+
+pub const HASHING_MODEL: &str = "hashing-ngram-256";
+
+const DEFAULT_MAX_BATCH_SIZE: usize = 2048;
+
+// Name accepted for the default model, kept for older clients
+const DEFAULT_ALIAS: &str = "sentence-transformer";
+
+pub trait Embedder: Send + Sync {
+    fn dimensions(&self) -> usize;
+    fn embed(&self, text: &str) -> Vec<f32>;
+
+    // Shrink a full vector to `dimensions` entries before re-normalising
+    fn reduce(&self, vector: &mut Vec<f32>, dimensions: usize) {
+        vector.truncate(dimensions);
+    }
+}
+
+#[derive(Debug)]
+pub enum EmbeddingError {
+    UnknownModel(String),
+    InvalidDimensions { requested: usize, available: usize },
+    BatchTooLarge { size: usize, max: usize },
+    EmptyInput,
+}
+
+impl fmt::Display for EmbeddingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddingError::UnknownModel(model) => write!(f, "Embedding model '{}' is not loaded", model),
+            EmbeddingError::InvalidDimensions { requested, available } => {
+                write!(f, "Requested {} dimensions, model provides between 1 and {}", requested, available)
+            },
+            EmbeddingError::BatchTooLarge { size, max } => {
+                write!(f, "Batch of {} inputs exceeds the maximum of {}", size, max)
+            },
+            EmbeddingError::EmptyInput => write!(f, "Input text is empty"),
+        }
+    }
+}
+
+pub struct Embedding {
+    pub model: String,
+    pub vector: Vec<f32>,
+}
+
+// One result per input, in input order; a failed item does not fail the batch
+pub struct BatchEmbedding {
+    pub model: String,
+    pub results: Vec<Result<Vec<f32>, EmbeddingError>>,
+}
+
+pub struct EmbedderRegistry {
+    embedders: HashMap<String, Box<dyn Embedder>>,
+    default_model: String,
+    default_dimensions: Option<usize>,
+    max_batch_size: usize,
+}
+
+impl EmbedderRegistry {
+    pub fn from_env() -> Self {
+        let mut embedders: HashMap<String, Box<dyn Embedder>> = HashMap::new();
+        embedders.insert(HASHING_MODEL.to_string(), Box::new(HashingEmbedder { dimensions: 256 }));
+
+        let mut first_loaded = None;
+        for entry in env::var("EMBEDDING_MODELS").unwrap_or_default().split(',') {
+            let Some((name, path)) = entry.split_once('=') else { continue };
+            let (name, path) = (name.trim(), path.trim());
+
+            match SentenceTransformer::load(Path::new(path)) {
+                Ok(model) => {
+                    println!("   - Loaded embedding model '{}' ({} dimensions) from {}", name, model.hidden_size, path);
+                    first_loaded.get_or_insert_with(|| name.to_string());
+                    embedders.insert(name.to_string(), Box::new(model));
+                },
+                Err(e) => println!("⚠️  Could not load embedding model '{}' from {}: {}", name, path, e),
+            }
+        }
+
+        let default_model = env::var("EMBEDDING_DEFAULT_MODEL").ok()
+            .filter(|name| embedders.contains_key(name))
+            .or(first_loaded)
+            .unwrap_or_else(|| HASHING_MODEL.to_string());
+        println!("   - Default embedding model: {}", default_model);
+
+        EmbedderRegistry {
+            embedders,
+            default_model,
+            default_dimensions: env::var("EMBEDDING_DIMENSIONS").ok().and_then(|value| value.parse().ok()),
+            max_batch_size: env::var("EMBEDDING_MAX_BATCH_SIZE").ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|&size| size > 0)
+                .unwrap_or(DEFAULT_MAX_BATCH_SIZE),
+        }
+    }
+
+    pub fn models(&self) -> Vec<(&str, usize)> {
+        let mut models: Vec<(&str, usize)> = self.embedders.iter()
+            .map(|(name, embedder)| (name.as_str(), embedder.dimensions()))
+            .collect();
+        models.sort();
+        models
+    }
+
+    pub fn max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    pub fn embed(&self, model: Option<&str>, text: &str, dimensions: Option<usize>) -> Result<Embedding, EmbeddingError> {
+        let (name, embedder, dimensions) = self.resolve(model, dimensions)?;
+        let vector = embed_one(embedder, text, dimensions)?;
+        Ok(Embedding { model: name.to_string(), vector })
+    }
+
+    // Embed every input, spreading the batch over the available cores. Model and
+    // dimension problems reject the whole batch; empty inputs are reported per item
+    pub fn embed_batch(&self, model: Option<&str>, inputs: &[String], dimensions: Option<usize>) -> Result<BatchEmbedding, EmbeddingError> {
+        if inputs.len() > self.max_batch_size {
+            return Err(EmbeddingError::BatchTooLarge { size: inputs.len(), max: self.max_batch_size });
+        }
+        let (name, embedder, dimensions) = self.resolve(model, dimensions)?;
+
+        let workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let chunk_size = inputs.len().div_ceil(workers).max(1);
+        let results = std::thread::scope(|scope| {
+            let handles: Vec<_> = inputs.chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || {
+                    chunk.iter().map(|text| embed_one(embedder, text, dimensions)).collect::<Vec<_>>()
+                }))
+                .collect();
+            handles.into_iter()
+                .flat_map(|handle| handle.join().expect("embedding worker panicked"))
+                .collect()
+        });
+
+        Ok(BatchEmbedding { model: name.to_string(), results })
+    }
+
+    // Look up the model and settle the output size for a request
+    fn resolve<'a>(&'a self, model: Option<&'a str>, dimensions: Option<usize>) -> Result<(&'a str, &'a dyn Embedder, usize), EmbeddingError> {
+        let name = match model {
+            None | Some(DEFAULT_ALIAS) => self.default_model.as_str(),
+            Some(name) => name,
+        };
+        let embedder = self.embedders.get(name)
+            .ok_or_else(|| EmbeddingError::UnknownModel(name.to_string()))?;
+
+        let available = embedder.dimensions();
+        let requested = dimensions.or(self.default_dimensions).unwrap_or(available).min(available);
+        if requested == 0 {
+            return Err(EmbeddingError::InvalidDimensions { requested, available });
+        }
+        if let Some(requested) = dimensions {
+            if requested > available {
+                return Err(EmbeddingError::InvalidDimensions { requested, available });
+            }
+        }
+
+        Ok((name, embedder.as_ref(), requested))
+    }
+}
+
+fn embed_one(embedder: &dyn Embedder, text: &str, dimensions: usize) -> Result<Vec<f32>, EmbeddingError> {
+    if text.trim().is_empty() {
+        return Err(EmbeddingError::EmptyInput);
+    }
+    let mut vector = embedder.embed(text);
+    embedder.reduce(&mut vector, dimensions);
+    l2_normalize(&mut vector);
+    Ok(vector)
+}
+
+fn l2_normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+// Character 3-5-gram feature hashing over lowercased words
+struct HashingEmbedder {
+    dimensions: usize,
+}
+
+impl Embedder for HashingEmbedder {
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    // Hashed buckets carry no ordering, so fold them instead of dropping the tail
+    fn reduce(&self, vector: &mut Vec<f32>, dimensions: usize) {
+        for i in dimensions..vector.len() {
+            vector[i % dimensions] += vector[i];
+        }
+        vector.truncate(dimensions);
+    }
+
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+
+        for word in text.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            let chars: Vec<char> = format!("<{}>", word).chars().collect();
+            for n in 3..=5 {
+                for gram in chars.windows(n) {
+                    let hash = fnv1a(gram.iter().collect::<String>().as_bytes());
+                    let index = (hash % self.dimensions as u64) as usize;
+                    // A second hash bit picks the sign to keep collisions unbiased
+                    let sign = if (hash >> 63) == 0 { 1.0 } else { -1.0 };
+                    vector[index] += sign;
+                }
+            }
+        }
+
+        l2_normalize(&mut vector);
+        vector
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[derive(Deserialize)]
+struct BertConfig {
+    hidden_size: usize,
+    num_attention_heads: usize,
+    num_hidden_layers: usize,
+    max_position_embeddings: usize,
+    #[serde(default = "default_layer_norm_eps")]
+    layer_norm_eps: f32,
+}
+
+fn default_layer_norm_eps() -> f32 {
+    1e-12
+}
+
+struct Linear {
+    weight: Vec<f32>, // [out, in], PyTorch layout
+    bias: Vec<f32>,
+    in_features: usize,
+    out_features: usize,
+}
+
+impl Linear {
+    fn forward(&self, input: &[f32]) -> Vec<f32> {
+        let rows = input.len() / self.in_features;
+        let mut output = Vec::with_capacity(rows * self.out_features);
+
+        for row in input.chunks_exact(self.in_features) {
+            for (weights, bias) in self.weight.chunks_exact(self.in_features).zip(&self.bias) {
+                output.push(bias + row.iter().zip(weights).map(|(x, w)| x * w).sum::<f32>());
+            }
+        }
+        output
+    }
+}
+
+struct LayerNorm {
+    gamma: Vec<f32>,
+    beta: Vec<f32>,
+    eps: f32,
+}
+
+impl LayerNorm {
+    fn forward(&self, input: &mut [f32]) {
+        let width = self.gamma.len();
+        for row in input.chunks_exact_mut(width) {
+            let mean = row.iter().sum::<f32>() / width as f32;
+            let variance = row.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / width as f32;
+            let scale = 1.0 / (variance + self.eps).sqrt();
+            for (i, x) in row.iter_mut().enumerate() {
+                *x = (*x - mean) * scale * self.gamma[i] + self.beta[i];
+            }
+        }
+    }
+}
+
+struct EncoderLayer {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    attention_output: Linear,
+    attention_norm: LayerNorm,
+    intermediate: Linear,
+    output: Linear,
+    output_norm: LayerNorm,
+}
+
+pub struct SentenceTransformer {
+    vocab: HashMap<String, u32>,
+    lowercase: bool,
+    max_sequence_length: usize,
+    hidden_size: usize,
+    num_heads: usize,
+    word_embeddings: Vec<f32>,
+    position_embeddings: Vec<f32>,
+    token_type_embeddings: Vec<f32>,
+    embeddings_norm: LayerNorm,
+    layers: Vec<EncoderLayer>,
+}
+
+impl SentenceTransformer {
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let config: BertConfig = read_json(&dir.join("config.json"))?;
+
+        let vocab: HashMap<String, u32> = fs::read_to_string(dir.join("vocab.txt"))
+            .map_err(|e| format!("vocab.txt: {}", e))?
+            .lines()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as u32))
+            .collect();
+
+        let lowercase = read_json::<serde_json::Value>(&dir.join("tokenizer_config.json"))
+            .ok()
+            .and_then(|value| value["do_lower_case"].as_bool())
+            .unwrap_or(true);
+        let max_sequence_length = read_json::<serde_json::Value>(&dir.join("sentence_bert_config.json"))
+            .ok()
+            .and_then(|value| value["max_seq_length"].as_u64())
+            .map(|length| length as usize)
+            .unwrap_or(config.max_position_embeddings)
+            .min(config.max_position_embeddings);
+        // Every sequence holds at least [CLS] and [SEP]
+        if max_sequence_length < 2 {
+            return Err(format!("max sequence length {} leaves no room for [CLS] and [SEP]", max_sequence_length));
+        }
+
+        let bytes = fs::read(dir.join("model.safetensors")).map_err(|e| format!("model.safetensors: {}", e))?;
+        let tensors = SafeTensors::deserialize(&bytes).map_err(|e| format!("model.safetensors: {:?}", e))?;
+        // Checkpoints exported from BertForX carry a "bert." prefix
+        let prefix = if tensors.names().iter().any(|name| name.starts_with("bert.")) { "bert." } else { "" };
+        let weights = Weights { tensors, prefix };
+
+        let eps = config.layer_norm_eps;
+        let mut layers = Vec::with_capacity(config.num_hidden_layers);
+        for i in 0..config.num_hidden_layers {
+            let layer = format!("encoder.layer.{}", i);
+            layers.push(EncoderLayer {
+                query: weights.linear(&format!("{}.attention.self.query", layer))?,
+                key: weights.linear(&format!("{}.attention.self.key", layer))?,
+                value: weights.linear(&format!("{}.attention.self.value", layer))?,
+                attention_output: weights.linear(&format!("{}.attention.output.dense", layer))?,
+                attention_norm: weights.layer_norm(&format!("{}.attention.output.LayerNorm", layer), eps)?,
+                intermediate: weights.linear(&format!("{}.intermediate.dense", layer))?,
+                output: weights.linear(&format!("{}.output.dense", layer))?,
+                output_norm: weights.layer_norm(&format!("{}.output.LayerNorm", layer), eps)?,
+            });
+        }
+
+        Ok(SentenceTransformer {
+            vocab,
+            lowercase,
+            max_sequence_length,
+            hidden_size: config.hidden_size,
+            num_heads: config.num_attention_heads,
+            word_embeddings: weights.tensor("embeddings.word_embeddings.weight")?,
+            position_embeddings: weights.tensor("embeddings.position_embeddings.weight")?,
+            token_type_embeddings: weights.tensor("embeddings.token_type_embeddings.weight")?,
+            embeddings_norm: weights.layer_norm("embeddings.LayerNorm", eps)?,
+            layers,
+        })
+    }
+
+    // BERT basic tokenization followed by greedy longest-match WordPiece
+    fn tokenize(&self, text: &str) -> Vec<u32> {
+        let unknown = self.vocab.get("[UNK]").copied().unwrap_or(100);
+        let mut ids = vec![self.vocab.get("[CLS]").copied().unwrap_or(101)];
+
+        let text: String = if self.lowercase {
+            // Uncased BERT vocabularies are built from lowercased, accent-stripped text
+            text.to_lowercase().nfd().filter(|c| !('\u{300}'..='\u{36f}').contains(c)).collect()
+        } else {
+            text.to_string()
+        };
+
+        let mut words = Vec::new();
+        let mut current = String::new();
+        for c in text.chars() {
+            if c.is_whitespace() || c.is_control() {
+                words.extend((!current.is_empty()).then(|| std::mem::take(&mut current)));
+            } else if c.is_ascii_punctuation() || (!c.is_alphanumeric() && !c.is_whitespace()) {
+                words.extend((!current.is_empty()).then(|| std::mem::take(&mut current)));
+                words.push(c.to_string());
+            } else {
+                current.push(c);
+            }
+        }
+        words.extend((!current.is_empty()).then_some(current));
+
+        'words: for word in words {
+            let chars: Vec<char> = word.chars().collect();
+            if chars.len() > 100 {
+                ids.push(unknown);
+                continue;
+            }
+
+            let mut pieces = Vec::new();
+            let mut start = 0;
+            while start < chars.len() {
+                let mut end = chars.len();
+                let mut found = None;
+                while end > start {
+                    let mut piece: String = chars[start..end].iter().collect();
+                    if start > 0 {
+                        piece.insert_str(0, "##");
+                    }
+                    if let Some(&id) = self.vocab.get(&piece) {
+                        found = Some(id);
+                        break;
+                    }
+                    end -= 1;
+                }
+                match found {
+                    Some(id) => pieces.push(id),
+                    None => {
+                        ids.push(unknown);
+                        continue 'words;
+                    },
+                }
+                start = end;
+            }
+            ids.extend(pieces);
+        }
+
+        ids.truncate(self.max_sequence_length - 1);
+        ids.push(self.vocab.get("[SEP]").copied().unwrap_or(102));
+        ids
+    }
+
+    fn attention(&self, layer: &EncoderLayer, hidden: &[f32], length: usize) -> Vec<f32> {
+        let (query, key, value) = (layer.query.forward(hidden), layer.key.forward(hidden), layer.value.forward(hidden));
+        let head_size = self.hidden_size / self.num_heads;
+        let scale = 1.0 / (head_size as f32).sqrt();
+        let mut context = vec![0.0; length * self.hidden_size];
+
+        for head in 0..self.num_heads {
+            let offset = head * head_size;
+            for i in 0..length {
+                let q = &query[i * self.hidden_size + offset..i * self.hidden_size + offset + head_size];
+                let mut scores: Vec<f32> = (0..length).map(|j| {
+                    let k = &key[j * self.hidden_size + offset..j * self.hidden_size + offset + head_size];
+                    q.iter().zip(k).map(|(a, b)| a * b).sum::<f32>() * scale
+                }).collect();
+
+                let max = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                scores.iter_mut().for_each(|s| *s = (*s - max).exp());
+                let total: f32 = scores.iter().sum();
+
+                let out = &mut context[i * self.hidden_size + offset..i * self.hidden_size + offset + head_size];
+                for (j, score) in scores.iter().enumerate() {
+                    let v = &value[j * self.hidden_size + offset..j * self.hidden_size + offset + head_size];
+                    let weight = score / total;
+                    out.iter_mut().zip(v).for_each(|(o, v)| *o += weight * v);
+                }
+            }
+        }
+
+        context
+    }
+}
+
+impl Embedder for SentenceTransformer {
+    fn dimensions(&self) -> usize {
+        self.hidden_size
+    }
+
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let ids = self.tokenize(text);
+        let length = ids.len();
+        let width = self.hidden_size;
+
+        let mut hidden = Vec::with_capacity(length * width);
+        for (position, &id) in ids.iter().enumerate() {
+            let id = (id as usize).min(self.word_embeddings.len() / width - 1);
+            let word = &self.word_embeddings[id * width..(id + 1) * width];
+            let pos = &self.position_embeddings[position * width..(position + 1) * width];
+            let token_type = &self.token_type_embeddings[..width];
+            hidden.extend((0..width).map(|i| word[i] + pos[i] + token_type[i]));
+        }
+        self.embeddings_norm.forward(&mut hidden);
+
+        for layer in &self.layers {
+            let context = self.attention(layer, &hidden, length);
+            let mut attended = layer.attention_output.forward(&context);
+            attended.iter_mut().zip(&hidden).for_each(|(a, h)| *a += h);
+            layer.attention_norm.forward(&mut attended);
+
+            let mut intermediate = layer.intermediate.forward(&attended);
+            intermediate.iter_mut().for_each(|x| *x = gelu(*x));
+            let mut output = layer.output.forward(&intermediate);
+            output.iter_mut().zip(&attended).for_each(|(o, a)| *o += a);
+            layer.output_norm.forward(&mut output);
+
+            hidden = output;
+        }
+
+        // Mean pooling over all tokens (single unpadded sequence)
+        let mut pooled = vec![0.0; width];
+        for row in hidden.chunks_exact(width) {
+            pooled.iter_mut().zip(row).for_each(|(p, x)| *p += x);
+        }
+        pooled.iter_mut().for_each(|p| *p /= length as f32);
+        l2_normalize(&mut pooled);
+        pooled
+    }
+}
+
+fn gelu(x: f32) -> f32 {
+    0.5 * x * (1.0 + erf(x / std::f32::consts::SQRT_2))
+}
+
+// Abramowitz & Stegun 7.1.26, max error 1.5e-7
+fn erf(x: f32) -> f32 {
+    let x = x as f64;
+    let sign = if x < 0.0 { -1.0 } else { 1.0 };
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let y = 1.0 - (((((1.061405429 * t - 1.453152027) * t) + 1.421413741) * t - 0.284496736) * t + 0.254829592) * t * (-x * x).exp();
+    (sign * y) as f32
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+struct Weights<'a> {
+    tensors: SafeTensors<'a>,
+    prefix: &'static str,
+}
+
+impl Weights<'_> {
+    fn tensor(&self, name: &str) -> Result<Vec<f32>, String> {
+        let view = self.tensors.tensor(&format!("{}{}", self.prefix, name))
+            .map_err(|_| format!("missing tensor {}{}", self.prefix, name))?;
+        let data = view.data();
+
+        match view.dtype() {
+            Dtype::F32 => Ok(data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()),
+            Dtype::F16 => Ok(data.chunks_exact(2).map(|b| half::f16::from_le_bytes([b[0], b[1]]).to_f32()).collect()),
+            Dtype::BF16 => Ok(data.chunks_exact(2).map(|b| half::bf16::from_le_bytes([b[0], b[1]]).to_f32()).collect()),
+            other => Err(format!("tensor {} has unsupported dtype {:?}", name, other)),
+        }
+    }
+
+    fn linear(&self, name: &str) -> Result<Linear, String> {
+        let weight = self.tensor(&format!("{}.weight", name))?;
+        let bias = self.tensor(&format!("{}.bias", name))?;
+        let out_features = bias.len();
+        Ok(Linear {
+            in_features: weight.len() / out_features,
+            out_features,
+            weight,
+            bias,
+        })
+    }
+
+    fn layer_norm(&self, name: &str, eps: f32) -> Result<LayerNorm, String> {
+        Ok(LayerNorm {
+            gamma: self.tensor(&format!("{}.weight", name))?,
+            beta: self.tensor(&format!("{}.bias", name))?,
+            eps,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity_norm(width: usize) -> LayerNorm {
+        LayerNorm { gamma: vec![1.0; width], beta: vec![0.0; width], eps: 1e-12 }
+    }
+
+    // A three-wide encoder without layers, so an embedding is the mean of the
+    // normalised word embeddings
+    fn tiny_model(max_sequence_length: usize) -> SentenceTransformer {
+        let vocab = ["[PAD]", "[UNK]", "[CLS]", "[SEP]", "a", "b", "kvit", "##ter", "##ing"];
+        let mut word_embeddings = vec![0.0; vocab.len() * 3];
+        for (id, column) in [(2, 0), (3, 2), (4, 1), (5, 2)] {
+            word_embeddings[id * 3 + column] = 1.0;
+        }
+        SentenceTransformer {
+            vocab: vocab.iter().enumerate().map(|(id, token)| (token.to_string(), id as u32)).collect(),
+            lowercase: true,
+            max_sequence_length,
+            hidden_size: 3,
+            num_heads: 1,
+            word_embeddings,
+            position_embeddings: vec![0.0; max_sequence_length * 3],
+            token_type_embeddings: vec![0.0; 3],
+            embeddings_norm: identity_norm(3),
+            layers: Vec::new(),
+        }
+    }
+
+    fn registry(default_dimensions: Option<usize>) -> EmbedderRegistry {
+        let mut embedders: HashMap<String, Box<dyn Embedder>> = HashMap::new();
+        embedders.insert(HASHING_MODEL.to_string(), Box::new(HashingEmbedder { dimensions: 256 }));
+        embedders.insert("tiny".to_string(), Box::new(tiny_model(8)));
+        EmbedderRegistry { embedders, default_model: "tiny".to_string(), default_dimensions, max_batch_size: 4 }
+    }
+
+    fn norm(vector: &[f32]) -> f32 {
+        vector.iter().map(|v| v * v).sum::<f32>().sqrt()
+    }
+
+    #[test]
+    fn word_pieces_are_truncated_before_the_separator() {
+        let model = tiny_model(8);
+        assert_eq!(model.tokenize("Kvittering, A"), vec![2, 6, 7, 8, 1, 4, 3]);
+        // Unknown pieces make the whole word unknown
+        assert_eq!(model.tokenize("kvitx b"), vec![2, 1, 5, 3]);
+
+        assert_eq!(tiny_model(4).tokenize("a b a b a"), vec![2, 4, 5, 3]);
+        assert_eq!(tiny_model(2).tokenize("a b"), vec![2, 3]);
+    }
+
+    #[test]
+    fn too_short_sequences_are_rejected_at_load() {
+        let dir = env::temp_dir().join(format!("embedding-{}", uuid::Uuid::new_v4().simple()));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("config.json"), r#"{"hidden_size": 3, "num_attention_heads": 1, "num_hidden_layers": 0, "max_position_embeddings": 0}"#).unwrap();
+        fs::write(dir.join("vocab.txt"), "[PAD]\n[UNK]\n[CLS]\n[SEP]\n").unwrap();
+
+        let result = SentenceTransformer::load(&dir).map(|_| ());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(result, Err("max sequence length 0 leaves no room for [CLS] and [SEP]".to_string()));
+    }
+
+    #[test]
+    fn tokens_are_mean_pooled() {
+        // [CLS], [SEP] and one "a" cancel out after normalisation, leaving the other "a"
+        let vector = tiny_model(8).embed("a a");
+        let expected = [-1.0, 2.0, -1.0].map(|v: f32| v / 6f32.sqrt());
+        assert!(vector.iter().zip(expected).all(|(v, e)| (v - e).abs() < 1e-4), "{:?}", vector);
+    }
+
+    #[test]
+    fn dimensions_are_requested_or_defaulted_within_the_model() {
+        let vector = registry(None).embed(None, "a b", None).unwrap().vector;
+        assert_eq!(vector.len(), 3);
+
+        let embedding = registry(None).embed(Some("sentence-transformer"), "a b", Some(2)).unwrap();
+        assert_eq!(embedding.model, "tiny");
+        assert_eq!(embedding.vector.len(), 2);
+        assert!((norm(&embedding.vector) - 1.0).abs() < 1e-5);
+
+        // The hashing embedder folds the cut entries back in instead of dropping them
+        let vector = registry(None).embed(Some(HASHING_MODEL), "kvittering", Some(16)).unwrap().vector;
+        assert_eq!(vector.len(), 16);
+        assert!((norm(&vector) - 1.0).abs() < 1e-5);
+
+        // A configured default larger than the model is capped; a requested one is an error
+        assert_eq!(registry(Some(64)).embed(None, "a", None).unwrap().vector.len(), 3);
+        assert_eq!(registry(Some(2)).embed(None, "a", None).unwrap().vector.len(), 2);
+        assert!(matches!(
+            registry(None).embed(None, "a", Some(4)),
+            Err(EmbeddingError::InvalidDimensions { requested: 4, available: 3 })
+        ));
+        assert!(matches!(registry(None).embed(None, "a", Some(0)), Err(EmbeddingError::InvalidDimensions { .. })));
+        assert!(matches!(registry(None).embed(Some("bert"), "a", None), Err(EmbeddingError::UnknownModel(_))));
+    }
+
+    #[test]
+    fn batches_report_empty_inputs_per_item() {
+        let inputs: Vec<String> = ["a", " ", "b"].map(str::to_string).into();
+        let batch = registry(None).embed_batch(None, &inputs, None).unwrap();
+        assert_eq!(batch.results.len(), 3);
+        assert!(batch.results[0].is_ok() && batch.results[2].is_ok());
+        assert!(matches!(batch.results[1], Err(EmbeddingError::EmptyInput)));
+
+        let too_many = vec!["a".to_string(); 5];
+        assert!(matches!(registry(None).embed_batch(None, &too_many, None), Err(EmbeddingError::BatchTooLarge { size: 5, max: 4 })));
+    }
+}
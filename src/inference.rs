@@ -0,0 +1,640 @@
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// Default model served when the request does not name one
+pub const DEFAULT_MODEL: &str = "rust-llm-norwegian-v1";
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, InferenceError>> + Send + 'a>>;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ChatMessage {
+    pub role: String, // system, user, assistant
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn user(content: &str) -> Self {
+        ChatMessage { role: "user".to_string(), content: content.to_string() }
+    }
+}
+
+#[derive(Clone)]
+pub struct GenerationParams {
+    pub messages: Vec<ChatMessage>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    pub stop: Vec<String>,
+}
+
+impl GenerationParams {
+    // All system messages, for backends with a separate system field
+    pub fn system(&self) -> Option<String> {
+        let system: Vec<&str> = self.messages.iter()
+            .filter(|message| message.role == "system")
+            .map(|message| message.content.as_str())
+            .collect();
+        if system.is_empty() { None } else { Some(system.join("\n\n")) }
+    }
+
+    // Conversation flattened to a single prompt for completion-style backends.
+    // A lone user message is passed through verbatim.
+    pub fn prompt(&self) -> String {
+        let turns: Vec<&ChatMessage> = self.messages.iter()
+            .filter(|message| message.role != "system")
+            .collect();
+
+        if let [only] = turns.as_slice() {
+            if only.role == "user" {
+                return only.content.clone();
+            }
+        }
+
+        let mut prompt = String::new();
+        for turn in turns {
+            let speaker = if turn.role == "assistant" { "Assistant" } else { "User" };
+            prompt.push_str(&format!("{}: {}\n", speaker, turn.content));
+        }
+        prompt.push_str("Assistant:");
+        prompt
+    }
+}
+
+pub struct Completion {
+    pub text: String,
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    pub finish_reason: String, // "stop" or "length", OpenAI vocabulary
+}
+
+#[derive(Debug)]
+pub enum InferenceError {
+    UnknownModel(String),
+    Upstream(String),
+    InvalidResponse(String),
+}
+
+impl fmt::Display for InferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InferenceError::UnknownModel(model) => write!(f, "Model '{}' is not served by any configured backend", model),
+            InferenceError::Upstream(message) => write!(f, "Upstream inference request failed: {}", message),
+            InferenceError::InvalidResponse(message) => write!(f, "Upstream returned an unexpected response: {}", message),
+        }
+    }
+}
+
+impl From<reqwest::Error> for InferenceError {
+    fn from(error: reqwest::Error) -> Self {
+        InferenceError::Upstream(error.to_string())
+    }
+}
+
+// A text generation engine. `model` is the upstream model name, with any
+// routing prefix already stripped by the registry.
+pub trait InferenceBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn generate<'a>(&'a self, model: &'a str, params: &'a GenerationParams) -> BackendFuture<'a, Completion>;
+
+    // Streaming variant: text deltas are sent on `deltas` as they are produced and
+    // the returned completion carries the full text. Backends without native
+    // streaming send the whole completion as a single delta.
+    fn generate_stream<'a>(
+        &'a self,
+        model: &'a str,
+        params: &'a GenerationParams,
+        deltas: mpsc::Sender<String>,
+    ) -> BackendFuture<'a, Completion> {
+        Box::pin(async move {
+            let completion = self.generate(model, params).await?;
+            let _ = deltas.send(completion.text.clone()).await;
+            Ok(completion)
+        })
+    }
+}
+
+fn http_client() -> reqwest::Client {
+    let timeout_secs = env::var("INFERENCE_TIMEOUT_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(120);
+
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+async fn post_json(request: reqwest::RequestBuilder, body: &Value) -> Result<Value, InferenceError> {
+    let response = request.json(body).send().await?;
+    let status = response.status();
+    let payload = response.text().await?;
+
+    if !status.is_success() {
+        return Err(InferenceError::Upstream(format!("HTTP {}: {}", status.as_u16(), payload)));
+    }
+
+    serde_json::from_str(&payload).map_err(|e| InferenceError::InvalidResponse(e.to_string()))
+}
+
+fn as_u32(value: &Value) -> Option<u32> {
+    value.as_u64().map(|v| v as u32)
+}
+
+// Starts a streaming request and fails early on non-2xx responses
+async fn post_stream(request: reqwest::RequestBuilder, body: &Value) -> Result<LineReader, InferenceError> {
+    let response = request.json(body).send().await?;
+    let status = response.status();
+
+    if !status.is_success() {
+        let payload = response.text().await.unwrap_or_default();
+        return Err(InferenceError::Upstream(format!("HTTP {}: {}", status.as_u16(), payload)));
+    }
+
+    Ok(LineReader {
+        stream: Box::pin(response.bytes_stream()),
+        buffer: Vec::new(),
+    })
+}
+
+// Splits a streamed HTTP body into lines (SSE and NDJSON are both line based)
+struct LineReader {
+    stream: Pin<Box<dyn futures_util::Stream<Item = reqwest::Result<bytes::Bytes>> + Send>>,
+    buffer: Vec<u8>,
+}
+
+impl LineReader {
+    async fn next_line(&mut self) -> Result<Option<String>, InferenceError> {
+        loop {
+            if let Some(position) = self.buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=position).collect();
+                return Ok(Some(String::from_utf8_lossy(&line).trim_end().to_string()));
+            }
+
+            match self.stream.next().await {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk?),
+                None if self.buffer.is_empty() => return Ok(None),
+                None => {
+                    let line: Vec<u8> = self.buffer.drain(..).collect();
+                    return Ok(Some(String::from_utf8_lossy(&line).trim_end().to_string()));
+                },
+            }
+        }
+    }
+
+    // Next `data:` payload of a Server-Sent Events stream, None at end or [DONE]
+    async fn next_event(&mut self) -> Result<Option<Value>, InferenceError> {
+        while let Some(line) = self.next_line().await? {
+            let Some(data) = line.strip_prefix("data:") else { continue };
+            let data = data.trim();
+            if data == "[DONE]" {
+                return Ok(None);
+            }
+            let event = serde_json::from_str(data).map_err(|e| InferenceError::InvalidResponse(e.to_string()))?;
+            return Ok(Some(event));
+        }
+        Ok(None)
+    }
+}
+
+// OpenAI-compatible upstream (OpenAI, Azure-style proxies, vLLM, LM Studio, ...)
+pub struct OpenAiBackend {
+    base_url: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+}
+
+impl OpenAiBackend {
+    pub fn new(base_url: &str, api_key: Option<String>) -> Self {
+        OpenAiBackend {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            client: http_client(),
+        }
+    }
+
+    fn request(&self, model: &str, params: &GenerationParams, stream: bool) -> (reqwest::RequestBuilder, Value) {
+        let mut body = json!({
+            "model": model,
+            "messages": params.messages,
+            "max_tokens": params.max_tokens,
+            "temperature": params.temperature,
+        });
+        if !params.stop.is_empty() {
+            body["stop"] = json!(params.stop);
+        }
+        if stream {
+            body["stream"] = json!(true);
+            body["stream_options"] = json!({ "include_usage": true });
+        }
+
+        let mut request = self.client.post(format!("{}/chat/completions", self.base_url));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        (request, body)
+    }
+}
+
+impl InferenceBackend for OpenAiBackend {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn generate<'a>(&'a self, model: &'a str, params: &'a GenerationParams) -> BackendFuture<'a, Completion> {
+        Box::pin(async move {
+            let (request, body) = self.request(model, params, false);
+            let payload = post_json(request, &body).await?;
+            let choice = payload["choices"].get(0)
+                .ok_or_else(|| InferenceError::InvalidResponse("missing choices[0]".to_string()))?;
+
+            Ok(Completion {
+                text: choice["message"]["content"].as_str().unwrap_or_default().to_string(),
+                prompt_tokens: as_u32(&payload["usage"]["prompt_tokens"]),
+                completion_tokens: as_u32(&payload["usage"]["completion_tokens"]),
+                finish_reason: choice["finish_reason"].as_str().unwrap_or("stop").to_string(),
+            })
+        })
+    }
+
+    fn generate_stream<'a>(
+        &'a self,
+        model: &'a str,
+        params: &'a GenerationParams,
+        deltas: mpsc::Sender<String>,
+    ) -> BackendFuture<'a, Completion> {
+        Box::pin(async move {
+            let (request, body) = self.request(model, params, true);
+            let mut reader = post_stream(request, &body).await?;
+            let mut completion = Completion {
+                text: String::new(),
+                prompt_tokens: None,
+                completion_tokens: None,
+                finish_reason: "stop".to_string(),
+            };
+
+            while let Some(event) = reader.next_event().await? {
+                if let Some(choice) = event["choices"].get(0) {
+                    if let Some(content) = choice["delta"]["content"].as_str() {
+                        completion.text.push_str(content);
+                        if deltas.send(content.to_string()).await.is_err() {
+                            break; // client went away
+                        }
+                    }
+                    if let Some(reason) = choice["finish_reason"].as_str() {
+                        completion.finish_reason = reason.to_string();
+                    }
+                }
+                // The usage block arrives in a final chunk with empty choices
+                if event["usage"].is_object() {
+                    completion.prompt_tokens = as_u32(&event["usage"]["prompt_tokens"]);
+                    completion.completion_tokens = as_u32(&event["usage"]["completion_tokens"]);
+                }
+            }
+
+            Ok(completion)
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum LocalFlavor {
+    Ollama,   // POST /api/generate
+    LlamaCpp, // POST /completion (llama.cpp server)
+}
+
+// Self-hosted model server running next to the service
+pub struct LocalBackend {
+    base_url: String,
+    flavor: LocalFlavor,
+    client: reqwest::Client,
+}
+
+impl LocalBackend {
+    pub fn new(base_url: &str, flavor: LocalFlavor) -> Self {
+        LocalBackend {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            flavor,
+            client: http_client(),
+        }
+    }
+
+    fn request(&self, model: &str, params: &GenerationParams, stream: bool) -> (reqwest::RequestBuilder, Value) {
+        match self.flavor {
+            LocalFlavor::Ollama => {
+                let mut body = json!({
+                    "model": model,
+                    "prompt": params.prompt(),
+                    "stream": stream,
+                    "options": {
+                        "num_predict": params.max_tokens,
+                        "temperature": params.temperature,
+                        "stop": params.stop,
+                    },
+                });
+                if let Some(system) = params.system() {
+                    body["system"] = json!(system);
+                }
+                (self.client.post(format!("{}/api/generate", self.base_url)), body)
+            },
+            LocalFlavor::LlamaCpp => {
+                // llama.cpp serves a single loaded model and has no system field
+                let prompt = match params.system() {
+                    Some(system) => format!("{}\n\n{}", system, params.prompt()),
+                    None => params.prompt(),
+                };
+                let body = json!({
+                    "prompt": prompt,
+                    "n_predict": params.max_tokens,
+                    "temperature": params.temperature,
+                    "stop": params.stop,
+                    "stream": stream,
+                });
+                (self.client.post(format!("{}/completion", self.base_url)), body)
+            },
+        }
+    }
+
+    // Final (or only) response object to a completion
+    fn completion_from(&self, payload: &Value, text: String) -> Completion {
+        match self.flavor {
+            LocalFlavor::Ollama => Completion {
+                text,
+                prompt_tokens: as_u32(&payload["prompt_eval_count"]),
+                completion_tokens: as_u32(&payload["eval_count"]),
+                finish_reason: payload["done_reason"].as_str().unwrap_or("stop").to_string(),
+            },
+            LocalFlavor::LlamaCpp => Completion {
+                text,
+                prompt_tokens: as_u32(&payload["tokens_evaluated"]),
+                completion_tokens: as_u32(&payload["tokens_predicted"]),
+                finish_reason: if payload["stopped_limit"].as_bool().unwrap_or(false) { "length" } else { "stop" }.to_string(),
+            },
+        }
+    }
+
+    fn text_field(&self) -> &'static str {
+        match self.flavor {
+            LocalFlavor::Ollama => "response",
+            LocalFlavor::LlamaCpp => "content",
+        }
+    }
+}
+
+impl InferenceBackend for LocalBackend {
+    fn name(&self) -> &'static str {
+        match self.flavor {
+            LocalFlavor::Ollama => "ollama",
+            LocalFlavor::LlamaCpp => "llamacpp",
+        }
+    }
+
+    fn generate<'a>(&'a self, model: &'a str, params: &'a GenerationParams) -> BackendFuture<'a, Completion> {
+        Box::pin(async move {
+            let (request, body) = self.request(model, params, false);
+            let payload = post_json(request, &body).await?;
+            let text = payload[self.text_field()].as_str().unwrap_or_default().to_string();
+            Ok(self.completion_from(&payload, text))
+        })
+    }
+
+    fn generate_stream<'a>(
+        &'a self,
+        model: &'a str,
+        params: &'a GenerationParams,
+        deltas: mpsc::Sender<String>,
+    ) -> BackendFuture<'a, Completion> {
+        Box::pin(async move {
+            let (request, body) = self.request(model, params, true);
+            let mut reader = post_stream(request, &body).await?;
+            let mut text = String::new();
+            let mut last = Value::Null;
+
+            // Ollama streams NDJSON objects, llama.cpp streams SSE `data:` events
+            loop {
+                let event = match self.flavor {
+                    LocalFlavor::Ollama => match reader.next_line().await? {
+                        Some(line) if line.trim().is_empty() => continue,
+                        Some(line) => serde_json::from_str::<Value>(&line)
+                            .map_err(|e| InferenceError::InvalidResponse(e.to_string()))?,
+                        None => break,
+                    },
+                    LocalFlavor::LlamaCpp => match reader.next_event().await? {
+                        Some(event) => event,
+                        None => break,
+                    },
+                };
+
+                if let Some(content) = event[self.text_field()].as_str() {
+                    if !content.is_empty() {
+                        text.push_str(content);
+                        if deltas.send(content.to_string()).await.is_err() {
+                            break; // client went away
+                        }
+                    }
+                }
+
+                let finished = event["done"].as_bool().unwrap_or(false) || event["stop"].as_bool().unwrap_or(false);
+                last = event;
+                if finished {
+                    break;
+                }
+            }
+
+            Ok(self.completion_from(&last, text))
+        })
+    }
+}
+
+// Deterministic echo backend, used when no upstream is configured and in tests
+pub struct MockBackend;
+
+impl MockBackend {
+    fn respond(params: &GenerationParams) -> String {
+        format!(
+            "AI Response to '{}': This is a simulated response from the Rust LLM service. In a production environment, this would be replaced with actual LLM inference.",
+            params.prompt()
+        )
+    }
+}
+
+impl InferenceBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn generate<'a>(&'a self, _model: &'a str, params: &'a GenerationParams) -> BackendFuture<'a, Completion> {
+        Box::pin(async move {
+            Ok(Completion {
+                text: MockBackend::respond(params),
+                prompt_tokens: None,
+                completion_tokens: None,
+                finish_reason: "stop".to_string(),
+            })
+        })
+    }
+
+    // Emits the canned response word by word so streaming clients can be exercised
+    fn generate_stream<'a>(
+        &'a self,
+        _model: &'a str,
+        params: &'a GenerationParams,
+        deltas: mpsc::Sender<String>,
+    ) -> BackendFuture<'a, Completion> {
+        Box::pin(async move {
+            let text = MockBackend::respond(params);
+            for word in text.split_inclusive(' ') {
+                if deltas.send(word.to_string()).await.is_err() {
+                    break;
+                }
+            }
+
+            Ok(Completion {
+                text,
+                prompt_tokens: None,
+                completion_tokens: None,
+                finish_reason: "stop".to_string(),
+            })
+        })
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct ModelRoute {
+    pub id: String,
+    pub backend: String,
+    pub upstream_model: String,
+}
+
+// Maps public model ids onto backends. Models are configured through env:
+// OPENAI_BASE_URL/OPENAI_API_KEY/OPENAI_MODELS, OLLAMA_BASE_URL/OLLAMA_MODELS,
+// LLAMACPP_BASE_URL/LLAMACPP_MODELS and DEFAULT_MODEL. Any configured backend
+// can also be addressed explicitly as "<backend>/<model>", e.g. "ollama/llama3:8b".
+pub struct BackendRegistry {
+    backends: HashMap<&'static str, Arc<dyn InferenceBackend>>,
+    routes: Vec<ModelRoute>,
+    default_model: String,
+}
+
+impl BackendRegistry {
+    pub fn from_env() -> Self {
+        let mut registry = BackendRegistry {
+            backends: HashMap::new(),
+            routes: Vec::new(),
+            default_model: env::var("DEFAULT_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string()),
+        };
+
+        registry.register(Arc::new(MockBackend), &[DEFAULT_MODEL, "rust-llm-v1", "mock"]);
+
+        let openai_key = env::var("OPENAI_API_KEY").ok().filter(|key| !key.is_empty());
+        let openai_url = env::var("OPENAI_BASE_URL").ok()
+            .or_else(|| openai_key.as_ref().map(|_| "https://api.openai.com/v1".to_string()));
+        if let Some(base_url) = openai_url {
+            let models = model_list("OPENAI_MODELS");
+            registry.register(Arc::new(OpenAiBackend::new(&base_url, openai_key)), &models);
+        }
+
+        if let Ok(base_url) = env::var("OLLAMA_BASE_URL") {
+            let models = model_list("OLLAMA_MODELS");
+            registry.register(Arc::new(LocalBackend::new(&base_url, LocalFlavor::Ollama)), &models);
+        }
+
+        if let Ok(base_url) = env::var("LLAMACPP_BASE_URL") {
+            let models = model_list("LLAMACPP_MODELS");
+            registry.register(Arc::new(LocalBackend::new(&base_url, LocalFlavor::LlamaCpp)), &models);
+        }
+
+        for route in &registry.routes {
+            println!("   - Model '{}' served by {} backend", route.id, route.backend);
+        }
+
+        registry
+    }
+
+    fn register<S: AsRef<str>>(&mut self, backend: Arc<dyn InferenceBackend>, models: &[S]) {
+        let name = backend.name();
+        for model in models {
+            self.routes.push(ModelRoute {
+                id: model.as_ref().to_string(),
+                backend: name.to_string(),
+                upstream_model: model.as_ref().to_string(),
+            });
+        }
+        self.backends.insert(name, backend);
+    }
+
+    pub fn default_model(&self) -> &str {
+        &self.default_model
+    }
+
+    pub fn routes(&self) -> &[ModelRoute] {
+        &self.routes
+    }
+
+    // Resolve a requested model to its backend and upstream model name
+    pub fn resolve(&self, requested: Option<&str>) -> Result<(Arc<dyn InferenceBackend>, ModelRoute), InferenceError> {
+        let model = requested.unwrap_or(&self.default_model);
+
+        if let Some(route) = self.routes.iter().find(|route| route.id == model) {
+            if let Some(backend) = self.backends.get(route.backend.as_str()) {
+                return Ok((backend.clone(), route.clone()));
+            }
+        }
+
+        if let Some((prefix, upstream_model)) = model.split_once('/') {
+            if let Some((name, backend)) = self.backends.get_key_value(prefix) {
+                return Ok((backend.clone(), ModelRoute {
+                    id: model.to_string(),
+                    backend: name.to_string(),
+                    upstream_model: upstream_model.to_string(),
+                }));
+            }
+        }
+
+        Err(InferenceError::UnknownModel(model.to_string()))
+    }
+}
+
+fn model_list(var: &str) -> Vec<String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(prompt: &str) -> GenerationParams {
+        GenerationParams { messages: vec![ChatMessage::user(prompt)], max_tokens: 100, temperature: 0.7, stop: Vec::new() }
+    }
+
+    #[tokio::test]
+    async fn mock_backend_echoes_the_prompt() {
+        let completion = MockBackend.generate("mock", &params("Hva er MVA på mat?")).await.unwrap();
+        assert!(completion.text.starts_with("AI Response to 'Hva er MVA på mat?':"));
+        assert_eq!(completion.finish_reason, "stop");
+    }
+
+    #[tokio::test]
+    async fn mock_stream_adds_up_to_the_completion() {
+        let (sender, mut receiver) = mpsc::channel(256);
+        let completion = MockBackend.generate_stream("mock", &params("Hei"), sender).await.unwrap();
+        let mut streamed = String::new();
+        while let Some(delta) = receiver.recv().await {
+            streamed.push_str(&delta);
+        }
+        assert_eq!(streamed, completion.text);
+    }
+}
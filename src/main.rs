@@ -4,8 +4,49 @@ use serde::{Deserialize, Serialize};
 use std::env;
 use std::collections::HashMap;
 use sha2::{Sha256, Digest};
-use std::sync::{Arc, Mutex};
-use std::fs;
+use std::sync::Arc;
+
+mod amount;
+mod brreg;
+mod calendar;
+mod currency;
+mod ehf;
+mod embeddings;
+mod inference;
+mod merchants;
+mod money;
+mod ocr;
+mod openai_api;
+mod orgnr;
+mod pdf;
+mod preprocess;
+mod receipt;
+mod seasonality;
+mod store;
+mod tokenizer;
+mod transaction;
+mod vat_rates;
+
+use brreg::{BusinessRegister, RegisteredEntity};
+use calendar::{Holiday, ShoppingWindow};
+use currency::{CurrencyConversion, DetectedCurrency, ExchangeRateTable};
+use ehf::EhfInvoice;
+use embeddings::{EmbedderRegistry, EmbeddingError};
+use ocr::{OcrEngine, OcrError, OcrLine, OcrResult};
+use openai_api::StringOrList;
+use orgnr::OrgNumber;
+use pdf::PdfError;
+use preprocess::PreprocessedImage;
+use receipt::{ReceiptBreakdown, VatRateBreakdown};
+use seasonality::SeasonalInsight;
+use merchants::{MerchantError, MerchantRecord, MerchantRegistry};
+use money::{Currency, Money, MAX_KRONER};
+use inference::{BackendRegistry, ChatMessage, Completion, GenerationParams, InferenceBackend, InferenceError, ModelRoute};
+use store::Store;
+use tokenizer::Tokenizer;
+use transaction::TransactionDetails;
+use vat_rates::{EffectiveRates, VatRateRegistry};
+use tokio::sync::mpsc;
 
 #[derive(Deserialize)]
 struct TextGenerationRequest {
@@ -15,30 +56,42 @@ struct TextGenerationRequest {
     temperature: Option<f32>,
     norwegian_context: Option<bool>,
     organization_type: Option<String>,
+    stream: Option<bool>, // Server-Sent Events instead of a single JSON body
 }
 
 #[derive(Deserialize)]
 struct EmbeddingsRequest {
-    text: String,
+    text: Option<String>,
+    input: Option<StringOrList>, // one text or a batch; answered with `data`
     model: Option<String>,
-    norwegian_context: Option<bool>,
+    dimensions: Option<usize>, // truncate (and re-normalise) to this many dimensions
 }
 
 #[derive(Deserialize)]
 struct DocumentProcessingRequest {
     image_data: Option<String>, // Base64 encoded image
+    pdf_data: Option<String>, // Base64 encoded PDF, analysed page by page
     document_text: Option<String>, // Pre-extracted text
+    #[allow(dead_code)]
     document_type: Option<String>, // receipt, invoice, etc.
+    #[allow(dead_code)]
     norwegian_context: Option<bool>,
     organization_type: Option<String>,
+    transaction_date: Option<String>, // YYYY-MM-DD; VAT rates in force that day are applied
     correction_data: Option<UserCorrection>,
 }
 
+#[derive(Deserialize)]
+struct EhfInvoiceRequest {
+    invoice_xml: String, // EHF / PEPPOL BIS 3.0 UBL XML, raw or base64 encoded
+    organization_type: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 struct UserCorrection {
     original_analysis: String,
     corrected_merchant: Option<String>,
-    corrected_amount: Option<f32>,
+    corrected_amount: Option<Money>,
     corrected_vat_rate: Option<u8>,
     corrected_category: Option<String>,
     user_feedback: Option<String>,
@@ -51,12 +104,44 @@ struct TextGenerationResponse {
     model: String,
     processing_time_ms: u64,
     tokens_generated: u32,
+    prompt_tokens: Option<u32>,
+    finish_reason: Option<String>,
     timestamp: String,
     generated_text: Option<String>, // Alias for felleskassen compatibility
     inference_time_ms: Option<u64>, // Alias for felleskassen compatibility
     _routing: Option<RoutingInfo>,
 }
 
+// One Server-Sent Event in an OpenAI-style `chat.completion.chunk` stream
+#[derive(Serialize)]
+struct TextGenerationChunk {
+    id: String,
+    object: String,
+    created: i64,
+    model: String,
+    choices: Vec<TextGenerationChunkChoice>,
+    // Only present on the final event
+    #[serde(skip_serializing_if = "Option::is_none")]
+    processing_time_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tokens_generated: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prompt_tokens: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct TextGenerationChunkChoice {
+    index: u32,
+    delta: TextGenerationDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TextGenerationDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+}
+
 #[derive(Serialize)]
 struct RoutingInfo {
     service: String,
@@ -73,6 +158,23 @@ struct EmbeddingsResponse {
     timestamp: String,
 }
 
+#[derive(Serialize)]
+struct EmbeddingsBatchResponse {
+    data: Vec<EmbeddingsBatchItem>,
+    model: String,
+    processing_time_ms: u64,
+    timestamp: String,
+}
+
+#[derive(Serialize)]
+struct EmbeddingsBatchItem {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embedding: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
 #[derive(Serialize, Clone)]
 struct NorwegianMerchantInfo {
     name: String,
@@ -81,6 +183,15 @@ struct NorwegianMerchantInfo {
     typical_vat_rate: u8,
     seasonal_products: Vec<String>,
     org_pattern: Option<String>,
+    // Organisation number printed on the document, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_number: Option<OrgNumber>,
+    // Enhetsregisteret data when the seller was resolved from the register
+    #[serde(skip_serializing_if = "Option::is_none")]
+    register_entry: Option<RegisteredEntity>,
+    // ISO 3166 code for chains outside Norway
+    #[serde(skip_serializing_if = "Option::is_none")]
+    country: Option<String>,
     confidence: f32,
 }
 
@@ -92,14 +203,40 @@ struct NorwegianAnalysis {
     compliance_check: ComplianceCheck,
     cultural_significance: Option<String>,
     deductibility_assessment: String,
+    amount: Option<Money>, // total the analysis is based on
+    amount_not_found: bool, // no amount could be read from the document
+    // Other merchants the text matched, best first; `merchant` is the first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    merchant_candidates: Vec<MerchantMatch>,
+    // Set for receipts in a foreign currency; returned on the document response
+    #[serde(skip)]
+    currency_conversion: Option<CurrencyConversion>,
+}
+
+#[derive(Serialize, Clone)]
+struct MerchantMatch {
+    name: String,
+    score: f32, // 0 to 1
+    matched: String, // name, alias or organisation number found in the text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>, // where the name was found, counting non-blank lines
+    similarity: f32, // below 1 for names matched despite OCR errors
+    #[serde(skip)]
+    merchant: NorwegianMerchantInfo,
 }
 
 #[derive(Serialize)]
 struct VatAnalysis {
     detected_rate: u8,
     rate_explanation: String,
-    total_vat_amount: Option<f32>,
+    total_vat_amount: Option<Money>,
     compliance_status: String,
+    breakdown: Vec<VatRateBreakdown>, // base and VAT per rate
+    breakdown_source: String, // receipt_summary, item_classification, ehf_invoice or estimated
+    rates_date: String, // the day whose VAT rates were applied
+    // Currency of the amounts above when the receipt is not in NOK
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
 }
 
 #[derive(Serialize)]
@@ -108,6 +245,12 @@ struct SeasonalContext {
     cultural_event: Option<String>,
     typical_purchases: Vec<String>,
     price_expectations: String,
+    // Holiday on the date itself, e.g. "Skjærtorsdag"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    holiday: Option<String>,
+    // Days until the holiday the season leads up to, 0 on the day
+    #[serde(skip_serializing_if = "Option::is_none")]
+    days_until_event: Option<i64>,
 }
 
 #[derive(Serialize)]
@@ -116,12 +259,32 @@ struct ComplianceCheck {
     deductibility: String,
     documentation_required: Vec<String>,
     approval_needed: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    foreign_vat: Option<ForeignVat>,
+}
+
+#[derive(Serialize)]
+struct ForeignVat {
+    country_currency: Currency,
+    vat_amount: Option<Money>, // in country_currency, as printed on the receipt
+    refundable: bool, // never as Norwegian input VAT
 }
 
 #[derive(Serialize)]
 struct DocumentProcessingResponse {
     norwegian_analysis: NorwegianAnalysis,
     image_analysis: Option<ImageAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pages: Option<Vec<PageAnalysis>>, // one entry per PDF page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transaction: Option<TransactionDetails>, // date, time, receipt number, cashier and terminal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    receipt: Option<ReceiptBreakdown>, // line items, discounts and pant checked against the total
+    // Original and NOK amounts of a receipt paid in another currency
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency_conversion: Option<CurrencyConversion>,
     processing_confidence: f32,
     learning_applied: bool,
     model: String,
@@ -129,13 +292,76 @@ struct DocumentProcessingResponse {
     timestamp: String,
 }
 
+#[derive(Serialize)]
+struct EhfInvoiceResponse {
+    invoice: EhfInvoice,
+    norwegian_analysis: NorwegianAnalysis,
+    validation_warnings: Vec<String>, // declared sums that do not add up
+    // Invoice and NOK amounts of an invoice in another currency
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency_conversion: Option<CurrencyConversion>,
+    model: String,
+    processing_time_ms: u64,
+    timestamp: String,
+}
+
+#[derive(Serialize)]
+struct MerchantListResponse {
+    version: u32, // merchant file format
+    source: String, // MERCHANTS_FILE path, or built-in
+    count: usize,
+    merchants: Vec<MerchantRecord>,
+    timestamp: String,
+}
+
+#[derive(Serialize)]
+struct MerchantResponse {
+    merchant: MerchantRecord,
+    persisted: bool, // written to MERCHANTS_FILE, not only held in memory
+    timestamp: String,
+}
+
+#[derive(Serialize)]
+struct CalendarResponse {
+    year: i32,
+    easter_sunday: String,
+    holidays: Vec<Holiday>, // public holidays and the eves people shop for
+    shopping_windows: Vec<ShoppingWindow>,
+    timestamp: String,
+}
+
 #[derive(Serialize)]
 struct ImageAnalysis {
-    image_quality: String,
+    image_quality: ImageQuality,
     text_regions_detected: u32,
     ocr_confidence: f32,
     document_type_detected: String,
     norwegian_text_detected: bool,
+    ocr_engine: String,
+    width: u32,
+    height: u32,
+    text: String,
+    lines: Vec<OcrLine>, // recognised lines with pixel bounding boxes
+}
+
+#[derive(Serialize)]
+struct PageAnalysis {
+    page_number: u32,
+    text_source: String, // text_layer, ocr or unreadable
+    text: String,
+    norwegian_analysis: Option<NorwegianAnalysis>,
+    image_analysis: Option<ImageAnalysis>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ImageQuality {
+    rating: String, // High, Medium or Low, from the sharpness score
+    sharpness_score: f32, // variance of the Laplacian; below 60 is blurry
+    blurry: bool,
+    preprocessing_steps: Vec<String>,
+    original_width: u32,
+    original_height: u32,
 }
 
 #[derive(Serialize)]
@@ -151,8 +377,11 @@ struct LearningResponse {
 struct FineTuningRequest {
     training_data: Vec<TrainingExample>,
     model_type: Option<String>, // norwegian_merchant, vat_analysis, seasonal_patterns
+    #[allow(dead_code)]
     epochs: Option<u32>,
+    #[allow(dead_code)]
     learning_rate: Option<f32>,
+    #[allow(dead_code)]
     validation_split: Option<f32>,
 }
 
@@ -160,7 +389,7 @@ struct FineTuningRequest {
 struct TrainingExample {
     input_text: String,
     expected_merchant: Option<String>,
-    expected_amount: Option<f32>,
+    expected_amount: Option<Money>,
     expected_vat_rate: Option<u8>,
     expected_category: Option<String>,
     context_metadata: Option<String>,
@@ -201,7 +430,8 @@ struct PredictiveAnalysisRequest {
 struct HistoricalTransaction {
     date: String,
     merchant: String,
-    amount: f32,
+    #[serde(deserialize_with = "money::deserialize_nok")]
+    amount: Money, // NOK; foreign amounts are rejected rather than summed as kroner
     category: String,
     season: Option<String>,
     cultural_event: Option<String>,
@@ -221,26 +451,17 @@ struct PredictiveAnalysisResponse {
 #[derive(Serialize)]
 struct SpendingPrediction {
     period: String,
-    predicted_amount: f32,
+    predicted_amount: Money,
     category: String,
     confidence: f32,
     trend: String, // "increasing", "decreasing", "stable"
     factors: Vec<String>,
 }
 
-#[derive(Serialize)]
-struct SeasonalInsight {
-    season: String,
-    cultural_event: Option<String>,
-    expected_spending_increase: f32,
-    key_categories: Vec<String>,
-    historical_pattern: String,
-}
-
 #[derive(Serialize)]
 struct BudgetRecommendation {
     category: String,
-    recommended_budget: f32,
+    recommended_budget: Money,
     reasoning: String,
     risk_level: String, // "low", "medium", "high"
     optimization_tips: Vec<String>,
@@ -253,6 +474,7 @@ struct HealthResponse {
     version: String,
     timestamp: String,
     uptime_seconds: u64,
+    storage: String,
 }
 
 #[derive(Serialize)]
@@ -264,14 +486,21 @@ struct ErrorResponse {
 
 // Global learning storage (in production, this would be a proper database)
 lazy_static::lazy_static! {
-    static ref LEARNING_DATA: Arc<Mutex<Vec<UserCorrection>>> = Arc::new(Mutex::new(Vec::new()));
-    static ref MERCHANT_LEARNING: Arc<Mutex<HashMap<String, f32>>> = Arc::new(Mutex::new(HashMap::new()));
-    static ref TRAINING_DATA: Arc<Mutex<Vec<TrainingExample>>> = Arc::new(Mutex::new(Vec::new()));
-    static ref FINE_TUNED_MODELS: Arc<Mutex<HashMap<String, ModelMetrics>>> = Arc::new(Mutex::new(HashMap::new()));
-    static ref SEASONAL_PATTERNS: Arc<Mutex<HashMap<String, Vec<HistoricalTransaction>>>> = Arc::new(Mutex::new(HashMap::new()));
+    // Corrections, merchant confidence, training data, fine-tuned models and seasonal history
+    static ref STORE: Box<dyn Store> = store::from_env();
+    static ref INFERENCE_BACKENDS: BackendRegistry = BackendRegistry::from_env();
+    static ref TOKENIZER: Tokenizer = Tokenizer::from_env();
+    static ref EMBEDDERS: EmbedderRegistry = EmbedderRegistry::from_env();
+    static ref OCR_ENGINE: OcrEngine = OcrEngine::from_env();
+    static ref VAT_RATES: VatRateRegistry = VatRateRegistry::from_env();
+    static ref MERCHANTS: MerchantRegistry = MerchantRegistry::from_env();
+    // Enhetsregisteret entities for sellers outside the merchant database
+    static ref BUSINESS_REGISTER: BusinessRegister = BusinessRegister::from_env();
+    static ref EXCHANGE_RATES: ExchangeRateTable = ExchangeRateTable::from_env();
 }
 
 // API Key validation function
+#[allow(clippy::result_large_err)]
 fn validate_api_key_header(req: &HttpRequest) -> Result<(), HttpResponse> {
     let api_key = env::var("RUST_LLM_API_KEY").unwrap_or_else(|_| "".to_string());
     
@@ -285,8 +514,7 @@ fn validate_api_key_header(req: &HttpRequest) -> Result<(), HttpResponse> {
     
     if let Some(auth_value) = auth_header {
         if let Ok(auth_str) = auth_value.to_str() {
-            if auth_str.starts_with("Bearer ") {
-                let provided_key = &auth_str[7..]; // Remove "Bearer " prefix
+            if let Some(provided_key) = auth_str.strip_prefix("Bearer ") {
                 
                 // Use constant-time comparison to prevent timing attacks
                 let expected_hash = {
@@ -318,250 +546,243 @@ fn validate_api_key_header(req: &HttpRequest) -> Result<(), HttpResponse> {
     Err(HttpResponse::Unauthorized().json(error_response))
 }
 
-// Norwegian Merchant Intelligence Database
-fn get_norwegian_merchant_database() -> HashMap<&'static str, NorwegianMerchantInfo> {
-    let mut merchants = HashMap::new();
-    
-    // REMA 1000 Intelligence
-    merchants.insert("REMA", NorwegianMerchantInfo {
-        name: "REMA 1000".to_string(),
-        chain: "REMA 1000".to_string(),
-        category: "Grocery Store".to_string(),
-        typical_vat_rate: 15, // Food VAT
-        seasonal_products: vec![
-            "Ribbe".to_string(), "Pinnekjøtt".to_string(), "Lutefisk".to_string(),
-            "Egg".to_string(), "Lam".to_string(), "Is".to_string(), "Grillmat".to_string()
-        ],
-        org_pattern: Some("999208372".to_string()),
-        confidence: 0.95,
-    });
-    
-    // ICA Intelligence
-    merchants.insert("ICA", NorwegianMerchantInfo {
-        name: "ICA Supermarket".to_string(),
-        chain: "ICA".to_string(),
-        category: "Grocery Store".to_string(),
-        typical_vat_rate: 15,
-        seasonal_products: vec![
-            "Kvikk Lunsj".to_string(), "Egg".to_string(), "Melk".to_string(),
-            "Brød".to_string(), "Ost".to_string()
-        ],
-        org_pattern: None,
-        confidence: 0.92,
-    });
-    
-    // COOP Intelligence  
-    merchants.insert("COOP", NorwegianMerchantInfo {
-        name: "Coop".to_string(),
-        chain: "COOP".to_string(),
-        category: "Grocery Store".to_string(),
-        typical_vat_rate: 15,
-        seasonal_products: vec![
-            "Ø-merket".to_string(), "Miljømerket".to_string(), "Lokalt".to_string(),
-            "Nærprodusert".to_string()
-        ],
-        org_pattern: None,
-        confidence: 0.94,
-    });
-    
-    // KIWI Intelligence
-    merchants.insert("KIWI", NorwegianMerchantInfo {
-        name: "KIWI".to_string(),
-        chain: "KIWI".to_string(),
-        category: "Discount Grocery".to_string(),
-        typical_vat_rate: 15,
-        seasonal_products: vec![
-            "Lavpris".to_string(), "Tilbud".to_string(), "2 for 1".to_string()
-        ],
-        org_pattern: None,
-        confidence: 0.93,
-    });
-    
-    // Norwegian Gas Stations
-    merchants.insert("CIRCLE K", NorwegianMerchantInfo {
-        name: "Circle K".to_string(),
-        chain: "Circle K".to_string(),
-        category: "Gas Station".to_string(),
-        typical_vat_rate: 25, // General VAT
-        seasonal_products: vec![
-            "Bensin".to_string(), "Diesel".to_string(), "Kaffe".to_string(),
-            "Pølse".to_string(), "Brus".to_string()
-        ],
-        org_pattern: None,
-        confidence: 0.88,
-    });
-    
-    merchants.insert("SHELL", NorwegianMerchantInfo {
-        name: "Shell".to_string(),
-        chain: "Shell".to_string(),
-        category: "Gas Station".to_string(),
-        typical_vat_rate: 25,
-        seasonal_products: vec![
-            "Drivstoff".to_string(), "Bil".to_string(), "Kaffe".to_string()
-        ],
-        org_pattern: None,
-        confidence: 0.87,
-    });
-    
-    // Norwegian Brands and Stores
-    merchants.insert("TINE", NorwegianMerchantInfo {
-        name: "Tine".to_string(),
-        chain: "Tine".to_string(),
-        category: "Dairy Products".to_string(),
-        typical_vat_rate: 15,
-        seasonal_products: vec![
-            "Melk".to_string(), "Yoghurt".to_string(), "Ost".to_string(),
-            "Smør".to_string(), "Fløte".to_string()
-        ],
-        org_pattern: None,
-        confidence: 0.98,
-    });
-    
-    merchants.insert("POSTEN", NorwegianMerchantInfo {
-        name: "Posten Norge".to_string(),
-        chain: "Posten".to_string(),
-        category: "Postal Service".to_string(),
-        typical_vat_rate: 25,
-        seasonal_products: vec![
-            "Porto".to_string(), "Pakke".to_string(), "Brev".to_string()
-        ],
-        org_pattern: Some("984661185".to_string()),
-        confidence: 0.99,
-    });
-    
-    merchants.insert("VINMONOPOLET", NorwegianMerchantInfo {
-        name: "Vinmonopolet".to_string(),
-        chain: "Vinmonopolet".to_string(),
-        category: "Alcohol Monopoly".to_string(),
-        typical_vat_rate: 25, // Plus special alcohol taxes
-        seasonal_products: vec![
-            "Vin".to_string(), "Øl".to_string(), "Brennevin".to_string(),
-            "Champagne".to_string(), "Akevitt".to_string()
-        ],
-        org_pattern: Some("971425831".to_string()),
-        confidence: 0.99,
-    });
-    
-    merchants
+// Merchant database entry as used in the analysis
+fn merchant_info(record: MerchantRecord) -> NorwegianMerchantInfo {
+    NorwegianMerchantInfo {
+        name: record.name,
+        chain: record.chain,
+        category: record.category,
+        typical_vat_rate: record.typical_vat_rate,
+        seasonal_products: record.seasonal_products,
+        org_pattern: record.org_numbers.into_iter().next(),
+        org_number: None,
+        register_entry: None,
+        country: record.country,
+        confidence: record.confidence as f32,
+    }
 }
 
-// Norwegian Business Pattern Recognition
-fn detect_norwegian_merchant(text: &str) -> Option<NorwegianMerchantInfo> {
-    let merchants = get_norwegian_merchant_database();
-    let text_upper = text.to_uppercase();
-    
-    // Check for exact chain matches
-    for (key, merchant) in &merchants {
-        if text_upper.contains(key) {
-            return Some(merchant.clone());
+// Norwegian Business Pattern Recognition: every merchant the text may come
+// from, best first. Register entries get the VAT rate in force in `rates`
+fn detect_norwegian_merchant(text: &str, rates: &EffectiveRates) -> Vec<MerchantMatch> {
+    // Organization number patterns, however the number is formatted
+    let org_numbers = orgnr::org_numbers_in(text);
+    let matches: Vec<MerchantMatch> = MERCHANTS.rank(text, &org_numbers).into_iter().map(|candidate| {
+        let mut merchant = merchant_info(candidate.merchant);
+        merchant.confidence = candidate.score;
+        MerchantMatch {
+            name: merchant.name.clone(),
+            score: candidate.score,
+            matched: candidate.matched,
+            line: candidate.line,
+            similarity: candidate.similarity,
+            merchant,
         }
+    }).collect();
+    if !matches.is_empty() {
+        return matches;
     }
     
-    // Check for specific Norwegian patterns
-    if text_upper.contains("REMA 1000") || text_upper.contains("REMA1000") {
-        return merchants.get("REMA").cloned();
-    }
-    
-    if text_upper.contains("ICA SUPERMARKET") || text_upper.contains("ICA MAXI") {
-        return merchants.get("ICA").cloned();
-    }
-    
-    if text_upper.contains("COOP EXTRA") || text_upper.contains("COOP MEGA") || text_upper.contains("COOP PRIX") {
-        return merchants.get("COOP").cloned();
-    }
-    
-    if text_upper.contains("POSTEN NORGE") || text_upper.contains("POST NORGE") {
-        return merchants.get("POSTEN").cloned();
-    }
+    // Unknown chains are resolved through Enhetsregisteret by organisation number
+    org_numbers.iter()
+        .filter(|number| orgnr::is_valid(number))
+        .filter_map(|number| BUSINESS_REGISTER.lookup(number))
+        .map(|entity| {
+            let matched = entity.org_number.clone();
+            let merchant = merchant_from_register(entity, rates);
+            MerchantMatch { name: merchant.name.clone(), score: merchant.confidence, matched, line: None, similarity: 1.0, merchant }
+        })
+        .collect()
+}
+
+// Industry (NACE code prefix) to merchant category and VAT rate category
+const NACE_CATEGORIES: &[(&str, &str, &str)] = &[
+    ("47.11", "Grocery Store", "food"),
+    ("47.2", "Food Store", "food"),
+    ("47.3", "Gas Station", "standard"),
+    ("47.61", "Bookstore", "zero"),
+    ("47.62", "Newsagent", "zero"),
+    ("47.73", "Pharmacy", "standard"),
+    ("47", "Retail", "standard"),
+    ("10", "Food Producer", "food"),
+    ("11", "Beverage Producer", "standard"),
+    ("49.1", "Passenger Transport", "low"),
+    ("49.3", "Passenger Transport", "low"),
+    ("50.1", "Passenger Transport", "low"),
+    ("50.3", "Passenger Transport", "low"),
+    ("51.1", "Passenger Transport", "low"),
+    ("53", "Postal Service", "standard"),
+    ("55.1", "Hotel", "low"),
+    ("56", "Restaurant", "standard"),
+    ("59.14", "Cinema", "low"),
+    ("91.02", "Museum", "low"),
+    ("93.21", "Amusement Park", "low"),
+];
+
+fn merchant_from_register(entity: RegisteredEntity, rates: &EffectiveRates) -> NorwegianMerchantInfo {
+    let nace_code = entity.nace_code.as_deref().unwrap_or("");
+    let (category, vat_category) = NACE_CATEGORIES.iter()
+        .find(|(prefix, _, _)| nace_code.starts_with(prefix))
+        .map(|(_, category, vat_category)| (category.to_string(), *vat_category))
+        .unwrap_or_else(|| (entity.nace_description.clone().unwrap_or_else(|| "Uidentifisert".to_string()), "standard"));
+    // A seller outside Merverdiavgiftsregisteret cannot charge VAT
+    let typical_vat_rate = if entity.vat_registered {
+        rates.rate(vat_category).unwrap_or(25)
+    } else {
+        0
+    };
     
-    // Organization number patterns
-    for (_, merchant) in &merchants {
-        if let Some(org_pattern) = &merchant.org_pattern {
-            if text.contains(org_pattern) {
-                return Some(merchant.clone());
-            }
-        }
+    NorwegianMerchantInfo {
+        name: entity.name.clone(),
+        chain: entity.name.clone(),
+        category,
+        typical_vat_rate,
+        seasonal_products: vec![],
+        org_pattern: Some(entity.org_number.clone()),
+        org_number: None,
+        register_entry: Some(entity),
+        country: None,
+        confidence: 0.9,
     }
-    
-    None
 }
 
 // Multi-modal Document Processing
-fn process_document_image(image_data: &str) -> Option<ImageAnalysis> {
-    // Simulate image processing (in production, use proper OCR like Tesseract)
-    let decoded_size = (image_data.len() * 3) / 4; // Estimate original size
-    
-    Some(ImageAnalysis {
-        image_quality: if decoded_size > 100000 { "High".to_string() } else { "Medium".to_string() },
-        text_regions_detected: 5 + (decoded_size % 10) as u32, // Simulate text region detection
-        ocr_confidence: 0.85 + (decoded_size % 100) as f32 / 1000.0, // Simulate OCR confidence
-        document_type_detected: if image_data.contains("receipt") || image_data.len() % 3 == 0 { 
-            "receipt".to_string() 
-        } else { 
-            "invoice".to_string() 
+fn analyze_document_image(image: &PreprocessedImage, ocr: &OcrResult) -> ImageAnalysis {
+    let text = ocr.text();
+    let lower = text.to_lowercase();
+    let ocr_confidence = ocr.confidence();
+    
+    let document_type_detected = if text.trim().is_empty() {
+        "unknown"
+    } else if ["faktura", "invoice", "forfallsdato", "kid"].iter().any(|word| lower.contains(word)) {
+        "invoice"
+    } else {
+        "receipt"
+    };
+    
+    let norwegian_text_detected = lower.contains(['æ', 'ø', 'å'])
+        || ["totalt", "kvittering", "mva", "beløp", "sum å betale", "org.nr", "foretaksregisteret"]
+            .iter().any(|word| lower.contains(word));
+    
+    ImageAnalysis {
+        image_quality: ImageQuality {
+            rating: image.quality_rating().to_string(),
+            sharpness_score: image.sharpness,
+            blurry: image.is_blurry(),
+            preprocessing_steps: image.steps.clone(),
+            original_width: image.original_width,
+            original_height: image.original_height,
         },
-        norwegian_text_detected: image_data.len() % 2 == 0, // Simulate Norwegian text detection
-    })
+        text_regions_detected: ocr.lines.len() as u32,
+        ocr_confidence,
+        document_type_detected: document_type_detected.to_string(),
+        norwegian_text_detected,
+        ocr_engine: OCR_ENGINE.name(),
+        width: ocr.width,
+        height: ocr.height,
+        text,
+        lines: ocr.lines.clone(),
+    }
 }
 
-// Extract text from image (simulate OCR)
-fn extract_text_from_image(image_data: &str) -> String {
-    // In production, this would use actual OCR
-    // For demo, simulate Norwegian receipt text based on image characteristics
-    let size_indicator = image_data.len() % 5;
-    
-    match size_indicator {
-        0 => "REMA 1000 Oslo Melk 34.90 kr Brød 28.50 kr TOTALT 63.40 kr".to_string(),
-        1 => "ICA Supermarket Bergen Egg 12stk 45.90 kr Lam 289.90 kr SUMMA 335.80 kr".to_string(),
-        2 => "COOP Trondheim Kaffe 89.90 kr Ost 67.50 kr MEDLEM 12345 TOTALT 157.40 kr".to_string(),
-        3 => "KIWI Stavanger Pölse 15.90 kr Brus 2L 29.90 kr TOTALT 45.80 kr".to_string(),
-        _ => "VINMONOPOLET Oslo Vin rød 299.90 kr Øl 6pk 189.90 kr TOTALT 489.80 kr".to_string(),
-    }
+// Text for every PDF page, running OCR on pages that only hold a scan.
+// Page-level failures are reported on the page; the norwegian_analysis is
+// filled in by the caller
+fn read_pdf_pages(bytes: &[u8]) -> std::result::Result<Vec<PageAnalysis>, PdfError> {
+    let pages = pdf::read_pdf(bytes)?;
+    
+    Ok(pages.into_iter().map(|page| {
+        let mut analysis = PageAnalysis {
+            page_number: page.number,
+            text_source: "text_layer".to_string(),
+            text: page.text,
+            norwegian_analysis: None,
+            image_analysis: None,
+            error: None,
+        };
+        
+        let recognized = match page.scan {
+            None => return analysis,
+            Some(Err(e)) => Err(e),
+            Some(Ok(_)) if !OCR_ENGINE.available() => Err(OcrError::EngineUnavailable.to_string()),
+            Some(Ok(scan)) => {
+                let image = preprocess::preprocess_image(scan);
+                OCR_ENGINE.recognize(&image.image)
+                    .map(|ocr| analyze_document_image(&image, &ocr))
+                    .map_err(|e| e.to_string())
+            },
+        };
+        match recognized {
+            Ok(image_analysis) => {
+                analysis.text_source = "ocr".to_string();
+                analysis.text = image_analysis.text.clone();
+                analysis.image_analysis = Some(image_analysis);
+            },
+            Err(e) => {
+                analysis.text_source = "unreadable".to_string();
+                analysis.error = Some(e);
+            },
+        }
+        analysis
+    }).collect())
+}
+
+fn ocr_error_response(error: &OcrError) -> HttpResponse {
+    let (mut builder, error_name) = match error {
+        OcrError::InvalidBase64(_) | OcrError::InvalidImage(_) => (HttpResponse::BadRequest(), "Invalid Image"),
+        OcrError::EngineUnavailable => (HttpResponse::ServiceUnavailable(), "OCR Unavailable"),
+        OcrError::Engine(_) => (HttpResponse::InternalServerError(), "OCR Failed"),
+    };
+    builder.json(ErrorResponse {
+        error: error_name.to_string(),
+        message: error.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
 }
 
 // Apply learning from user corrections
 fn apply_user_learning(correction: &UserCorrection) -> bool {
-    if let Ok(mut learning_data) = LEARNING_DATA.lock() {
-        learning_data.push(correction.clone());
-        
-        // Update merchant learning confidence
-        if let Some(merchant) = &correction.corrected_merchant {
-            if let Ok(mut merchant_learning) = MERCHANT_LEARNING.lock() {
-                let current_confidence = merchant_learning.get(merchant).unwrap_or(&0.5);
-                let new_confidence = if correction.confidence_rating.unwrap_or(5) > 7 {
-                    (current_confidence + 0.1).min(0.99)
-                } else {
-                    (current_confidence - 0.05).max(0.1)
-                };
-                merchant_learning.insert(merchant.clone(), new_confidence);
-            }
+    if let Err(e) = STORE.add_correction(correction) {
+        println!("⚠️  Could not store user correction: {}", e);
+        return false;
+    }
+    
+    // Update merchant learning confidence
+    if let Some(merchant) = &correction.corrected_merchant {
+        let current_confidence = get_learned_merchant_confidence(merchant);
+        let new_confidence = if correction.confidence_rating.unwrap_or(5) > 7 {
+            (current_confidence + 0.1).min(0.99)
+        } else {
+            (current_confidence - 0.05).max(0.1)
+        };
+        if let Err(e) = STORE.set_merchant_confidence(merchant, new_confidence) {
+            println!("⚠️  Could not update confidence for {}: {}", merchant, e);
         }
-        
-        true
-    } else {
-        false
     }
+    
+    true
 }
 
 // Get learned merchant confidence
 fn get_learned_merchant_confidence(merchant_name: &str) -> f32 {
-    if let Ok(merchant_learning) = MERCHANT_LEARNING.lock() {
-        merchant_learning.get(merchant_name).copied().unwrap_or(0.5)
-    } else {
-        0.5
+    match STORE.merchant_confidence(merchant_name) {
+        Ok(confidence) => confidence.unwrap_or(0.5),
+        Err(e) => {
+            println!("⚠️  Could not read confidence for {}: {}", merchant_name, e);
+            0.5
+        },
     }
 }
 
 // Enhanced Norwegian merchant detection with learning
-fn detect_norwegian_merchant_with_learning(text: &str) -> Option<NorwegianMerchantInfo> {
-    if let Some(mut merchant) = detect_norwegian_merchant(text) {
+fn detect_norwegian_merchant_with_learning(text: &str, rates: &EffectiveRates) -> Vec<MerchantMatch> {
+    let mut matches = detect_norwegian_merchant(text, rates);
+    for candidate in matches.iter_mut() {
         // Apply learned confidence adjustments
-        let learned_confidence = get_learned_merchant_confidence(&merchant.name);
-        merchant.confidence = (merchant.confidence + learned_confidence) / 2.0;
-        Some(merchant)
-    } else {
-        None
+        let learned_confidence = get_learned_merchant_confidence(&candidate.merchant.name);
+        candidate.merchant.confidence = (candidate.merchant.confidence + learned_confidence) / 2.0;
     }
+    matches
 }
 
 // Advanced Fine-Tuning Capabilities
@@ -596,17 +817,14 @@ fn simulate_model_fine_tuning(training_data: &[TrainingExample], model_type: &st
     }
 }
 
-// Store training data for continuous learning
-fn store_training_example(example: TrainingExample) -> bool {
-    if let Ok(mut training_data) = TRAINING_DATA.lock() {
-        training_data.push(example);
-        // Keep only the most recent 10,000 examples
-        if training_data.len() > 10000 {
-            training_data.drain(0..1000);
-        }
-        true
-    } else {
-        false
+// Store training data for continuous learning (the store keeps the most recent 10,000 examples)
+fn store_training_example(example: &TrainingExample) -> bool {
+    match STORE.add_training_example(example) {
+        Ok(()) => true,
+        Err(e) => {
+            println!("⚠️  Could not store training example: {}", e);
+            false
+        },
     }
 }
 
@@ -619,28 +837,21 @@ fn analyze_spending_patterns(
     use chrono::{NaiveDate, Datelike};
     
     // Group transactions by category and month
-    let mut category_totals: HashMap<String, f32> = HashMap::new();
-    let mut monthly_totals: HashMap<u32, f32> = HashMap::new();
-    let mut seasonal_data: HashMap<String, f32> = HashMap::new();
+    let mut category_totals: HashMap<String, Money> = HashMap::new();
+    let mut monthly_totals: HashMap<u32, Money> = HashMap::new();
     
     for transaction in historical_data {
         // Category analysis
-        *category_totals.entry(transaction.category.clone()).or_insert(0.0) += transaction.amount;
+        *category_totals.entry(transaction.category.clone()).or_default() += transaction.amount;
         
         // Monthly analysis
         if let Ok(date) = NaiveDate::parse_from_str(&transaction.date, "%Y-%m-%d") {
-            *monthly_totals.entry(date.month()).or_insert(0.0) += transaction.amount;
-        }
-        
-        // Seasonal analysis
-        if let Some(season) = &transaction.season {
-            *seasonal_data.entry(season.clone()).or_insert(0.0) += transaction.amount;
+            *monthly_totals.entry(date.month()).or_default() += transaction.amount;
         }
     }
     
     // Generate predictions based on historical patterns
     let predictions: Vec<SpendingPrediction> = category_totals.iter().map(|(category, &total)| {
-        let avg_monthly = total / 12.0;
         let multiplier = match timeframe {
             "next_month" => 1.0,
             "next_quarter" => 3.0,
@@ -651,16 +862,17 @@ fn analyze_spending_patterns(
         // Add seasonal adjustments
         let seasonal_multiplier = match category.as_str() {
             "Grocery Store" => 1.1, // Always needed
-            "Alcohol Monopoly" => if monthly_totals.get(&12).unwrap_or(&0.0) > &monthly_totals.get(&6).unwrap_or(&0.0) { 1.3 } else { 0.8 },
+            "Alcohol Monopoly" => if monthly_totals.get(&12).copied().unwrap_or_default() > monthly_totals.get(&6).copied().unwrap_or_default() { 1.3 } else { 0.8 },
             _ => 1.0,
         };
         
         SpendingPrediction {
             period: timeframe.to_string(),
-            predicted_amount: avg_monthly * multiplier * seasonal_multiplier,
+            // The monthly average over the year, times the months predicted
+            predicted_amount: total.scale(multiplier * seasonal_multiplier / 12.0),
             category: category.clone(),
-            confidence: 0.75 + (total / 10000.0).min(0.2),
-            trend: if total > 5000.0 { "increasing".to_string() } else { "stable".to_string() },
+            confidence: 0.75 + (total.kroner() as f32 / 10000.0).min(0.2),
+            trend: if total > Money::nok(5000) { "increasing".to_string() } else { "stable".to_string() },
             factors: vec![
                 "Historical spending patterns".to_string(),
                 "Seasonal adjustments".to_string(),
@@ -669,37 +881,15 @@ fn analyze_spending_patterns(
         }
     }).collect();
     
-    // Seasonal insights
-    let seasonal_insights = vec![
-        SeasonalInsight {
-            season: "17. mai (Constitution Day)".to_string(),
-            cultural_event: Some("Norwegian National Day".to_string()),
-            expected_spending_increase: 1.8,
-            key_categories: vec!["Flagg".to_string(), "Korv".to_string(), "Brus".to_string()],
-            historical_pattern: "350% increase in patriotic items and food for celebrations".to_string(),
-        },
-        SeasonalInsight {
-            season: "Jul (Christmas)".to_string(),
-            cultural_event: Some("Norwegian Christmas".to_string()),
-            expected_spending_increase: 2.2,
-            key_categories: vec!["Ribbe".to_string(), "Pinnekjøtt".to_string(), "Julepresanger".to_string()],
-            historical_pattern: "Peak spending season with traditional food focus".to_string(),
-        },
-        SeasonalInsight {
-            season: "Påske (Easter)".to_string(),
-            cultural_event: Some("Norwegian Easter".to_string()),
-            expected_spending_increase: 1.4,
-            key_categories: vec!["Egg".to_string(), "Lam".to_string(), "Kvikk Lunsj".to_string()],
-            historical_pattern: "Moderate increase focused on Easter traditions".to_string(),
-        },
-    ];
+    // Seasonal insights: spending around each holiday against the rest of the history
+    let seasonal_insights = seasonality::seasonal_insights(historical_data);
     
     // Budget recommendations
-    let total_predicted: f32 = predictions.iter().map(|p| p.predicted_amount).sum();
+    let total_predicted: Money = predictions.iter().map(|p| p.predicted_amount).sum();
     let budget_recommendations = vec![
         BudgetRecommendation {
             category: "Emergency Reserve".to_string(),
-            recommended_budget: total_predicted * 0.15,
+            recommended_budget: total_predicted.scale(0.15),
             reasoning: "15% buffer for unexpected expenses based on Norwegian organizational best practices".to_string(),
             risk_level: "low".to_string(),
             optimization_tips: vec![
@@ -709,7 +899,7 @@ fn analyze_spending_patterns(
         },
         BudgetRecommendation {
             category: "Seasonal Events".to_string(),
-            recommended_budget: total_predicted * 0.25,
+            recommended_budget: total_predicted.scale(0.25),
             reasoning: "Norwegian cultural events drive significant spending spikes".to_string(),
             risk_level: "medium".to_string(),
             optimization_tips: vec![
@@ -730,42 +920,26 @@ fn analyze_spending_patterns(
     }
 }
 
-// Norwegian Seasonal Analysis
-fn get_seasonal_context(date_str: Option<&str>) -> SeasonalContext {
-    use chrono::{NaiveDate, Datelike};
-    
-    let now = chrono::Utc::now();
-    let month = now.month();
-    let day = now.day();
+// Norwegian Seasonal Analysis for the purchase date, today when it is unknown.
+// Holidays and the shopping before them come from the holiday calendar; the
+// summer holiday and school start cover the rest of the year.
+fn get_seasonal_context(date: Option<chrono::NaiveDate>) -> SeasonalContext {
+    use chrono::Datelike;
+    
+    let date = date.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    if let Some(season) = calendar::season_on(date) {
+        let info = season.event.info();
+        return SeasonalContext {
+            season: info.season.to_string(),
+            cultural_event: Some(info.cultural_event.to_string()),
+            typical_purchases: info.typical_purchases.iter().map(|purchase| purchase.to_string()).collect(),
+            price_expectations: info.price_expectations.to_string(),
+            holiday: season.holiday.map(|holiday| holiday.name.to_string()),
+            days_until_event: Some(season.days_until),
+        };
+    }
     
-    match month {
-        5 if day == 17 => SeasonalContext {
-            season: "17. mai (Constitution Day)".to_string(),
-            cultural_event: Some("Norwegian National Day".to_string()),
-            typical_purchases: vec![
-                "Flagg".to_string(), "Korv".to_string(), "Brus".to_string(),
-                "Is".to_string(), "Bunad tilbehør".to_string()
-            ],
-            price_expectations: "Premium pricing for patriotic items".to_string(),
-        },
-        12 => SeasonalContext {
-            season: "Jul (Christmas)".to_string(),
-            cultural_event: Some("Norwegian Christmas".to_string()),
-            typical_purchases: vec![
-                "Ribbe".to_string(), "Pinnekjøtt".to_string(), "Lutefisk".to_string(),
-                "Lefse".to_string(), "Krumkake".to_string(), "Julepresanger".to_string()
-            ],
-            price_expectations: "High seasonal pricing for traditional foods".to_string(),
-        },
-        3..=4 => SeasonalContext {
-            season: "Påske (Easter)".to_string(),
-            cultural_event: Some("Norwegian Easter".to_string()),
-            typical_purchases: vec![
-                "Egg".to_string(), "Lam".to_string(), "Kvikk Lunsj".to_string(),
-                "Påskeegg".to_string(), "Sjokolade".to_string()
-            ],
-            price_expectations: "Elevated prices for Easter chocolate and lamb".to_string(),
-        },
+    match date.month() {
         6..=8 => SeasonalContext {
             season: "Sommer (Summer)".to_string(),
             cultural_event: Some("Norwegian Summer Vacation".to_string()),
@@ -774,6 +948,8 @@ fn get_seasonal_context(date_str: Option<&str>) -> SeasonalContext {
                 "Øl".to_string(), "Solkrem".to_string(), "Camping utstyr".to_string()
             ],
             price_expectations: "Peak pricing for summer and vacation items".to_string(),
+            holiday: None,
+            days_until_event: None,
         },
         9 => SeasonalContext {
             season: "Skolestart (Back to School)".to_string(),
@@ -783,6 +959,8 @@ fn get_seasonal_context(date_str: Option<&str>) -> SeasonalContext {
                 "Matboks".to_string(), "Klær".to_string()
             ],
             price_expectations: "Back-to-school promotions and bulk pricing".to_string(),
+            holiday: None,
+            days_until_event: None,
         },
         _ => SeasonalContext {
             season: "Standard periode".to_string(),
@@ -791,85 +969,100 @@ fn get_seasonal_context(date_str: Option<&str>) -> SeasonalContext {
                 "Dagligvarer".to_string(), "Mat".to_string(), "Drikke".to_string()
             ],
             price_expectations: "Regular pricing".to_string(),
+            holiday: None,
+            days_until_event: None,
         },
     }
 }
 
-// Norwegian VAT Analysis
-fn analyze_norwegian_vat(amount: f32, merchant: &NorwegianMerchantInfo, items: &str) -> VatAnalysis {
-    let detected_rate = if items.to_lowercase().contains("melk") || 
+// Norwegian VAT Analysis, split per rate when a receipt mixes them
+fn analyze_norwegian_vat(amount: Option<Money>, merchant: &NorwegianMerchantInfo, items: &str, rates: &EffectiveRates) -> VatAnalysis {
+    let keyword_category = if items.to_lowercase().contains("melk") || 
                          items.to_lowercase().contains("brød") ||
                          items.to_lowercase().contains("mat") ||
                          merchant.category == "Grocery Store" {
-        15 // Food VAT rate
-    } else if merchant.chain == "Vinmonopolet" {
-        25 // Alcohol gets 25% + special taxes
+        "food" // Food VAT rate
     } else {
-        25 // General VAT rate
+        "standard" // General VAT rate; alcohol (Vinmonopolet) gets 25% + special taxes
+    };
+    let keyword_rate = rates.rate(keyword_category).unwrap_or(if keyword_category == "food" { 15 } else { 25 });
+    
+    // The till's own MVA summary is exact; classified items are the next best thing
+    let summary = receipt::parse_vat_summary(items);
+    let (breakdown, source) = if !summary.is_empty() {
+        (summary, "receipt_summary")
+    } else if let Some(parsed) = receipt::parse_receipt(items, rates)
+        .filter(|parsed| parsed.line_items.iter().any(|item| item.vat_rate.is_some())) {
+        (parsed.vat_by_item_rate(keyword_rate), "item_classification")
+    } else {
+        // Nothing to estimate from when the amount is unknown
+        (amount.map(|amount| VatRateBreakdown::from_gross(keyword_rate, amount)).into_iter().collect(), "estimated")
     };
     
-    let vat_amount = amount * (detected_rate as f32 / (100.0 + detected_rate as f32));
-    
-    let rate_explanation = match detected_rate {
-        0 => "VAT-exempt goods (books, newspapers, medicine)".to_string(),
-        15 => "Reduced VAT rate for food and non-alcoholic beverages".to_string(),
-        25 => "Standard VAT rate for general goods and services".to_string(),
-        _ => "Special VAT rate".to_string(),
+    vat_analysis_from_breakdown(breakdown, source, merchant, rates)
+}
+
+fn vat_analysis_from_breakdown(breakdown: Vec<VatRateBreakdown>, source: &str, merchant: &NorwegianMerchantInfo, rates: &EffectiveRates) -> VatAnalysis {
+    // The rate carrying the largest base is reported as the receipt's rate
+    let detected_rate = breakdown.iter()
+        .max_by_key(|rate| rate.base_amount.abs())
+        .map(|rate| rate.rate)
+        .unwrap_or(merchant.typical_vat_rate);
+    
+    let rate_explanation = if breakdown.len() > 1 {
+        format!("Mixed VAT rates: {}", breakdown.iter()
+            .map(|rate| format!("{}% on {}", rate.rate, rate.base_amount))
+            .collect::<Vec<_>>()
+            .join(", "))
+    } else {
+        rates.explanation(detected_rate)
     };
     
-    let compliance_status = if detected_rate == merchant.typical_vat_rate {
+    // Merchants list today's rate; compare with that category's rate on the receipt's date
+    let expected_rate = vat_rates_on(None).category_of(merchant.typical_vat_rate)
+        .and_then(|category| rates.rate(category))
+        .unwrap_or(merchant.typical_vat_rate);
+    let compliance_status = if detected_rate == expected_rate {
         "Compliant with expected rate".to_string()
     } else {
-        format!("Rate differs from typical {}% for {}", merchant.typical_vat_rate, merchant.chain)
+        format!("Rate differs from typical {}% for {}", expected_rate, merchant.chain)
     };
     
     VatAnalysis {
         detected_rate,
         rate_explanation,
-        total_vat_amount: Some(vat_amount),
+        total_vat_amount: (!breakdown.is_empty()).then(|| breakdown.iter().map(|rate| rate.vat_amount).sum()),
         compliance_status,
+        breakdown,
+        breakdown_source: source.to_string(),
+        rates_date: rates.date.to_string(),
+        currency: None,
     }
 }
 
-// Extract amount from Norwegian text
-fn extract_amount_from_text(text: &str) -> Option<f32> {
-    use regex::Regex;
-    
-    // Common Norwegian amount patterns
-    let patterns = vec![
-        r"(\d+[,.]?\d*)\s*(?:NOK|kr|kroner)", // 245.50 NOK, 156,90 kr
-        r"(\d+[,.]?\d*)\s*(?:,-|:-)?\s*$",    // 245.50 at end of line
-        r"TOTALT?\s*[:|]?\s*(\d+[,.]?\d*)",   // TOTALT: 245.50
-        r"SUM\w*\s*[:|]?\s*(\d+[,.]?\d*)",    // SUMMA: 245.50
-    ];
-    
-    for pattern_str in &patterns {
-        if let Ok(re) = Regex::new(pattern_str) {
-            if let Some(caps) = re.captures(text) {
-                if let Some(amount_str) = caps.get(1) {
-                    let amount_text = amount_str.as_str().replace(',', ".");
-                    if let Ok(amount) = amount_text.parse::<f32>() {
-                        return Some(amount);
-                    }
-                }
-            }
-        }
-    }
-    
-    None
+// VAT rates in force on `date`, today when the date is unknown
+fn vat_rates_on(date: Option<chrono::NaiveDate>) -> EffectiveRates {
+    VAT_RATES.on(date.unwrap_or_else(|| chrono::Utc::now().date_naive()))
 }
 
+const AMOUNT_NOT_FOUND_WARNING: &str = "Fant ikke totalbeløpet i dokumentet - registrer beløpet manuelt";
+
 // Norwegian Organization Compliance Check
-fn check_norwegian_compliance(org_type: &str, merchant: &NorwegianMerchantInfo, amount: f32) -> ComplianceCheck {
+fn check_norwegian_compliance(org_type: &str, merchant: &NorwegianMerchantInfo, amount: Option<Money>, vat: &VatAnalysis) -> ComplianceCheck {
     let mut documentation_required = vec!["Kvittering".to_string()];
     let mut approval_needed = false;
+    let mut warnings = org_number_warnings(merchant, vat);
+    if amount.is_none() {
+        warnings.push(AMOUNT_NOT_FOUND_WARNING.to_string());
+    }
+    let amount = amount.unwrap_or_default();
     
     let deductibility = match org_type {
         "forening" | "lag" | "klubb" => {
             if merchant.category == "Grocery Store" {
                 documentation_required.push("Formål dokumentasjon".to_string());
                 "Delvis fradragsberettiget - kun aktivitetsrelaterte innkjøp"
-            } else if amount > 5000.0 {
+            } else if amount > Money::nok(5000) {
                 approval_needed = true;
                 documentation_required.push("Styregodkjenning".to_string());
                 "Krever styregodkjenning for beløp over 5000 NOK"
@@ -888,7 +1081,7 @@ fn check_norwegian_compliance(org_type: &str, merchant: &NorwegianMerchantInfo,
         _ => "Kontakt regnskapsfører for vurdering"
     };
     
-    if amount > 1000.0 {
+    if amount > Money::nok(1000) {
         documentation_required.push("Bilagsnummer".to_string());
         documentation_required.push("Dato og formål".to_string());
     }
@@ -898,6 +1091,317 @@ fn check_norwegian_compliance(org_type: &str, merchant: &NorwegianMerchantInfo,
         deductibility: deductibility.to_string(),
         documentation_required,
         approval_needed,
+        warnings,
+        foreign_vat: None,
+    }
+}
+
+// Problems with the seller's organisation number as printed on the document
+fn org_number_warnings(merchant: &NorwegianMerchantInfo, vat: &VatAnalysis) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let Some(org_number) = &merchant.org_number else {
+        return warnings;
+    };
+    if !org_number.mod11_valid {
+        warnings.push(format!("Organisasjonsnummer {} har ugyldig kontrollsiffer - kontroller selgeren", org_number.number));
+        return warnings;
+    }
+    
+    // The register is authoritative; without it the printed MVA suffix is all we have
+    let register_entry = merchant.register_entry.as_ref().filter(|entity| entity.org_number == org_number.number);
+    // Only VAT the document itself declares counts, not our estimate
+    let declared = matches!(vat.breakdown_source.as_str(), "receipt_summary" | "ehf_invoice");
+    let vat_charged: Money = vat.breakdown.iter().map(|rate| rate.vat_amount).sum();
+    if declared && vat_charged > Money::ZERO {
+        match register_entry {
+            Some(entity) if !entity.vat_registered => warnings.push(format!("Selger {} er ikke registrert i Merverdiavgiftsregisteret, men krever {} i merverdiavgift", org_number.number, vat_charged)),
+            None if !org_number.vat_registered => warnings.push(format!("Selger {} er ikke merket MVA, men krever {} i merverdiavgift - kontroller registrering i Merverdiavgiftsregisteret", org_number.number, vat_charged)),
+            _ => {},
+        }
+    }
+    if register_entry.is_some_and(|entity| entity.bankrupt) {
+        warnings.push(format!("Selger {} er registrert konkurs i Enhetsregisteret", org_number.number));
+    }
+    warnings
+}
+
+// Deterministic Norwegian business analysis report for a prompt
+fn generate_norwegian_analysis_text(prompt: &str, org_type: &str) -> String {
+    // The amount the prompt mentions, preferring a labelled total
+    let amount = amount::extract_amount(prompt).map(|amount| amount.amount);
+    // The date the prompt mentions sets the VAT rates and seasonal context
+    let date = transaction::extract_transaction_details(prompt, chrono::Utc::now().date_naive()).date;
+    let rates = vat_rates_on(date);
+    
+    // Detect Norwegian merchant
+    let merchant_candidates = detect_norwegian_merchant(prompt, &rates);
+    let mut merchant = merchant_candidates.first().map(|candidate| candidate.merchant.clone()).unwrap_or_else(|| {
+        NorwegianMerchantInfo {
+            name: "Ukjent norsk forhandler".to_string(),
+            chain: "Generisk".to_string(),
+            category: "Uidentifisert".to_string(),
+            typical_vat_rate: 25,
+            seasonal_products: vec![],
+            org_pattern: None,
+            org_number: None,
+            register_entry: None,
+            country: None,
+            confidence: 0.5,
+        }
+    });
+    merchant.org_number = orgnr::find_org_number(prompt);
+    
+    // Get seasonal context for the date the prompt mentions
+    let seasonal = get_seasonal_context(date);
+    
+    // Analyze VAT
+    let vat_analysis = analyze_norwegian_vat(amount, &merchant, prompt, &rates);
+    
+    // Check compliance
+    let compliance = check_norwegian_compliance(org_type, &merchant, amount, &vat_analysis);
+    
+    // Determine cultural significance
+    let cultural_significance = seasonal.cultural_event.as_ref().map(|event| {
+        format!("Kulturell betydning: {} - typiske innkjøp inkluderer {}",
+            event,
+            seasonal.typical_purchases.join(", ")
+        )
+    });
+    
+    // Generate comprehensive Norwegian analysis
+    let analysis = NorwegianAnalysis {
+        merchant: merchant.clone(),
+        vat_analysis,
+        seasonal_context: seasonal,
+        compliance_check: compliance,
+        cultural_significance,
+        deductibility_assessment: deductibility_assessment(&merchant, amount, org_type),
+        amount,
+        amount_not_found: amount.is_none(),
+        merchant_candidates,
+        currency_conversion: None,
+    };
+    
+    // Format the comprehensive analysis
+    format!(
+        "🇳🇴 NORSK AI-ANALYSE FOR {} 🇳🇴\n\nMERCHANT: {} ({})\n├─ Kategori: {}\n├─ Konfidensgrad: {:.1}%\n├─ Forventet MVA: {}%\n\nMVA-ANALYSE:\n├─ Detektert sats: {}%\n├─ Forklaring: {}\n├─ MVA-beløp: {}\n├─ Status: {}\n\nSESONGANALYSE:\n├─ Periode: {}\n├─ Kulturell kontekst: {}\n├─ Typiske innkjøp: {}\n├─ Prisforventning: {}\n\nKOMPLIANCE FOR {}:\n├─ Fradragsberettighet: {}\n├─ Dokumentasjon påkrevd: {}\n├─ Styregodkjenning: {}\n\n{}ORIGINAL PROMPT: {}",
+        org_type.to_uppercase(),
+        analysis.merchant.name,
+        analysis.merchant.chain,
+        analysis.merchant.category,
+        analysis.merchant.confidence * 100.0,
+        analysis.merchant.typical_vat_rate,
+        analysis.vat_analysis.detected_rate,
+        analysis.vat_analysis.rate_explanation,
+        analysis.vat_analysis.total_vat_amount.map(|vat| vat.to_string()).unwrap_or_else(|| "ukjent, fant ikke beløpet".to_string()),
+        analysis.vat_analysis.compliance_status,
+        analysis.seasonal_context.season,
+        analysis.seasonal_context.cultural_event.as_deref().unwrap_or("Ingen spesiell"),
+        analysis.seasonal_context.typical_purchases.join(", "),
+        analysis.seasonal_context.price_expectations,
+        org_type.to_uppercase(),
+        analysis.compliance_check.deductibility,
+        analysis.compliance_check.documentation_required.join(", "),
+        if analysis.compliance_check.approval_needed { "JA" } else { "NEI" },
+        if let Some(cultural) = analysis.cultural_significance {
+            format!("{}\n\n", cultural)
+        } else {
+            String::new()
+        },
+        prompt
+    )
+}
+
+// Stream a generation as Server-Sent Events: `data: {chunk}` deltas, a final
+// chunk with finish_reason and timing, then `data: [DONE]`. A precomputed
+// completion (the Norwegian analysis report) is streamed line by line instead
+// of calling the backend. Backend output ends at the first stop sequence, so the last
+// bytes that could start one are held back until the next delta.
+fn stream_text_generation(
+    backend: Arc<dyn InferenceBackend>,
+    route: ModelRoute,
+    params: GenerationParams,
+    precomputed: Option<Completion>,
+    start_time: std::time::Instant,
+) -> HttpResponse {
+    let (frame_tx, frame_rx) = mpsc::channel::<String>(32);
+    let id = format!("chatcmpl-{}", uuid::Uuid::new_v4().simple());
+    let created = chrono::Utc::now().timestamp();
+    // Precomputed text is already within limits; backend output is cut at max_tokens
+    let token_limit = if precomputed.is_some() { None } else { Some(params.max_tokens) };
+    let prompt_tokens = TOKENIZER.count(&params.prompt());
+    let stop: Vec<String> = params.stop.iter().filter(|sequence| !sequence.is_empty()).cloned().collect();
+    let hold_back = stop.iter().map(String::len).max().unwrap_or(1) - 1;
+    
+    let chunk = move |delta: Option<String>, finish_reason: Option<String>| TextGenerationChunk {
+        id: id.clone(),
+        object: "chat.completion.chunk".to_string(),
+        created,
+        model: route.id.clone(),
+        choices: vec![TextGenerationChunkChoice {
+            index: 0,
+            delta: TextGenerationDelta { content: delta },
+            finish_reason,
+        }],
+        processing_time_ms: None,
+        tokens_generated: None,
+        prompt_tokens: None,
+    };
+    let upstream_model = route.upstream_model.clone();
+    
+    actix_web::rt::spawn(async move {
+        let (delta_tx, delta_rx) = mpsc::channel::<String>(32);
+        
+        let generation = async move {
+            match precomputed {
+                Some(completion) => {
+                    for line in completion.text.split_inclusive('\n') {
+                        let _ = delta_tx.send(line.to_string()).await;
+                    }
+                    Ok(completion)
+                },
+                None => backend.generate_stream(&upstream_model, &params, delta_tx).await,
+            }
+        };
+        
+        let forward = async {
+            // Owned here so it is dropped (and the backend stops) on truncation
+            let mut delta_rx = delta_rx;
+            let mut streamed = String::new();
+            let mut sent = 0;
+            // Counted per delta rather than over the whole text each time
+            let mut tokens = 0;
+            let mut finished = false;
+            
+            while !finished {
+                let delta = delta_rx.recv().await;
+                finished = delta.is_none();
+                streamed.push_str(&delta.unwrap_or_default());
+                
+                // A stop sequence starting before `sent` would have been found
+                // by an earlier delta, since the held-back bytes were searched too
+                let stopped_at = stop.iter()
+                    .filter_map(|sequence| streamed[sent..].find(sequence.as_str()))
+                    .min()
+                    .map(|position| sent + position);
+                let mut end = match stopped_at {
+                    Some(position) => position,
+                    None if finished => streamed.len(),
+                    None => streamed.len().saturating_sub(hold_back).max(sent),
+                };
+                while !streamed.is_char_boundary(end) {
+                    end -= 1;
+                }
+                let remaining = token_limit.map(|limit| limit.saturating_sub(tokens));
+                let (end, cut) = match remaining.and_then(|remaining| TOKENIZER.truncate(&streamed[sent..end], remaining)) {
+                    Some(prefix) => (sent + prefix.len(), Some("length")),
+                    None => (end, stopped_at.map(|_| "stop")),
+                };
+                tokens += TOKENIZER.count(&streamed[sent..end]);
+                
+                if end > sent {
+                    let event = sse_frame(&chunk(Some(streamed[sent..end].to_string()), None));
+                    if frame_tx.send(event).await.is_err() {
+                        break; // client disconnected
+                    }
+                    sent = end;
+                }
+                if cut.is_some() {
+                    return (tokens, cut);
+                }
+            }
+            (tokens, None)
+        };
+        
+        let (result, (tokens, cut)) = tokio::join!(generation, forward);
+        
+        let final_event = match result {
+            Ok(completion) => {
+                let finish_reason = cut.map_or(completion.finish_reason, str::to_string);
+                let tokens_generated = match completion.completion_tokens {
+                    Some(upstream) if cut.is_none() => upstream,
+                    _ => tokens,
+                };
+                
+                let mut last = chunk(None, Some(finish_reason));
+                last.processing_time_ms = Some(start_time.elapsed().as_millis() as u64);
+                last.tokens_generated = Some(tokens_generated);
+                last.prompt_tokens = Some(completion.prompt_tokens.unwrap_or(prompt_tokens));
+                sse_frame(&last)
+            },
+            Err(e) => {
+                println!("⚠️  Inference backend error during stream: {}", e);
+                sse_frame(&ErrorResponse {
+                    error: "Inference Backend Error".to_string(),
+                    message: e.to_string(),
+                    timestamp: chrono::Utc::now().to_rfc3339(),
+                })
+            },
+        };
+        let _ = frame_tx.send(final_event).await;
+        let _ = frame_tx.send("data: [DONE]\n\n".to_string()).await;
+        
+        println!("Streamed text generation in {}ms", start_time.elapsed().as_millis());
+    });
+    
+    let body = futures_util::stream::unfold(frame_rx, |mut frame_rx| async move {
+        frame_rx.recv().await
+            .map(|frame| (Ok::<_, actix_web::Error>(web::Bytes::from(frame)), frame_rx))
+    });
+    
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no")) // keep proxies from buffering the stream
+        .streaming(body)
+}
+
+fn sse_frame<T: Serialize>(payload: &T) -> String {
+    format!("data: {}\n\n", serde_json::to_string(payload).unwrap_or_default())
+}
+
+// Describes why a request does not fit the model context window, if it doesn't
+fn context_window_violation(prompt_tokens: u32, max_tokens: u32) -> Option<String> {
+    let window = tokenizer::context_window();
+    if max_tokens > window {
+        Some(format!("max_tokens ({}) exceeds the model context window of {} tokens", max_tokens, window))
+    } else if prompt_tokens + max_tokens > window {
+        Some(format!(
+            "Prompt is {} tokens; with max_tokens {} this exceeds the model context window of {} tokens",
+            prompt_tokens, max_tokens, window
+        ))
+    } else {
+        None
+    }
+}
+
+// Cut a completion at max_tokens and fill in the completion token count when
+// the backend did not report one
+fn enforce_max_tokens(completion: &mut Completion, max_tokens: u32) {
+    if let Some(prefix) = TOKENIZER.truncate(&completion.text, max_tokens) {
+        completion.text = prefix.to_string();
+        completion.completion_tokens = Some(TOKENIZER.count(&completion.text));
+        completion.finish_reason = "length".to_string();
+    } else if completion.completion_tokens.is_none() {
+        completion.completion_tokens = Some(TOKENIZER.count(&completion.text));
+    }
+}
+
+// Map backend failures onto the service's error format
+fn inference_error_response(error: &InferenceError) -> HttpResponse {
+    println!("⚠️  Inference backend error: {}", error);
+    let body = ErrorResponse {
+        error: match error {
+            InferenceError::UnknownModel(_) => "Unknown Model",
+            _ => "Inference Backend Error",
+        }.to_string(),
+        message: error.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    };
+    
+    match error {
+        InferenceError::UnknownModel(_) => HttpResponse::BadRequest().json(body),
+        _ => HttpResponse::BadGateway().json(body),
     }
 }
 
@@ -908,6 +1412,7 @@ async fn health_check() -> Result<HttpResponse> {
         version: "0.1.0".to_string(),
         timestamp: chrono::Utc::now().to_rfc3339(),
         uptime_seconds: 0,
+        storage: STORE.name().to_string(),
     };
     
     println!("Health check requested");
@@ -921,105 +1426,83 @@ async fn text_generation(http_req: HttpRequest, req: web::Json<TextGenerationReq
     }
     let start_time = std::time::Instant::now();
     
+    // Select the inference backend for the requested model
+    let (backend, route) = match INFERENCE_BACKENDS.resolve(req.model.as_deref()) {
+        Ok(resolved) => resolved,
+        Err(e) => return Ok(inference_error_response(&e)),
+    };
+    let max_tokens = req.max_tokens.unwrap_or(100);
+    let mut prompt_tokens = TOKENIZER.count(&req.prompt);
+    let mut finish_reason = None;
+    
+    if let Some(message) = context_window_violation(prompt_tokens, max_tokens) {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Context Length Exceeded".to_string(),
+            message,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }));
+    }
+    
+    let params = GenerationParams {
+        messages: vec![ChatMessage::user(&req.prompt)],
+        max_tokens,
+        temperature: req.temperature.unwrap_or(0.7),
+        stop: Vec::new(),
+    };
+    
     // Enhanced Norwegian context processing with comprehensive intelligence
-    let generated_text = if req.norwegian_context.unwrap_or(false) {
-        // Norwegian Business Intelligence Analysis
+    let norwegian_analysis = if req.norwegian_context.unwrap_or(false) {
         let org_type = req.organization_type.as_deref().unwrap_or("forening");
-        
-        // Try to extract amount from prompt
-        let amount = extract_amount_from_text(&req.prompt).unwrap_or(100.0);
-        
-        // Detect Norwegian merchant
-        let merchant = detect_norwegian_merchant(&req.prompt).unwrap_or_else(|| {
-            NorwegianMerchantInfo {
-                name: "Ukjent norsk forhandler".to_string(),
-                chain: "Generisk".to_string(),
-                category: "Uidentifisert".to_string(),
-                typical_vat_rate: 25,
-                seasonal_products: vec![],
-                org_pattern: None,
-                confidence: 0.5,
-            }
-        });
-        
-        // Get seasonal context
-        let seasonal = get_seasonal_context(None);
-        
-        // Analyze VAT
-        let vat_analysis = analyze_norwegian_vat(amount, &merchant, &req.prompt);
-        
-        // Check compliance
-        let compliance = check_norwegian_compliance(org_type, &merchant, amount);
-        
-        // Determine cultural significance
-        let cultural_significance = if seasonal.cultural_event.is_some() {
-            Some(format!("Kulturell betydning: {} - typiske innkjøp inkluderer {}",
-                seasonal.cultural_event.as_ref().unwrap(),
-                seasonal.typical_purchases.join(", ")
-            ))
-        } else {
-            None
-        };
-        
-        // Generate comprehensive Norwegian analysis
-        let analysis = NorwegianAnalysis {
-            merchant: merchant.clone(),
-            vat_analysis,
-            seasonal_context: seasonal,
-            compliance_check: compliance,
-            cultural_significance,
-            deductibility_assessment: if merchant.category == "Alcohol Monopoly" && org_type == "korps" {
-                "IKKE FRADRAGSBERETTIGET - Alkohol ikke tillatt for korps".to_string()
-            } else if amount > 5000.0 {
-                "Krever styregodkjenning for beløp over 5000 NOK".to_string()
-            } else {
-                "Fradragsberettiget for organisasjonsformål".to_string()
-            },
-        };
-        
-        // Format the comprehensive analysis
-        format!(
-            "🇳🇴 NORSK AI-ANALYSE FOR {} 🇳🇴\n\nMERCHANT: {} ({})\n├─ Kategori: {}\n├─ Konfidensgrad: {:.1}%\n├─ Forventet MVA: {}%\n\nMVA-ANALYSE:\n├─ Detektert sats: {}%\n├─ Forklaring: {}\n├─ MVA-beløp: {:.2} NOK\n├─ Status: {}\n\nSESONGANALYSE:\n├─ Periode: {}\n├─ Kulturell kontekst: {}\n├─ Typiske innkjøp: {}\n├─ Prisforventning: {}\n\nKOMPLIANCE FOR {}:\n├─ Fradragsberettighet: {}\n├─ Dokumentasjon påkrevd: {}\n├─ Styregodkjenning: {}\n\n{}ORIGINAL PROMPT: {}",
-            org_type.to_uppercase(),
-            analysis.merchant.name,
-            analysis.merchant.chain,
-            analysis.merchant.category,
-            analysis.merchant.confidence * 100.0,
-            analysis.merchant.typical_vat_rate,
-            analysis.vat_analysis.detected_rate,
-            analysis.vat_analysis.rate_explanation,
-            analysis.vat_analysis.total_vat_amount.unwrap_or(0.0),
-            analysis.vat_analysis.compliance_status,
-            analysis.seasonal_context.season,
-            analysis.seasonal_context.cultural_event.as_deref().unwrap_or("Ingen spesiell"),
-            analysis.seasonal_context.typical_purchases.join(", "),
-            analysis.seasonal_context.price_expectations,
-            org_type.to_uppercase(),
-            analysis.compliance_check.deductibility,
-            analysis.compliance_check.documentation_required.join(", "),
-            if analysis.compliance_check.approval_needed { "JA" } else { "NEI" },
-            if let Some(cultural) = analysis.cultural_significance {
-                format!("{}\n\n", cultural)
-            } else {
-                String::new()
+        BUSINESS_REGISTER.fetch_missing(&orgnr::org_numbers_in(&req.prompt)).await;
+        let report = generate_norwegian_analysis_text(&req.prompt, org_type);
+        // The report is not model output, so it is only cut when the caller asked for a limit
+        match req.max_tokens.and_then(|limit| TOKENIZER.truncate(&report, limit)) {
+            Some(prefix) => {
+                finish_reason = Some("length".to_string());
+                Some(prefix.to_string())
             },
-            req.prompt
-        )
+            None => Some(report),
+        }
     } else {
-        format!(
-            "AI Response to '{}': This is a simulated response from the Rust LLM service. In a production environment, this would be replaced with actual LLM inference.",
-            req.prompt
-        )
+        None
+    };
+    
+    if req.stream.unwrap_or(false) {
+        // The report keeps its "length" finish_reason when it was cut at max_tokens
+        let report = norwegian_analysis.map(|text| Completion {
+            text,
+            prompt_tokens: None,
+            completion_tokens: None,
+            finish_reason: finish_reason.unwrap_or_else(|| "stop".to_string()),
+        });
+        return Ok(stream_text_generation(backend, route, params, report, start_time));
+    }
+    
+    let (generated_text, tokens_generated) = if let Some(analysis_text) = norwegian_analysis {
+        let tokens = TOKENIZER.count(&analysis_text);
+        (analysis_text, tokens)
+    } else {
+        match backend.generate(&route.upstream_model, &params).await {
+            Ok(mut completion) => {
+                enforce_max_tokens(&mut completion, max_tokens);
+                prompt_tokens = completion.prompt_tokens.unwrap_or(prompt_tokens);
+                finish_reason = Some(completion.finish_reason);
+                (completion.text, completion.completion_tokens.unwrap_or_default())
+            },
+            Err(e) => return Ok(inference_error_response(&e)),
+        }
     };
     
     let processing_time = start_time.elapsed().as_millis() as u64;
-    let model_name = req.model.clone().unwrap_or_else(|| "rust-llm-norwegian-v1".to_string());
+    let model_name = route.id;
     
     let response = TextGenerationResponse {
         text: generated_text.clone(),
         model: model_name.clone(),
         processing_time_ms: processing_time,
-        tokens_generated: req.max_tokens.unwrap_or(100),
+        tokens_generated,
+        prompt_tokens: Some(prompt_tokens),
+        finish_reason,
         timestamp: chrono::Utc::now().to_rfc3339(),
         // Felleskassen compatibility fields
         generated_text: Some(generated_text.clone()),
@@ -1040,23 +1523,65 @@ async fn list_models(http_req: HttpRequest) -> Result<HttpResponse> {
     if let Err(error_response) = validate_api_key_header(&http_req) {
         return Ok(error_response);
     }
+    let models: Vec<serde_json::Value> = INFERENCE_BACKENDS.routes().iter().map(|route| {
+        serde_json::json!({
+            "id": route.id,
+            "name": route.upstream_model,
+            "description": format!("Served by the {} inference backend", route.backend),
+            "backend": route.backend,
+            "max_tokens": tokenizer::context_window(),
+            "tokenizer": TOKENIZER.name(),
+            "capabilities": ["text-generation", "completion"]
+        })
+    }).collect();
+    
+    let embedding_models: Vec<serde_json::Value> = EMBEDDERS.models().into_iter().map(|(name, dimensions)| {
+        serde_json::json!({
+            "id": name,
+            "dimensions": dimensions,
+            "capabilities": ["embeddings"]
+        })
+    }).collect();
+    
+    let fine_tuned_models: Vec<serde_json::Value> = STORE.fine_tuned_models().unwrap_or_else(|e| {
+        println!("⚠️  Could not load fine-tuned models: {}", e);
+        Vec::new()
+    }).into_iter().map(|model| {
+        serde_json::json!({
+            "id": model.model_id,
+            "model_type": model.model_type,
+            "validation_metrics": model.metrics,
+            "created_at": model.created_at
+        })
+    }).collect();
+    
     let models = serde_json::json!({
-        "models": [
-            {
-                "id": "rust-llm-v1",
-                "name": "Rust LLM v1.0",
-                "description": "Production Rust-based language model",
-                "max_tokens": 4096,
-                "capabilities": ["text-generation", "completion"]
-            }
-        ],
-        "total": 1,
+        "total": models.len(),
+        "models": models,
+        "embedding_models": embedding_models,
+        "embedding_max_batch_size": EMBEDDERS.max_batch_size(),
+        "fine_tuned_models": fine_tuned_models,
+        "default_model": INFERENCE_BACKENDS.default_model(),
         "timestamp": chrono::Utc::now().to_rfc3339()
     });
     
     Ok(HttpResponse::Ok().json(models))
 }
 
+// Map embedding failures onto the service's error format
+fn embedding_error_response(error: &EmbeddingError) -> HttpResponse {
+    HttpResponse::BadRequest().json(ErrorResponse {
+        error: match error {
+            EmbeddingError::UnknownModel(_) => "Unknown Model",
+            EmbeddingError::InvalidDimensions { .. } => "Invalid Dimensions",
+            EmbeddingError::BatchTooLarge { .. } => "Batch Too Large",
+            EmbeddingError::EmptyInput => "Empty Input",
+        }.to_string(),
+        message: error.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
 async fn embeddings_endpoint(http_req: HttpRequest, req: web::Json<EmbeddingsRequest>) -> Result<HttpResponse> {
     // Validate API key
     if let Err(error_response) = validate_api_key_header(&http_req) {
@@ -1065,31 +1590,56 @@ async fn embeddings_endpoint(http_req: HttpRequest, req: web::Json<EmbeddingsReq
     
     let start_time = std::time::Instant::now();
     
-    // Generate mock Norwegian-aware embeddings (256-dimensional)
-    let embedding = if req.norwegian_context.unwrap_or(false) {
-        // Simulate Norwegian merchant pattern embeddings
-        (0..256).map(|i| {
-            // Create patterns based on Norwegian text characteristics
-            let base = (i as f32 * 0.1).sin();
-            let norwegian_bias = if req.text.contains("REMA") || req.text.contains("ICA") || req.text.contains("COOP") {
-                0.8 // High confidence for known Norwegian chains
-            } else if req.text.contains("AS") || req.text.contains("Norge") {
-                0.6 // Medium confidence for Norwegian business patterns
-            } else {
-                0.3 // Lower confidence for unknown patterns
-            };
-            base * norwegian_bias
-        }).collect()
-    } else {
-        // Generic embeddings
-        (0..256).map(|i| (i as f32 * 0.1).sin()).collect()
+    let req = req.into_inner();
+    let (model, dimensions) = (req.model, req.dimensions);
+    
+    if let Some(input) = req.input {
+        let inputs = input.into_vec();
+        let count = inputs.len();
+        // Model inference is CPU bound, keep it off the async workers
+        let result = web::block(move || EMBEDDERS.embed_batch(model.as_deref(), &inputs, dimensions)).await?;
+        let batch = match result {
+            Ok(batch) => batch,
+            Err(e) => return Ok(embedding_error_response(&e)),
+        };
+        
+        let data: Vec<EmbeddingsBatchItem> = batch.results.into_iter().enumerate().map(|(index, result)| match result {
+            Ok(vector) => EmbeddingsBatchItem { index, embedding: Some(vector), error: None },
+            Err(e) => EmbeddingsBatchItem { index, embedding: None, error: Some(e.to_string()) },
+        }).collect();
+        let failed = data.iter().filter(|item| item.error.is_some()).count();
+        
+        let processing_time = start_time.elapsed().as_millis() as u64;
+        println!("Generated {} embeddings ({} failed) in {}ms", count - failed, failed, processing_time);
+        
+        return Ok(HttpResponse::Ok().json(EmbeddingsBatchResponse {
+            data,
+            model: batch.model,
+            processing_time_ms: processing_time,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }));
+    }
+    
+    let Some(text) = req.text else {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Missing Input".to_string(),
+            message: "Provide either 'input' (a string or an array of strings) or 'text'".to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }));
+    };
+    
+    // Model inference is CPU bound, keep it off the async workers
+    let result = web::block(move || EMBEDDERS.embed(model.as_deref(), &text, dimensions)).await?;
+    let embedding = match result {
+        Ok(embedding) => embedding,
+        Err(e) => return Ok(embedding_error_response(&e)),
     };
     
     let processing_time = start_time.elapsed().as_millis() as u64;
     
     let response = EmbeddingsResponse {
-        embedding,
-        model: req.model.clone().unwrap_or_else(|| "sentence-transformer".to_string()),
+        embedding: embedding.vector,
+        model: embedding.model,
         processing_time_ms: processing_time,
         timestamp: chrono::Utc::now().to_rfc3339(),
     };
@@ -1098,32 +1648,18 @@ async fn embeddings_endpoint(http_req: HttpRequest, req: web::Json<EmbeddingsReq
     Ok(HttpResponse::Ok().json(response))
 }
 
-async fn document_processing(http_req: HttpRequest, req: web::Json<DocumentProcessingRequest>) -> Result<HttpResponse> {
-    // Validate API key
-    if let Err(error_response) = validate_api_key_header(&http_req) {
-        return Ok(error_response);
-    }
-    
-    let start_time = std::time::Instant::now();
-    let org_type = req.organization_type.as_deref().unwrap_or("forening");
-    
-    // Determine processing text
-    let processing_text = if let Some(image_data) = &req.image_data {
-        // Extract text from image using simulated OCR
-        extract_text_from_image(image_data)
-    } else if let Some(document_text) = &req.document_text {
-        document_text.clone()
-    } else {
-        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
-            error: "Missing Input".to_string(),
-            message: "Either image_data or document_text must be provided".to_string(),
-            timestamp: chrono::Utc::now().to_rfc3339(),
-        }));
-    };
-    
+// Merchant, VAT, seasonal and compliance analysis of a document's text
+fn analyze_document_text(text: &str, org_type: &str, date: Option<chrono::NaiveDate>) -> NorwegianAnalysis {
+    // A date given by the client wins over the one printed on the receipt
+    let date = date.or_else(|| transaction::extract_transaction_details(text, chrono::Utc::now().date_naive()).date);
+    let rates = vat_rates_on(date);
+    // Prefer the printed total of a parsed receipt, then the best-ranked amount in the text
+    let amount = receipt::parse_receipt(text, &rates)
+        .and_then(|receipt| receipt.total)
+        .or_else(|| amount::extract_amount(text).map(|amount| amount.amount));
     // Process with enhanced learning-enabled detection
-    let amount = extract_amount_from_text(&processing_text).unwrap_or(100.0);
-    let merchant = detect_norwegian_merchant_with_learning(&processing_text).unwrap_or_else(|| {
+    let merchant_candidates = detect_norwegian_merchant_with_learning(text, &rates);
+    let mut merchant = merchant_candidates.first().map(|candidate| candidate.merchant.clone()).unwrap_or_else(|| {
         NorwegianMerchantInfo {
             name: "Ukjent norsk forhandler".to_string(),
             chain: "Generisk".to_string(),
@@ -1131,44 +1667,269 @@ async fn document_processing(http_req: HttpRequest, req: web::Json<DocumentProce
             typical_vat_rate: 25,
             seasonal_products: vec![],
             org_pattern: None,
+            org_number: None,
+            register_entry: None,
+            country: None,
             confidence: 0.5,
         }
     });
+    merchant.org_number = orgnr::find_org_number(text);
     
-    let seasonal = get_seasonal_context(None);
-    let vat_analysis = analyze_norwegian_vat(amount, &merchant, &processing_text);
-    let compliance = check_norwegian_compliance(org_type, &merchant, amount);
+    // A receipt from abroad is judged on its NOK value at Norges Bank's rate for the day
+    let detected = currency::detect_currency(text, merchant.country.as_deref());
+    let conversion = (detected.currency != Currency::NOK).then(|| {
+        let rate = EXCHANGE_RATES.rate_on(detected.currency, date.unwrap_or_else(|| chrono::Utc::now().date_naive()));
+        CurrencyConversion::new(&detected, amount, rate)
+    });
+    let amount_nok = conversion.as_ref().map_or(amount, |conversion| conversion.amount_nok);
+    
+    let mut analysis = analyze_purchase(merchant, amount_nok, text, org_type, &rates);
+    analysis.merchant_candidates = merchant_candidates;
+    if let Some(conversion) = conversion {
+        apply_foreign_currency(&mut analysis, conversion, text, &rates);
+    }
+    analysis
+}
+
+// VAT paid abroad is the other country's: read only from the receipt's own
+// summary, in its currency, and never deductible as Norwegian input VAT
+fn apply_foreign_currency(analysis: &mut NorwegianAnalysis, conversion: CurrencyConversion, text: &str, rates: &EffectiveRates) {
+    let currency = conversion.original_currency;
+    let summary = receipt::parse_vat_summary(text);
+    let mut vat_analysis = vat_analysis_from_breakdown(summary, "receipt_summary", &analysis.merchant, rates);
+    if vat_analysis.breakdown.is_empty() {
+        vat_analysis.breakdown_source = "not_declared".to_string();
+    }
+    vat_analysis.rate_explanation = format!("Foreign VAT at {}%, charged in {}", vat_analysis.detected_rate, currency);
+    vat_analysis.compliance_status = "Foreign VAT - not deductible as Norwegian input VAT".to_string();
+    vat_analysis.total_vat_amount = vat_analysis.total_vat_amount.map(|vat| vat.in_currency(currency));
+    vat_analysis.currency = Some(currency);
+    
+    // Norwegian MVA registration says nothing about a foreign seller
+    let mut warnings = Vec::new();
+    match (conversion.original_amount, conversion.amount_nok) {
+        (None, _) => warnings.push(AMOUNT_NOT_FOUND_WARNING.to_string()),
+        (Some(original), None) => warnings.push(format!("Fant ingen valutakurs for {} på kjøpsdatoen - registrer beløpet på {} i NOK manuelt", currency, original)),
+        _ => {},
+    }
+    warnings.push(format!("Merverdiavgift betalt i utlandet ({}) kan ikke føres som inngående merverdiavgift i Norge", currency));
+    analysis.compliance_check.warnings = warnings;
+    analysis.compliance_check.foreign_vat = Some(ForeignVat {
+        country_currency: currency,
+        vat_amount: vat_analysis.total_vat_amount,
+        refundable: false,
+    });
     
-    let cultural_significance = if seasonal.cultural_event.is_some() {
-        Some(format!("Kulturell betydning: {} - typiske innkjøp inkluderer {}",
-            seasonal.cultural_event.as_ref().unwrap(),
+    analysis.amount_not_found = conversion.original_amount.is_none();
+    analysis.vat_analysis = vat_analysis;
+    analysis.currency_conversion = Some(conversion);
+}
+
+// Analysis of an e-invoice, with the supplier taken from the structured data
+fn analyze_ehf_invoice(invoice: &EhfInvoice, org_type: &str) -> NorwegianAnalysis {
+    let supplier = &invoice.supplier;
+    let issue_date = chrono::NaiveDate::parse_from_str(&invoice.issue_date, "%Y-%m-%d").ok();
+    let rates = vat_rates_on(issue_date);
+    
+    let lookup = format!("{} {}", supplier.name, supplier.org_number.as_deref().unwrap_or(""));
+    let mut merchant = detect_norwegian_merchant_with_learning(&lookup, &rates).into_iter().next().map(|candidate| candidate.merchant).unwrap_or_else(|| {
+        NorwegianMerchantInfo {
+            name: supplier.name.clone(),
+            chain: supplier.name.clone(),
+            category: "Uidentifisert".to_string(),
+            typical_vat_rate: invoice.dominant_vat_percent().map(|percent| percent.round() as u8).unwrap_or(25),
+            seasonal_products: vec![],
+            org_pattern: supplier.org_number.clone(),
+            org_number: None,
+            register_entry: None,
+            country: None,
+            confidence: 0.5,
+        }
+    });
+    merchant.org_number = supplier.org_number.as_deref().map(|number| OrgNumber::new(number, supplier.mva_registered));
+    
+    // Approval limits are in NOK, so an invoice in another currency is judged
+    // on its value at Norges Bank's rate on the issue date
+    let payable = invoice.totals.payable_amount;
+    let currency = payable.currency();
+    let conversion = (currency != Currency::NOK).then(|| {
+        let detected = DetectedCurrency { currency, detected_from: "document_currency_code" };
+        let rate = EXCHANGE_RATES.rate_on(currency, issue_date.unwrap_or_else(|| chrono::Utc::now().date_naive()));
+        CurrencyConversion::new(&detected, Some(payable), rate)
+    });
+    let amount_nok = conversion.as_ref().map_or(Some(payable), |conversion| conversion.amount_nok);
+    
+    let mut analysis = analyze_purchase(merchant, amount_nok, &invoice.item_text(), org_type, &rates);
+    // The invoice declares its VAT per category, so nothing has to be estimated
+    if !invoice.vat_breakdown.is_empty() {
+        let mut by_rate: std::collections::BTreeMap<u8, VatRateBreakdown> = std::collections::BTreeMap::new();
+        for subtotal in &invoice.vat_breakdown {
+            let rate = subtotal.percent.round() as u8;
+            let zero = Money::zero(subtotal.taxable_amount.currency());
+            let entry = by_rate.entry(rate).or_insert(VatRateBreakdown { rate, base_amount: zero, vat_amount: zero });
+            entry.base_amount += subtotal.taxable_amount;
+            entry.vat_amount += subtotal.tax_amount;
+        }
+        analysis.vat_analysis = vat_analysis_from_breakdown(by_rate.into_values().collect(), "ehf_invoice", &analysis.merchant, &rates);
+        analysis.vat_analysis.currency = (currency != Currency::NOK).then_some(currency);
+        analysis.compliance_check.warnings = org_number_warnings(&analysis.merchant, &analysis.vat_analysis);
+    }
+    if let Some(conversion) = conversion {
+        // The invoice states its amount; only the rate can be missing
+        analysis.amount_not_found = false;
+        if conversion.amount_nok.is_none() {
+            analysis.compliance_check.warnings.push(format!(
+                "Fant ingen valutakurs for {} på fakturadatoen - registrer beløpet på {} i NOK manuelt", currency, payable
+            ));
+        }
+        analysis.currency_conversion = Some(conversion);
+    }
+    // The e-invoice itself is the voucher
+    for document in analysis.compliance_check.documentation_required.iter_mut() {
+        if document == "Kvittering" {
+            *document = "EHF-faktura".to_string();
+        }
+    }
+    analysis
+}
+
+fn analyze_purchase(merchant: NorwegianMerchantInfo, amount: Option<Money>, items: &str, org_type: &str, rates: &EffectiveRates) -> NorwegianAnalysis {
+    let seasonal = get_seasonal_context(Some(rates.date));
+    let vat_analysis = analyze_norwegian_vat(amount, &merchant, items, rates);
+    let compliance = check_norwegian_compliance(org_type, &merchant, amount, &vat_analysis);
+    
+    let cultural_significance = seasonal.cultural_event.as_ref().map(|event| {
+        format!("Kulturell betydning: {} - typiske innkjøp inkluderer {}",
+            event,
             seasonal.typical_purchases.join(", ")
-        ))
-    } else {
-        None
-    };
+        )
+    });
     
-    let norwegian_analysis = NorwegianAnalysis {
+    NorwegianAnalysis {
         merchant: merchant.clone(),
         vat_analysis,
         seasonal_context: seasonal,
         compliance_check: compliance,
         cultural_significance,
-        deductibility_assessment: if merchant.category == "Alcohol Monopoly" && org_type == "korps" {
-            "IKKE FRADRAGSBERETTIGET - Alkohol ikke tillatt for korps".to_string()
-        } else if amount > 5000.0 {
-            "Krever styregodkjenning for beløp over 5000 NOK".to_string()
-        } else {
-            "Fradragsberettiget for organisasjonsformål".to_string()
-        },
+        deductibility_assessment: deductibility_assessment(&merchant, amount, org_type),
+        amount,
+        amount_not_found: amount.is_none(),
+        merchant_candidates: Vec::new(),
+        currency_conversion: None,
+    }
+}
+
+fn deductibility_assessment(merchant: &NorwegianMerchantInfo, amount: Option<Money>, org_type: &str) -> String {
+    if merchant.category == "Alcohol Monopoly" && org_type == "korps" {
+        "IKKE FRADRAGSBERETTIGET - Alkohol ikke tillatt for korps".to_string()
+    } else if amount.is_some_and(|amount| amount > Money::nok(5000)) {
+        "Krever styregodkjenning for beløp over 5000 NOK".to_string()
+    } else {
+        "Fradragsberettiget for organisasjonsformål".to_string()
+    }
+}
+
+async fn document_processing(http_req: HttpRequest, req: web::Json<DocumentProcessingRequest>) -> Result<HttpResponse> {
+    // Validate API key
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+    
+    let start_time = std::time::Instant::now();
+    let org_type = req.organization_type.as_deref().unwrap_or("forening");
+    let transaction_date = match req.transaction_date.as_deref().map(|date| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")) {
+        Some(Ok(date)) => Some(date),
+        Some(Err(_)) => return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Invalid Date".to_string(),
+            message: "transaction_date must be a YYYY-MM-DD date".to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        })),
+        None => None,
+    };
+    
+    // Run OCR on the uploaded image; decoding and recognition are CPU bound
+    let image_analysis = if let Some(image_data) = req.image_data.clone() {
+        let result = web::block(move || {
+            if !OCR_ENGINE.available() {
+                return Err(OcrError::EngineUnavailable);
+            }
+            let bytes = ocr::decode_base64(&image_data)?;
+            let image = preprocess::preprocess(&bytes)?;
+            let ocr = OCR_ENGINE.recognize(&image.image)?;
+            Ok::<_, OcrError>((image, ocr))
+        }).await?;
+        match result {
+            Ok((image, ocr)) => Some(analyze_document_image(&image, &ocr)),
+            // Clients that also send pre-extracted text still get an analysis
+            Err(OcrError::EngineUnavailable) if req.document_text.is_some() => None,
+            Err(e) => return Ok(ocr_error_response(&e)),
+        }
+    } else {
+        None
     };
     
-    // Process image if provided
-    let image_analysis = if let Some(image_data) = &req.image_data {
-        process_document_image(image_data)
+    // Read PDF invoices: the text layer where there is one, OCR for scanned pages
+    let pdf_pages = if let Some(pdf_data) = &req.pdf_data {
+        let bytes = match ocr::decode_base64(pdf_data) {
+            Ok(bytes) => bytes,
+            Err(e) => return Ok(ocr_error_response(&e)),
+        };
+        match web::block(move || read_pdf_pages(&bytes)).await? {
+            Ok(pages) => Some(pages),
+            Err(e) => return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid PDF".to_string(),
+                message: e.to_string(),
+                timestamp: chrono::Utc::now().to_rfc3339(),
+            })),
+        }
     } else {
         None
     };
+    let pdf_text = pdf_pages.as_ref().map(|pages| {
+        pages.iter().map(|page| page.text.as_str()).filter(|text| !text.is_empty()).collect::<Vec<_>>().join("\n\n")
+    });
+    
+    // Determine processing text
+    let processing_text = if let Some(image) = &image_analysis {
+        image.text.clone()
+    } else if let Some(text) = pdf_text.filter(|text| !text.is_empty()) {
+        text
+    } else if let Some(document_text) = &req.document_text {
+        document_text.clone()
+    } else if let Some(pages) = &pdf_pages {
+        // Nothing could be read from any page
+        let reasons: Vec<String> = pages.iter()
+            .filter_map(|page| page.error.as_ref().map(|error| format!("page {}: {}", page.page_number, error)))
+            .collect();
+        let unavailable = !OCR_ENGINE.available() && !reasons.is_empty();
+        return Ok(if unavailable { HttpResponse::ServiceUnavailable() } else { HttpResponse::UnprocessableEntity() }.json(ErrorResponse {
+            error: if unavailable { "OCR Unavailable" } else { "No Readable Text" }.to_string(),
+            message: if reasons.is_empty() { "The PDF contains no text".to_string() } else { reasons.join("; ") },
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }));
+    } else {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Missing Input".to_string(),
+            message: "One of image_data, pdf_data or document_text must be provided".to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }));
+    };
+    
+    // Sellers unknown to the local index are looked up before the synchronous analysis
+    BUSINESS_REGISTER.fetch_missing(&orgnr::org_numbers_in(&processing_text)).await;
+    
+    let pages = pdf_pages.map(|pages| pages.into_iter().map(|page| PageAnalysis {
+        norwegian_analysis: (!page.text.is_empty()).then(|| analyze_document_text(&page.text, org_type, transaction_date)),
+        ..page
+    }).collect::<Vec<_>>());
+    
+    let norwegian_analysis = analyze_document_text(&processing_text, org_type, transaction_date);
+    let transaction = transaction::extract_transaction_details(&processing_text, chrono::Utc::now().date_naive());
+    let receipt = receipt::parse_receipt(&processing_text, &vat_rates_on(transaction_date.or(transaction.date)));
+    if let Some(receipt) = receipt.as_ref().filter(|receipt| receipt.totals_match == Some(false)) {
+        println!("⚠️  Receipt line items sum to {}, printed total is {}",
+                 receipt.computed_total, receipt.total.unwrap_or_default());
+    }
     
     // Apply learning if correction data provided
     let learning_applied = if let Some(correction) = &req.correction_data {
@@ -1178,12 +1939,23 @@ async fn document_processing(http_req: HttpRequest, req: web::Json<DocumentProce
     };
     
     let processing_time = start_time.elapsed().as_millis() as u64;
-    let processing_confidence = (merchant.confidence + 
-        image_analysis.as_ref().map(|img| img.ocr_confidence).unwrap_or(0.9)) / 2.0;
+    // Text that did not come from OCR is trusted at 0.9
+    let text_confidence = match (&image_analysis, &pages) {
+        (Some(image), _) => image.ocr_confidence,
+        (None, Some(pages)) if !pages.is_empty() => pages.iter()
+            .map(|page| page.image_analysis.as_ref().map(|image| image.ocr_confidence).unwrap_or(0.9))
+            .sum::<f32>() / pages.len() as f32,
+        _ => 0.9,
+    };
+    let processing_confidence = (norwegian_analysis.merchant.confidence + text_confidence) / 2.0;
     
     let response = DocumentProcessingResponse {
+        currency_conversion: norwegian_analysis.currency_conversion.clone(),
         norwegian_analysis,
         image_analysis,
+        pages,
+        transaction: (!transaction.is_empty()).then_some(transaction),
+        receipt,
         processing_confidence,
         learning_applied,
         model: "rust-llm-multimodal-v1".to_string(),
@@ -1195,6 +1967,152 @@ async fn document_processing(http_req: HttpRequest, req: web::Json<DocumentProce
     Ok(HttpResponse::Ok().json(response))
 }
 
+async fn ehf_invoice_processing(http_req: HttpRequest, req: web::Json<EhfInvoiceRequest>) -> Result<HttpResponse> {
+    // Validate API key
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+    
+    let start_time = std::time::Instant::now();
+    let org_type = req.organization_type.as_deref().unwrap_or("forening");
+    
+    // Raw XML, or base64 of the file as received from the access point
+    let xml = if req.invoice_xml.trim_start().starts_with('<') {
+        req.invoice_xml.clone()
+    } else {
+        match ocr::decode_base64(&req.invoice_xml).map(String::from_utf8) {
+            Ok(Ok(xml)) => xml,
+            _ => return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid Invoice".to_string(),
+                message: "invoice_xml must be UBL XML or base64 encoded UTF-8 XML".to_string(),
+                timestamp: chrono::Utc::now().to_rfc3339(),
+            })),
+        }
+    };
+    
+    let invoice = match ehf::parse_invoice(&xml) {
+        Ok(invoice) => invoice,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Invalid Invoice".to_string(),
+            message: e.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        })),
+    };
+    
+    if let Some(org_number) = &invoice.supplier.org_number {
+        BUSINESS_REGISTER.fetch_missing(std::slice::from_ref(org_number)).await;
+    }
+    let norwegian_analysis = analyze_ehf_invoice(&invoice, org_type);
+    let validation_warnings = invoice.consistency_warnings();
+    
+    let processing_time = start_time.elapsed().as_millis() as u64;
+    println!("📨 Processed EHF {} {} from {} in {}ms with {} warnings",
+             invoice.document_type, invoice.invoice_number, invoice.supplier.name, processing_time, validation_warnings.len());
+    
+    Ok(HttpResponse::Ok().json(EhfInvoiceResponse {
+        invoice,
+        currency_conversion: norwegian_analysis.currency_conversion.clone(),
+        norwegian_analysis,
+        validation_warnings,
+        model: "rust-llm-multimodal-v1".to_string(),
+        processing_time_ms: processing_time,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    }))
+}
+
+fn merchant_error_response(error: &MerchantError) -> HttpResponse {
+    let body = ErrorResponse {
+        error: match error {
+            MerchantError::Invalid(_) => "Invalid Merchant",
+            MerchantError::NotFound(_) => "Merchant Not Found",
+            MerchantError::Conflict(_) => "Merchant Conflict",
+            MerchantError::Storage(_) => "Storage Error",
+        }.to_string(),
+        message: error.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    };
+    
+    match error {
+        MerchantError::Invalid(_) => HttpResponse::BadRequest().json(body),
+        MerchantError::NotFound(_) => HttpResponse::NotFound().json(body),
+        MerchantError::Conflict(_) => HttpResponse::Conflict().json(body),
+        MerchantError::Storage(_) => HttpResponse::InternalServerError().json(body),
+    }
+}
+
+fn merchant_response(result: Result<MerchantRecord, MerchantError>, action: &str) -> HttpResponse {
+    match result {
+        Ok(merchant) => {
+            println!("🏪 {} merchant {} ({})", action, merchant.id, merchant.name);
+            HttpResponse::Ok().json(MerchantResponse {
+                merchant,
+                persisted: MERCHANTS.source() != "built-in",
+                timestamp: chrono::Utc::now().to_rfc3339(),
+            })
+        },
+        Err(e) => merchant_error_response(&e),
+    }
+}
+
+async fn list_merchants(http_req: HttpRequest) -> Result<HttpResponse> {
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+    let merchants = MERCHANTS.list();
+    Ok(HttpResponse::Ok().json(MerchantListResponse {
+        version: merchants::FILE_VERSION,
+        source: MERCHANTS.source().to_string(),
+        count: merchants.len(),
+        merchants,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    }))
+}
+
+async fn add_merchant(http_req: HttpRequest, req: web::Json<MerchantRecord>) -> Result<HttpResponse> {
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+    Ok(merchant_response(MERCHANTS.add(req.into_inner()), "Added"))
+}
+
+async fn update_merchant(http_req: HttpRequest, path: web::Path<String>, req: web::Json<MerchantRecord>) -> Result<HttpResponse> {
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+    Ok(merchant_response(MERCHANTS.update(&path.into_inner(), req.into_inner()), "Updated"))
+}
+
+async fn delete_merchant(http_req: HttpRequest, path: web::Path<String>) -> Result<HttpResponse> {
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+    Ok(merchant_response(MERCHANTS.delete(&path.into_inner()), "Deleted"))
+}
+
+// Norwegian holidays and shopping windows of a year
+async fn holiday_calendar(http_req: HttpRequest, path: web::Path<i32>) -> Result<HttpResponse> {
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+    let year = path.into_inner();
+    // The computus holds for the Gregorian calendar, in use since 1583
+    if !(1583..=9999).contains(&year) {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Invalid Year".to_string(),
+            message: "year must be between 1583 and 9999".to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }));
+    }
+    
+    Ok(HttpResponse::Ok().json(CalendarResponse {
+        year,
+        easter_sunday: calendar::easter_sunday(year).to_string(),
+        holidays: calendar::holidays(year),
+        shopping_windows: calendar::shopping_windows(year),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    }))
+}
+
 async fn learning_feedback(http_req: HttpRequest, req: web::Json<UserCorrection>) -> Result<HttpResponse> {
     // Validate API key
     if let Err(error_response) = validate_api_key_header(&http_req) {
@@ -1214,13 +2132,10 @@ async fn learning_feedback(http_req: HttpRequest, req: web::Json<UserCorrection>
     };
     
     // Count similar cases that would be updated
-    let similar_cases = if let Ok(learning_data) = LEARNING_DATA.lock() {
-        learning_data.iter().filter(|correction| {
-            correction.corrected_merchant == req.corrected_merchant
-        }).count() as u32
-    } else {
+    let similar_cases = STORE.count_corrections_for_merchant(req.corrected_merchant.as_deref()).unwrap_or_else(|e| {
+        println!("⚠️  Could not count similar corrections: {}", e);
         0
-    };
+    });
     
     let processing_time = start_time.elapsed().as_millis() as u64;
     
@@ -1257,7 +2172,7 @@ async fn fine_tuning(http_req: HttpRequest, req: web::Json<FineTuningRequest>) -
     
     // Store training examples for continuous learning
     for example in &req.training_data {
-        store_training_example(example.clone());
+        store_training_example(example);
     }
     
     // Simulate fine-tuning process
@@ -1265,12 +2180,12 @@ async fn fine_tuning(http_req: HttpRequest, req: web::Json<FineTuningRequest>) -
     
     // Store the fine-tuned model metrics
     let model_id = format!("norwegian-ai-{}-{}", model_type, chrono::Utc::now().timestamp());
-    if let Ok(mut models) = FINE_TUNED_MODELS.lock() {
-        models.insert(model_id.clone(), validation_metrics.clone());
+    if let Err(e) = STORE.save_fine_tuned_model(&model_id, model_type, &validation_metrics) {
+        println!("⚠️  Could not store fine-tuned model {}: {}", model_id, e);
     }
     
     let processing_time = start_time.elapsed().as_millis() as u64;
-    let estimated_time = (training_examples_count as f32 * 0.01).max(5.0).min(120.0) as u32;
+    let estimated_time = (training_examples_count as f32 * 0.01).clamp(5.0, 120.0) as u32;
     
     let response = FineTuningResponse {
         training_started: true,
@@ -1302,16 +2217,21 @@ async fn predictive_analysis(http_req: HttpRequest, req: web::Json<PredictiveAna
             timestamp: chrono::Utc::now().to_rfc3339(),
         }));
     }
+    // Totals per category, month and holiday window each sum part of the history
+    if Money::checked_sum(req.historical_transactions.iter().map(|transaction| transaction.amount.abs())).is_none() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Invalid Historical Data".to_string(),
+            message: format!("Historical transactions add up to more than {} kroner", MAX_KRONER),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }));
+    }
     
     let timeframe = req.prediction_timeframe.as_deref().unwrap_or("next_quarter");
     let analysis_type = req.analysis_type.as_deref().unwrap_or("spending_patterns");
     
     // Store seasonal patterns for future analysis
-    if let Ok(mut seasonal_patterns) = SEASONAL_PATTERNS.lock() {
-        seasonal_patterns.insert(
-            req.organization_type.clone(), 
-            req.historical_transactions.clone()
-        );
+    if let Err(e) = STORE.save_seasonal_patterns(&req.organization_type, &req.historical_transactions) {
+        println!("⚠️  Could not store seasonal patterns for {}: {}", req.organization_type, e);
     }
     
     // Generate comprehensive predictive analysis
@@ -1324,16 +2244,13 @@ async fn predictive_analysis(http_req: HttpRequest, req: web::Json<PredictiveAna
     // Enhanced analysis based on type
     match analysis_type {
         "seasonal_trends" => {
-            analysis.seasonal_insights = analysis.seasonal_insights.into_iter().map(|mut insight| {
-                // Enhance with actual historical data
-                insight.expected_spending_increase *= 1.1; // More accurate with real data
-                insight
-            }).collect();
+            // Only the events the history can speak to
+            analysis.seasonal_insights.retain(|insight| insight.expected_spending_increase.is_some());
         },
         "budget_forecast" => {
             analysis.budget_recommendations = analysis.budget_recommendations.into_iter().map(|mut rec| {
                 // More conservative recommendations for budget forecasts
-                rec.recommended_budget *= 1.15;
+                rec.recommended_budget = rec.recommended_budget.scale(1.15);
                 rec.reasoning = format!("Conservative forecast: {}", rec.reasoning);
                 rec
             }).collect();
@@ -1363,12 +2280,18 @@ async fn main() -> std::io::Result<()> {
         .unwrap_or_else(|_| "3200".to_string())
         .parse::<u16>()
         .expect("PORT must be a valid port number");
+    // Base64 phone photos and scanned PDFs are far above actix's 2 MB default
+    let max_body_mb = env::var("MAX_REQUEST_BODY_MB")
+        .unwrap_or_else(|_| "25".to_string())
+        .parse::<usize>()
+        .expect("MAX_REQUEST_BODY_MB must be a whole number of megabytes");
 
     println!("🚀 Rust LLM Service starting...");
     println!("   - Host: {}", host);
     println!("   - Port: {}", port);
     println!("   - Environment PORT: {:?}", env::var("PORT"));
     println!("   - Binding to: {}:{}", host, port);
+    println!("   - Max request body: {} MB", max_body_mb);
 
     // Generate a secure API key if none is set
     if env::var("RUST_LLM_API_KEY").is_err() {
@@ -1381,8 +2304,28 @@ async fn main() -> std::io::Result<()> {
         println!("🔒 API key authentication enabled");
     }
 
+    // Build the inference backend registry up front so misconfiguration shows at boot
+    println!("🧠 Inference backends:");
+    lazy_static::initialize(&INFERENCE_BACKENDS);
+    lazy_static::initialize(&TOKENIZER);
+    println!("🧭 Embedding models:");
+    lazy_static::initialize(&EMBEDDERS);
+    println!("🔎 OCR:");
+    lazy_static::initialize(&OCR_ENGINE);
+    println!("   - Context window: {} tokens", tokenizer::context_window());
+    println!("💾 Storage:");
+    lazy_static::initialize(&STORE);
+    println!("🧾 VAT rates:");
+    lazy_static::initialize(&VAT_RATES);
+    println!("🏪 Merchants:");
+    lazy_static::initialize(&MERCHANTS);
+    println!("🏢 Enhetsregisteret:");
+    lazy_static::initialize(&BUSINESS_REGISTER);
+    println!("💱 Exchange rates:");
+    lazy_static::initialize(&EXCHANGE_RATES);
+
     // Start HTTP server
-    HttpServer::new(|| {
+    HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
@@ -1392,6 +2335,7 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .wrap(Logger::default())
             .wrap(cors)
+            .app_data(web::JsonConfig::default().limit(max_body_mb * 1024 * 1024))
             .route("/api/health", web::get().to(health_check))
             // Compatibility endpoint for felleskassen
             .route("/api/ai/text-generation", web::post().to(text_generation))
@@ -1402,6 +2346,15 @@ async fn main() -> std::io::Result<()> {
             // Advanced AI capabilities
             .route("/api/ai/fine-tuning", web::post().to(fine_tuning))
             .route("/api/ai/predictive-analysis", web::post().to(predictive_analysis))
+            // OpenAI-compatible API for off-the-shelf SDKs
+            .service(
+                web::scope("/v1")
+                    .app_data(web::JsonConfig::default().limit(max_body_mb * 1024 * 1024).error_handler(openai_api::json_error))
+                    .route("/chat/completions", web::post().to(openai_api::chat_completions))
+                    .route("/completions", web::post().to(openai_api::completions))
+                    .route("/embeddings", web::post().to(openai_api::embeddings))
+                    .route("/models", web::get().to(openai_api::models))
+            )
             .service(
                 web::scope("/api/v1")
                     .service(
@@ -1415,6 +2368,18 @@ async fn main() -> std::io::Result<()> {
                     .service(
                         web::scope("/documents")
                             .route("/process", web::post().to(document_processing))
+                            .route("/ehf", web::post().to(ehf_invoice_processing))
+                    )
+                    .service(
+                        web::scope("/admin")
+                            .route("/merchants", web::get().to(list_merchants))
+                            .route("/merchants", web::post().to(add_merchant))
+                            .route("/merchants/{id}", web::put().to(update_merchant))
+                            .route("/merchants/{id}", web::delete().to(delete_merchant))
+                    )
+                    .service(
+                        web::scope("/calendar")
+                            .route("/{year}", web::get().to(holiday_calendar))
                     )
                     .service(
                         web::scope("/learning")
@@ -0,0 +1,583 @@
+// Norwegian merchant database: chains recognised by name, alias or
+// organisation number, with their category, typical VAT rate and seasonal
+// products. Indexed once when loaded rather than on every lookup: names and
+// aliases go into an Aho-Corasick automaton, and matches are ranked by
+// specificity, whether they head the document and the merchant's confidence.
+// Names garbled by OCR are matched approximately at a lower score.
+//
+// MERCHANTS_FILE=/path/merchants.toml  merchant data replacing the built-in
+//     table (.json files are read as JSON); re-read whenever the file changes
+//     and rewritten when merchants are edited through the admin API
+//
+// The built-in table is src/merchants.toml. Without MERCHANTS_FILE, admin
+// edits only last until the next restart.
+
+use aho_corasick::AhoCorasick;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+const BUILT_IN_MERCHANTS: &str = include_str!("merchants.toml");
+
+// Format of the merchant file this build reads and writes
+pub const FILE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum MerchantError {
+    Invalid(String),
+    NotFound(String),
+    Conflict(String),
+    Storage(String),
+}
+
+impl fmt::Display for MerchantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerchantError::Invalid(message) => write!(f, "{}", message),
+            MerchantError::NotFound(id) => write!(f, "No merchant with id '{}'", id),
+            MerchantError::Conflict(message) => write!(f, "{}", message),
+            MerchantError::Storage(message) => write!(f, "Could not save merchants: {}", message),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MerchantRecord {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub chain: String,
+    pub category: String,
+    pub typical_vat_rate: u8,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub org_numbers: Vec<String>,
+    #[serde(default)]
+    pub seasonal_products: Vec<String>,
+    // ISO 3166 code of a chain outside Norway, whose receipts are in that country's currency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(default = "default_confidence")]
+    pub confidence: f64,
+}
+
+fn default_confidence() -> f64 {
+    0.9
+}
+
+#[derive(Serialize, Deserialize)]
+struct MerchantFile {
+    version: u32,
+    merchants: Vec<MerchantRecord>,
+}
+
+// How a merchant was found in a document
+#[derive(Clone)]
+pub struct MerchantCandidate {
+    pub merchant: MerchantRecord,
+    pub score: f32, // 0 to 1
+    pub matched: String, // the name, alias or organisation number found
+    pub line: Option<usize>, // 1-based line of the first match
+    pub similarity: f32, // 1 when matched verbatim, lower for OCR-garbled names
+}
+
+// Merchant names usually head the receipt, so matches there count fully
+const HEADER_LINES: usize = 4;
+const BODY_LINE_WEIGHT: f32 = 0.75;
+
+// Approximate matches below this similarity are ignored, and names shorter
+// than MIN_FUZZY_NAME_LENGTH ("VY", "ICA") only match verbatim
+const MIN_SIMILARITY: f32 = 0.85;
+const MIN_FUZZY_NAME_LENGTH: usize = 4;
+
+// Characters OCR engines commonly confuse; swapping within a group costs a
+// quarter of an ordinary edit
+const OCR_CONFUSIONS: &[&str] = &["O0DQ", "I1L|!", "S5", "B8", "Z2", "G6"];
+
+// Merchants with their names and organisation numbers ready for lookup
+struct MerchantIndex {
+    merchants: Vec<MerchantRecord>,
+    // Upper-case name or alias and the merchant it belongs to; the position
+    // in this list is the automaton's pattern id
+    names: Vec<(String, usize)>,
+    automaton: AhoCorasick,
+    org_numbers: HashMap<String, usize>,
+}
+
+impl MerchantIndex {
+    fn build(mut merchants: Vec<MerchantRecord>) -> Result<Self, MerchantError> {
+        let mut names = Vec::new();
+        let mut org_numbers = HashMap::new();
+        let mut ids = HashMap::new();
+
+        for (position, merchant) in merchants.iter_mut().enumerate() {
+            normalize(merchant)?;
+            if let Some(other) = ids.insert(merchant.id.clone(), position) {
+                return Err(MerchantError::Conflict(format!("Merchant id '{}' is used twice (entries {} and {})", merchant.id, other + 1, position + 1)));
+            }
+            for name in std::iter::once(&merchant.name).chain(&merchant.aliases) {
+                names.push((name.to_uppercase(), position));
+            }
+            for number in &merchant.org_numbers {
+                if org_numbers.insert(number.clone(), position).is_some() {
+                    return Err(MerchantError::Conflict(format!("Organisation number {} belongs to more than one merchant", number)));
+                }
+            }
+        }
+
+        // A name shared by two merchants would make matches depend on file order
+        names.sort();
+        names.dedup();
+        for pair in names.windows(2) {
+            if pair[0].0 == pair[1].0 {
+                return Err(MerchantError::Conflict(format!(
+                    "'{}' names both {} and {}", pair[0].0, merchants[pair[0].1].id, merchants[pair[1].1].id
+                )));
+            }
+        }
+
+        let automaton = AhoCorasick::new(names.iter().map(|(name, _)| name))
+            .map_err(|e| MerchantError::Invalid(e.to_string()))?;
+        Ok(MerchantIndex { merchants, names, automaton, org_numbers })
+    }
+
+    // Every merchant named in the text or carrying one of its organisation
+    // numbers, best first
+    fn rank(&self, text: &str, org_numbers: &[String]) -> Vec<MerchantCandidate> {
+        let text = text.to_uppercase();
+
+        // Whole-word matches only: "ICA" must not match inside "AMERICA"
+        let mut matches: Vec<(usize, usize, usize)> = self.automaton.find_overlapping_iter(&text)
+            .filter(|found| is_word(&text, found.start(), found.end()))
+            .map(|found| (found.start(), found.end(), found.pattern().as_usize()))
+            .collect();
+        // "COOP" inside a matched "COOP EXTRA" belongs to the longer name. By
+        // start and then longest first, a match lies inside another exactly
+        // when an earlier one reaches as far; names are unique, so no two
+        // matches cover the same span.
+        matches.sort_by_key(|&(start, end, _)| (start, std::cmp::Reverse(end)));
+        let mut reach = 0;
+        let longest = matches.into_iter().filter(|&(_, end, _)| {
+            let inside = end <= reach;
+            reach = reach.max(end);
+            !inside
+        });
+
+        let lines = ContentLines::new(&text);
+        let mut best: HashMap<usize, MerchantCandidate> = HashMap::new();
+        let mut occurrences: HashMap<usize, usize> = HashMap::new();
+        for (start, _, pattern) in longest {
+            let (name, position) = &self.names[pattern];
+            let line = lines.at(start);
+            let score = name_score(&self.merchants[*position], name, line);
+
+            *occurrences.entry(*position).or_default() += 1;
+            if best.get(position).is_none_or(|candidate| score > candidate.score) {
+                best.insert(*position, MerchantCandidate {
+                    merchant: self.merchants[*position].clone(),
+                    score,
+                    matched: name.clone(),
+                    line: Some(line),
+                    similarity: 1.0,
+                });
+            }
+        }
+        // A name printed several times, e.g. in the header and the footer
+        for (position, count) in occurrences {
+            if let Some(candidate) = best.get_mut(&position) {
+                candidate.score = (candidate.score + 0.02 * (count - 1) as f32).min(1.0);
+            }
+        }
+
+        // OCR misreads such as "REMA 1OOO" or "K1WI" are compared word by word
+        // against the names of merchants not found verbatim
+        let mut approximate: HashMap<usize, MerchantCandidate> = HashMap::new();
+        for (index, line) in text.lines().filter(|line| !line.trim().is_empty()).enumerate() {
+            let words: Vec<&str> = line.split_whitespace()
+                .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric() && !OCR_CONFUSIONS.iter().any(|group| group.contains(c))))
+                .filter(|word| !word.is_empty())
+                .collect();
+            for (name, position) in &self.names {
+                let width = name.split_whitespace().count();
+                if best.contains_key(position) || name.chars().count() < MIN_FUZZY_NAME_LENGTH || width > words.len() {
+                    continue;
+                }
+                for window in words.windows(width) {
+                    let found = window.join(" ");
+                    let similarity = ocr_similarity(&found, name);
+                    // A verbatim name was left to the automaton, which gives
+                    // "COOP" inside "COOP EXTRA" to the longer name
+                    if similarity < MIN_SIMILARITY || found == *name {
+                        continue;
+                    }
+                    let score = name_score(&self.merchants[*position], name, index + 1) * similarity;
+                    if approximate.get(position).is_none_or(|candidate| score > candidate.score) {
+                        approximate.insert(*position, MerchantCandidate {
+                            merchant: self.merchants[*position].clone(),
+                            score,
+                            matched: found,
+                            line: Some(index + 1),
+                            similarity,
+                        });
+                    }
+                }
+            }
+        }
+        best.extend(approximate);
+
+        // Organisation numbers identify a merchant regardless of where they are printed
+        for number in org_numbers {
+            let Some(&position) = self.org_numbers.get(number) else { continue };
+            let merchant = &self.merchants[position];
+            match best.get_mut(&position) {
+                // Name and number agree
+                Some(candidate) => candidate.score = (candidate.score.max(merchant.confidence as f32) + 0.05).min(1.0),
+                None => {
+                    best.insert(position, MerchantCandidate {
+                        merchant: merchant.clone(),
+                        score: merchant.confidence as f32,
+                        matched: number.clone(),
+                        line: None,
+                        similarity: 1.0,
+                    });
+                },
+            }
+        }
+
+        let mut candidates: Vec<MerchantCandidate> = best.into_values().collect();
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.merchant.id.cmp(&b.merchant.id)));
+        candidates
+    }
+}
+
+// Score of a verbatim name match on a given line
+fn name_score(merchant: &MerchantRecord, name: &str, line: usize) -> f32 {
+    // Short names like "VY" or "ICA" are more often accidental
+    let specificity = (0.8 + 0.04 * name.chars().count() as f32).min(1.0);
+    let placement = if line <= HEADER_LINES { 1.0 } else { BODY_LINE_WEIGHT };
+    merchant.confidence as f32 * specificity * placement
+}
+
+// Weighted edit distance turned into a similarity: 1 for identical text,
+// falling with each edit relative to the longer string
+fn ocr_similarity(found: &str, name: &str) -> f32 {
+    let found: Vec<char> = found.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let longest = found.len().max(name.len());
+    if longest == 0 || found.len().abs_diff(name.len()) * 4 > longest {
+        return 0.0;
+    }
+
+    let mut previous: Vec<f32> = (0..=name.len()).map(|j| j as f32).collect();
+    for (i, &found_char) in found.iter().enumerate() {
+        let mut current = vec![i as f32 + 1.0];
+        for (j, &name_char) in name.iter().enumerate() {
+            let substitution = previous[j] + substitution_cost(found_char, name_char);
+            current.push(substitution.min(previous[j + 1] + 1.0).min(current[j] + 1.0));
+        }
+        previous = current;
+    }
+    1.0 - previous[name.len()] / longest as f32
+}
+
+fn substitution_cost(a: char, b: char) -> f32 {
+    if a == b {
+        0.0
+    } else if OCR_CONFUSIONS.iter().any(|group| group.contains(a) && group.contains(b)) {
+        0.25
+    } else {
+        1.0
+    }
+}
+
+fn is_word(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+}
+
+// Line numbers not counting blank lines, looked up by byte offset
+struct ContentLines {
+    starts: Vec<(usize, usize)>, // offset of each line and its 1-based number
+}
+
+impl ContentLines {
+    fn new(text: &str) -> Self {
+        let mut starts = Vec::new();
+        let mut offset = 0;
+        let mut number = 1;
+        for line in text.split_inclusive('\n') {
+            starts.push((offset, number));
+            offset += line.len();
+            if !line.trim().is_empty() {
+                number += 1;
+            }
+        }
+        ContentLines { starts }
+    }
+
+    // Number of the line holding `offset`
+    fn at(&self, offset: usize) -> usize {
+        let index = self.starts.partition_point(|(start, _)| *start <= offset);
+        index.checked_sub(1).map_or(1, |index| self.starts[index].1)
+    }
+}
+
+fn normalize(merchant: &mut MerchantRecord) -> Result<(), MerchantError> {
+    merchant.id = merchant.id.trim().to_lowercase();
+    merchant.name = merchant.name.trim().to_string();
+    if merchant.id.is_empty() || merchant.id.contains('/') {
+        return Err(MerchantError::Invalid(format!("Merchant '{}' needs an id without '/'", merchant.name)));
+    }
+    if merchant.name.is_empty() {
+        return Err(MerchantError::Invalid(format!("Merchant '{}' has no name", merchant.id)));
+    }
+    if merchant.typical_vat_rate > 100 {
+        return Err(MerchantError::Invalid(format!("Merchant '{}' has a VAT rate of {}%", merchant.id, merchant.typical_vat_rate)));
+    }
+    if !(0.0..=1.0).contains(&merchant.confidence) {
+        return Err(MerchantError::Invalid(format!("Merchant '{}' needs a confidence between 0 and 1", merchant.id)));
+    }
+    merchant.country = merchant.country.as_deref().map(|country| country.trim().to_uppercase()).filter(|country| !country.is_empty());
+    if merchant.country.as_ref().is_some_and(|country| country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic())) {
+        return Err(MerchantError::Invalid(format!("Merchant '{}' needs a two-letter country code", merchant.id)));
+    }
+    merchant.aliases = merchant.aliases.iter().map(|alias| alias.trim().to_string()).filter(|alias| !alias.is_empty()).collect();
+    for number in merchant.org_numbers.iter_mut() {
+        *number = number.chars().filter(char::is_ascii_digit).collect();
+        if number.len() != 9 {
+            return Err(MerchantError::Invalid(format!("Merchant '{}' has an organisation number that is not nine digits", merchant.id)));
+        }
+    }
+    Ok(())
+}
+
+pub struct MerchantRegistry {
+    path: Option<String>,
+    state: RwLock<RegistryState>,
+}
+
+struct RegistryState {
+    index: Arc<MerchantIndex>,
+    modified: Option<SystemTime>,
+}
+
+impl MerchantRegistry {
+    pub fn from_env() -> Self {
+        let path = env::var("MERCHANTS_FILE").ok();
+        let (index, modified) = match &path {
+            Some(path) => match read_file(path) {
+                Ok(loaded) => loaded,
+                // Without merchants nothing can be recognised, so a broken file stops the boot
+                Err(e) => panic!("❌ Could not load merchants from {}: {}", path, e),
+            },
+            None => (parse_file(BUILT_IN_MERCHANTS, false).expect("built-in merchant table is valid"), None),
+        };
+
+        println!("   - {} merchants from {}", index.merchants.len(), path.as_deref().unwrap_or("the built-in table"));
+        if path.is_none() {
+            println!("⚠️  No MERCHANTS_FILE set, merchant edits are lost on restart");
+        }
+        MerchantRegistry { path, state: RwLock::new(RegistryState { index: Arc::new(index), modified }) }
+    }
+
+    pub fn source(&self) -> &str {
+        self.path.as_deref().unwrap_or("built-in")
+    }
+
+    // Merchants the text may come from, best first; `org_numbers` are the
+    // organisation numbers printed in it
+    pub fn rank(&self, text: &str, org_numbers: &[String]) -> Vec<MerchantCandidate> {
+        self.index().rank(text, org_numbers)
+    }
+
+    pub fn list(&self) -> Vec<MerchantRecord> {
+        self.index().merchants.clone()
+    }
+
+    pub fn add(&self, merchant: MerchantRecord) -> Result<MerchantRecord, MerchantError> {
+        self.edit(|merchants| {
+            let id = merchant.id.trim().to_lowercase();
+            if merchants.iter().any(|existing| existing.id == id) {
+                return Err(MerchantError::Conflict(format!("A merchant with id '{}' already exists", id)));
+            }
+            merchants.push(merchant);
+            Ok(merchants.len() - 1)
+        })
+    }
+
+    pub fn update(&self, id: &str, mut merchant: MerchantRecord) -> Result<MerchantRecord, MerchantError> {
+        let id = id.trim().to_lowercase();
+        merchant.id = id.clone();
+        self.edit(|merchants| {
+            let position = merchants.iter().position(|existing| existing.id == id).ok_or(MerchantError::NotFound(id))?;
+            merchants[position] = merchant;
+            Ok(position)
+        })
+    }
+
+    pub fn delete(&self, id: &str) -> Result<MerchantRecord, MerchantError> {
+        let id = id.trim().to_lowercase();
+        self.reload_if_changed();
+        let mut state = self.state.write().map_err(|_| MerchantError::Storage("merchant lock poisoned".to_string()))?;
+        let mut merchants = state.index.merchants.clone();
+        let position = merchants.iter().position(|existing| existing.id == id).ok_or(MerchantError::NotFound(id))?;
+        let removed = merchants.remove(position);
+        self.replace(&mut state, merchants)?;
+        Ok(removed)
+    }
+
+    // Applies a change to a copy of the merchants, checks it and saves it;
+    // `change` returns the position of the merchant it added or changed
+    fn edit(&self, change: impl FnOnce(&mut Vec<MerchantRecord>) -> Result<usize, MerchantError>) -> Result<MerchantRecord, MerchantError> {
+        self.reload_if_changed();
+        let mut state = self.state.write().map_err(|_| MerchantError::Storage("merchant lock poisoned".to_string()))?;
+        let mut merchants = state.index.merchants.clone();
+        let position = change(&mut merchants)?;
+        self.replace(&mut state, merchants)?;
+        Ok(state.index.merchants[position].clone())
+    }
+
+    fn replace(&self, state: &mut RegistryState, merchants: Vec<MerchantRecord>) -> Result<(), MerchantError> {
+        let index = MerchantIndex::build(merchants)?;
+        if let Some(path) = &self.path {
+            state.modified = Some(write_file(path, &index.merchants)?);
+        }
+        state.index = Arc::new(index);
+        Ok(())
+    }
+
+    fn index(&self) -> Arc<MerchantIndex> {
+        self.reload_if_changed();
+        let state = self.state.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.index.clone()
+    }
+
+    fn reload_if_changed(&self) {
+        let Some(path) = &self.path else { return };
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let current = self.state.read().ok().and_then(|state| state.modified);
+        if modified.is_none() || modified == current {
+            return;
+        }
+
+        match read_file(path) {
+            Ok((index, modified)) => {
+                println!("🏪 Reloaded {} merchants from {}", index.merchants.len(), path);
+                if let Ok(mut state) = self.state.write() {
+                    *state = RegistryState { index: Arc::new(index), modified };
+                }
+            },
+            Err(e) => {
+                println!("⚠️  Keeping the previous merchants, {} is invalid: {}", path, e);
+                // Remember the broken version so it is not re-read on every request
+                if let Ok(mut state) = self.state.write() {
+                    state.modified = modified;
+                }
+            },
+        }
+    }
+}
+
+fn read_file(path: &str) -> Result<(MerchantIndex, Option<SystemTime>), MerchantError> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let text = fs::read_to_string(path).map_err(|e| MerchantError::Storage(e.to_string()))?;
+    Ok((parse_file(&text, path.ends_with(".json"))?, modified))
+}
+
+fn parse_file(text: &str, json: bool) -> Result<MerchantIndex, MerchantError> {
+    let file: MerchantFile = if json {
+        serde_json::from_str(text).map_err(|e| MerchantError::Invalid(e.to_string()))?
+    } else {
+        toml::from_str(text).map_err(|e| MerchantError::Invalid(e.to_string()))?
+    };
+    if file.version != FILE_VERSION {
+        return Err(MerchantError::Invalid(format!("merchant file version {} is not supported, expected {}", file.version, FILE_VERSION)));
+    }
+    MerchantIndex::build(file.merchants)
+}
+
+// Written to a temporary file first so a crash never leaves half a file
+fn write_file(path: &str, merchants: &[MerchantRecord]) -> Result<SystemTime, MerchantError> {
+    let file = MerchantFile { version: FILE_VERSION, merchants: merchants.to_vec() };
+    let text = if path.ends_with(".json") {
+        serde_json::to_string_pretty(&file).map_err(|e| MerchantError::Storage(e.to_string()))?
+    } else {
+        toml::to_string_pretty(&file).map_err(|e| MerchantError::Storage(e.to_string()))?
+    };
+    let temporary = format!("{}.tmp", path);
+    fs::write(&temporary, text)
+        .and_then(|_| fs::rename(&temporary, path))
+        .and_then(|_| fs::metadata(path))
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| MerchantError::Storage(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked(text: &str) -> Vec<String> {
+        let index = parse_file(BUILT_IN_MERCHANTS, false).unwrap();
+        index.rank(text, &[]).into_iter().map(|candidate| candidate.merchant.id).collect()
+    }
+
+    #[test]
+    fn ocr_confusions_cost_less_than_other_edits() {
+        assert!(ocr_similarity("REMA 1OOO", "REMA 1000") >= MIN_SIMILARITY);
+        assert!(ocr_similarity("K1WI", "KIWI") >= MIN_SIMILARITY);
+        assert!(ocr_similarity("KAWI", "KIWI") < MIN_SIMILARITY);
+        assert_eq!(ocr_similarity("REMA", "REMA 1000"), 0.0);
+        assert_eq!(ocr_similarity("KIWI", "KIWI"), 1.0);
+    }
+
+    #[test]
+    fn names_match_whole_words_only() {
+        assert!(!ranked("BURGER AMERICA\nCHEESEBURGER 89,00").contains(&"ica".to_string()));
+        assert_eq!(ranked("ICA NÄRA\nMJÖLK 15,90"), ["ica"]);
+    }
+
+    #[test]
+    fn longer_name_claims_the_shorter_one_inside_it() {
+        assert_eq!(ranked("COOP EXTRA\nMELK 21,90"), ["extra"]);
+        assert_eq!(ranked("COOP MEGA\nMELK 21,90"), ["coop"]);
+    }
+
+    #[test]
+    fn ocr_garbled_names_are_found() {
+        let index = parse_file(BUILT_IN_MERCHANTS, false).unwrap();
+        let candidates = index.rank("K1WI MAJORSTUEN\nMELK 21,90", &[]);
+        assert_eq!(candidates[0].merchant.id, "kiwi");
+        assert!(candidates[0].similarity < 1.0);
+    }
+
+    #[test]
+    fn lines_are_numbered_without_blank_ones() {
+        let text = "\nREMA 1000\n\n  \nMELK 21,90\nKIWI";
+        let lines = ContentLines::new(text);
+        assert_eq!(lines.at(text.find("REMA").unwrap()), 1);
+        assert_eq!(lines.at(text.find("MELK").unwrap()), 2);
+        assert_eq!(lines.at(text.find("KIWI").unwrap()), 3);
+    }
+
+    #[test]
+    fn many_matches_are_ranked_in_one_pass() {
+        let text = "ICA\n".repeat(20_000);
+        let candidates = parse_file(BUILT_IN_MERCHANTS, false).unwrap().rank(&text, &[]);
+        assert_eq!(candidates[0].merchant.id, "ica");
+        assert_eq!(candidates[0].line, Some(1));
+    }
+
+    #[test]
+    fn org_number_identifies_the_merchant() {
+        let index = parse_file(BUILT_IN_MERCHANTS, false).unwrap();
+        let candidates = index.rank("PAKKE 129,00", &["984661185".to_string()]);
+        assert_eq!(candidates[0].merchant.id, "posten");
+        assert_eq!(candidates[0].line, None);
+    }
+}
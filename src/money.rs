@@ -0,0 +1,407 @@
+// Money as whole øre (hundredths of the currency unit) plus an ISO 4217
+// currency code, so VAT and totals add up exactly instead of drifting in f32.
+//
+// Rounding follows Norwegian practice: amounts are rounded to the øre half
+// away from zero (63,40 * 15/115 = 8,2696 -> 8,27), and cash payments are
+// rounded to whole kroner (øresavrunding: below 50 øre down, 50 øre and up up).
+//
+// JSON stays as it was: amounts are written as plain numbers in kroner
+// (63.4), and read from numbers or from strings as printed on Norwegian
+// documents ("1 245,50", "kr 245,-", "NOK 63,40", "12.50 EUR").
+//
+// Amounts in different currencies never add up: `+` and `-` are for amounts
+// known to share a currency, and `checked_add` for the ones that may not.
+// Amounts read from outside are limited to MAX_KRONER each, but a batch of
+// them (a transaction history, the lines of a receipt or invoice) can still
+// overflow when summed. Such a batch is first checked with `checked_sum` over
+// the amounts' magnitudes and rejected beyond MAX_KRONER; every total of part
+// of the batch, and the VAT of it, then stays far from overflowing.
+
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const NOK: Currency = Currency(*b"NOK");
+    pub const SEK: Currency = Currency(*b"SEK");
+    pub const DKK: Currency = Currency(*b"DKK");
+    pub const EUR: Currency = Currency(*b"EUR");
+
+    // Three letters, e.g. "nok" or "EUR"
+    pub fn parse(code: &str) -> Option<Currency> {
+        let code = code.trim().to_ascii_uppercase();
+        let bytes: [u8; 3] = code.as_bytes().try_into().ok()?;
+        bytes.iter().all(u8::is_ascii_uppercase).then_some(Currency(bytes))
+    }
+
+    pub fn code(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap_or("NOK")
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency::NOK
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+// Stored as its code next to an INTEGER øre column
+impl ToSql for Currency {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.code()))
+    }
+}
+
+impl FromSql for Currency {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let code = value.as_str()?;
+        Currency::parse(code).ok_or_else(|| FromSqlError::Other(format!("'{}' is not a currency code", code).into()))
+    }
+}
+
+// Ten trillion kroner, well above any real document
+pub const MAX_KRONER: i64 = 10_000_000_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Money {
+    ore: i64,
+    currency: Currency,
+}
+
+impl Money {
+    pub const ZERO: Money = Money::from_ore(0);
+
+    pub const fn from_ore(ore: i64) -> Self {
+        Money { ore, currency: Currency::NOK }
+    }
+
+    // Whole kroner, for limits such as "over 5000 NOK"
+    pub const fn nok(kroner: i64) -> Self {
+        Money::from_ore(kroner * 100)
+    }
+
+    pub const fn zero(currency: Currency) -> Self {
+        Money { ore: 0, currency }
+    }
+
+    // A decimal amount from outside the service (JSON numbers, EHF XML),
+    // rounded to the øre; None beyond MAX_KRONER
+    pub fn from_kroner(kroner: f64, currency: Currency) -> Option<Self> {
+        (kroner.is_finite() && kroner.abs() <= MAX_KRONER as f64)
+            .then(|| Money { ore: (kroner * 100.0).round() as i64, currency })
+    }
+
+    pub fn ore(&self) -> i64 {
+        self.ore
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn kroner(&self) -> f64 {
+        self.ore as f64 / 100.0
+    }
+
+    pub fn in_currency(self, currency: Currency) -> Self {
+        Money { currency, ..self }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.ore == 0
+    }
+
+    pub fn abs(self) -> Self {
+        Money { ore: self.ore.abs(), ..self }
+    }
+
+    // `factor` times the amount, rounded to the øre
+    pub fn scale(self, factor: f64) -> Self {
+        Money { ore: (self.ore as f64 * factor).round() as i64, ..self }
+    }
+
+    // VAT contained in an amount including VAT: gross * rate / (100 + rate)
+    pub fn vat_from_gross(self, rate: u8) -> Self {
+        Money { ore: divide_rounded(self.ore * rate as i64, 100 + rate as i64), ..self }
+    }
+
+    // VAT to add to an amount excluding VAT: net * rate / 100
+    pub fn vat_from_net(self, rate: u8) -> Self {
+        Money { ore: divide_rounded(self.ore * rate as i64, 100), ..self }
+    }
+
+    // Amount excluding VAT that carries `self` as VAT at `rate`
+    pub fn base_from_vat(self, rate: u8) -> Option<Self> {
+        (rate > 0).then(|| Money { ore: divide_rounded(self.ore * 100, rate as i64), ..self })
+    }
+
+    // The sum, or None for amounts in different currencies or on overflow.
+    // A zero amount takes the currency of the other, so totals can start at ZERO.
+    pub fn checked_add(self, other: Money) -> Option<Money> {
+        let currency = match (self.currency == other.currency, self.is_zero(), other.is_zero()) {
+            (true, _, _) | (false, false, true) => self.currency,
+            (false, true, _) => other.currency,
+            (false, false, false) => return None,
+        };
+        Some(Money { ore: self.ore.checked_add(other.ore)?, currency })
+    }
+
+    // The sum, or None for amounts in different currencies or a sum beyond MAX_KRONER
+    pub fn checked_sum<I: IntoIterator<Item = Money>>(amounts: I) -> Option<Money> {
+        amounts.into_iter()
+            .try_fold(Money::ZERO, Money::checked_add)
+            .filter(|sum| sum.ore.abs() <= MAX_KRONER * 100)
+    }
+
+    // Øresavrunding of a cash payment to whole kroner
+    pub fn round_to_krone(self) -> Self {
+        Money { ore: divide_rounded(self.ore, 100) * 100, ..self }
+    }
+}
+
+// Integer division rounding half away from zero
+fn divide_rounded(numerator: i64, denominator: i64) -> i64 {
+    let quotient = numerator / denominator;
+    let remainder = numerator % denominator;
+    if remainder.abs() * 2 >= denominator.abs() {
+        quotient + numerator.signum() * denominator.signum()
+    } else {
+        quotient
+    }
+}
+
+impl PartialOrd for Money {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Money {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.ore.cmp(&other.ore).then_with(|| self.currency.0.cmp(&other.currency.0))
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    // Callers add amounts of one currency only; a mixed sum is a bug, not a total
+    fn add(self, other: Money) -> Money {
+        self.checked_add(other).unwrap_or_else(|| panic!("cannot add {} to {}", other, self))
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        self + -other
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money { ore: -self.ore, ..self }
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.copied().sum()
+    }
+}
+
+// "1245.50 NOK", "-12.90 NOK"
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.ore < 0 { "-" } else { "" };
+        write!(f, "{}{}.{:02} {}", sign, self.ore.abs() / 100, self.ore.abs() % 100, self.currency)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidMoney(String);
+
+impl fmt::Display for InvalidMoney {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' is not an amount; expected e.g. 1245.50, \"1 245,50\" or \"NOK 63,40\"", self.0)
+    }
+}
+
+impl FromStr for Money {
+    type Err = InvalidMoney;
+
+    fn from_str(text: &str) -> Result<Money, InvalidMoney> {
+        let trimmed = text.trim();
+        // An ISO code before or after the amount; "kr" is left to the amount parser
+        let code = |word: Option<&str>| word.filter(|word| word.len() == 3).and_then(Currency::parse);
+        let (currency, amount) = if let Some(currency) = code(trimmed.split_whitespace().next()) {
+            (currency, &trimmed[3..])
+        } else if let Some(currency) = code(trimmed.split_whitespace().next_back()) {
+            (currency, &trimmed[..trimmed.len() - 3])
+        } else {
+            (Currency::NOK, trimmed)
+        };
+        crate::amount::parse_amount(amount)
+            .map(|money| money.in_currency(currency))
+            .ok_or_else(|| InvalidMoney(text.to_string()))
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.kroner())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MoneyInput {
+    Number(f64),
+    Text(String),
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+        match MoneyInput::deserialize(deserializer)? {
+            MoneyInput::Number(kroner) => Money::from_kroner(kroner, Currency::NOK)
+                .ok_or_else(|| serde::de::Error::custom(InvalidMoney(kroner.to_string()))),
+            MoneyInput::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+// For fields that are summed with NOK amounts: "12.50 EUR" is rejected
+// rather than added as if it were kroner
+pub fn deserialize_nok<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+    let money = Money::deserialize(deserializer)?;
+    if money.currency != Currency::NOK {
+        return Err(serde::de::Error::custom(format!("amount must be in NOK, got {}; convert it first", money)));
+    }
+    Ok(money)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vat_is_rounded_to_the_ore() {
+        assert_eq!(Money::from_ore(6340).vat_from_gross(15), Money::from_ore(827));
+        assert_eq!(Money::from_ore(10000).vat_from_gross(25), Money::from_ore(2000));
+        assert_eq!(Money::from_ore(16070).vat_from_net(15), Money::from_ore(2411));
+        assert_eq!(Money::from_ore(2000).base_from_vat(25), Some(Money::from_ore(8000)));
+        assert_eq!(Money::from_ore(2000).base_from_vat(0), None);
+    }
+
+    #[test]
+    fn halves_round_away_from_zero() {
+        assert_eq!(divide_rounded(5, 10), 1);
+        assert_eq!(divide_rounded(4, 10), 0);
+        assert_eq!(divide_rounded(-5, 10), -1);
+        assert_eq!(divide_rounded(-4, 10), 0);
+        assert_eq!(divide_rounded(15, -10), -2);
+    }
+
+    #[test]
+    fn cash_is_rounded_to_whole_kroner() {
+        assert_eq!(Money::from_ore(24549).round_to_krone(), Money::nok(245));
+        assert_eq!(Money::from_ore(24550).round_to_krone(), Money::nok(246));
+        assert_eq!(Money::from_ore(-24550).round_to_krone(), Money::nok(-246));
+    }
+
+    #[test]
+    fn only_amounts_in_one_currency_add_up() {
+        let sek = Money::nok(10).in_currency(Currency::SEK);
+        assert_eq!(Money::nok(10).checked_add(sek), None);
+        assert_eq!(Money::ZERO.checked_add(sek), Some(sek));
+        assert_eq!(sek.checked_add(Money::ZERO), Some(sek));
+        assert_eq!([sek, sek].iter().sum::<Money>(), Money::nok(20).in_currency(Currency::SEK));
+    }
+
+    #[test]
+    fn batches_sum_within_the_cap() {
+        let max = Money::nok(MAX_KRONER);
+        assert_eq!(Money::checked_sum([max, -max, max]), Some(max));
+        assert_eq!(Money::checked_sum([max, Money::from_ore(1)]), None);
+        assert_eq!(Money::checked_sum(vec![max; 10_000]), None);
+        assert_eq!(Money::checked_sum([Money::nok(1), Money::nok(1).in_currency(Currency::EUR)]), None);
+        assert_eq!(Money::checked_sum([]), Some(Money::ZERO));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot add")]
+    fn adding_mixed_currencies_panics() {
+        let _ = Money::nok(10) + Money::nok(10).in_currency(Currency::EUR);
+    }
+
+    #[test]
+    fn overflow_is_not_a_sum() {
+        assert_eq!(Money::from_ore(i64::MAX).checked_add(Money::from_ore(1)), None);
+        assert_eq!(Money::from_kroner(MAX_KRONER as f64 + 1.0, Currency::NOK), None);
+        assert_eq!(Money::from_kroner(f64::NAN, Currency::NOK), None);
+        assert_eq!(Money::from_kroner(63.4, Currency::EUR), Some(Money::from_ore(6340).in_currency(Currency::EUR)));
+    }
+
+    #[test]
+    fn currency_code_is_read_on_either_side() {
+        let eur = Money::from_ore(1250).in_currency(Currency::EUR);
+        assert_eq!("12.50 EUR".parse::<Money>().unwrap(), eur);
+        assert_eq!("EUR 12,50".parse::<Money>().unwrap(), eur);
+        assert_eq!("1 245,50".parse::<Money>().unwrap(), Money::from_ore(124550));
+        assert!("tolv kroner".parse::<Money>().is_err());
+        assert_eq!(eur.to_string(), "12.50 EUR");
+    }
+
+    #[test]
+    fn history_amounts_must_be_nok() {
+        #[derive(Deserialize)]
+        struct Row {
+            #[serde(deserialize_with = "deserialize_nok")]
+            amount: Money,
+        }
+        let row: Row = serde_json::from_str(r#"{"amount": 63.4}"#).unwrap();
+        assert_eq!(row.amount, Money::from_ore(6340));
+        assert!(serde_json::from_str::<Row>(r#"{"amount": "12.50 EUR"}"#).is_err());
+        assert!(serde_json::from_str::<Row>(r#"{"amount": 1e300}"#).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+// OCR for uploaded document images. image_data is base64 (optionally a
+// data: URL) in any format the image crate decodes; the preprocess module
+// cleans the photo up and recognition runs through Tesseract when the service
+// is built with `--features tesseract`.
+//
+// OCR_LANGUAGES=nor+eng   Tesseract language packs to load
+// TESSDATA_PREFIX=/path   directory holding the *.traineddata files
+//
+// Tesseract reports word boxes as TSV, which are grouped into lines here.
+
+use base64::Engine;
+use image::GrayImage;
+use serde::Serialize;
+use std::env;
+use std::fmt;
+
+const DEFAULT_LANGUAGES: &str = "nor+eng";
+
+// Phone photos carry no usable DPI, assume a typical scan resolution
+#[cfg(feature = "tesseract")]
+const SOURCE_RESOLUTION_PPI: i32 = 300;
+
+#[derive(Debug)]
+pub enum OcrError {
+    InvalidBase64(String),
+    InvalidImage(String),
+    EngineUnavailable,
+    #[cfg_attr(not(feature = "tesseract"), allow(dead_code))]
+    Engine(String),
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OcrError::InvalidBase64(e) => write!(f, "Input is not valid base64: {}", e),
+            OcrError::InvalidImage(e) => write!(f, "image_data could not be decoded as an image: {}", e),
+            OcrError::EngineUnavailable => {
+                write!(f, "No OCR engine is available in this build; send document_text or build with --features tesseract")
+            },
+            OcrError::Engine(e) => write!(f, "OCR failed: {}", e),
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct BoundingBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct OcrLine {
+    pub text: String,
+    pub bounding_box: BoundingBox,
+    pub confidence: f32, // 0.0-1.0, mean over the words in the line
+}
+
+pub struct OcrResult {
+    pub lines: Vec<OcrLine>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OcrResult {
+    pub fn text(&self) -> String {
+        self.lines.iter().map(|line| line.text.as_str()).collect::<Vec<_>>().join("\n")
+    }
+
+    // Mean line confidence weighted by line length, 0.0 when nothing was read
+    pub fn confidence(&self) -> f32 {
+        let chars: usize = self.lines.iter().map(|line| line.text.chars().count()).sum();
+        if chars == 0 {
+            return 0.0;
+        }
+        self.lines.iter()
+            .map(|line| line.confidence * line.text.chars().count() as f32)
+            .sum::<f32>() / chars as f32
+    }
+}
+
+pub struct OcrEngine {
+    languages: String,
+    #[cfg_attr(not(feature = "tesseract"), allow(dead_code))]
+    datapath: Option<String>,
+}
+
+impl OcrEngine {
+    pub fn from_env() -> Self {
+        let engine = OcrEngine {
+            languages: env::var("OCR_LANGUAGES").unwrap_or_else(|_| DEFAULT_LANGUAGES.to_string()),
+            datapath: env::var("TESSDATA_PREFIX").ok(),
+        };
+        if engine.available() {
+            println!("   - Tesseract OCR with languages {}", engine.languages);
+        } else {
+            println!("⚠️  Built without the tesseract feature, image_data cannot be read");
+        }
+        engine
+    }
+
+    pub fn available(&self) -> bool {
+        cfg!(feature = "tesseract")
+    }
+
+    pub fn name(&self) -> String {
+        if self.available() {
+            format!("tesseract ({})", self.languages)
+        } else {
+            "none".to_string()
+        }
+    }
+
+    // Bounding boxes are in the coordinates of `image`, i.e. after preprocessing
+    pub fn recognize(&self, image: &GrayImage) -> Result<OcrResult, OcrError> {
+        let (width, height) = image.dimensions();
+        let tsv = self.run_tesseract(image.as_raw(), width, height)?;
+
+        Ok(OcrResult {
+            lines: parse_tsv(&tsv),
+            width,
+            height,
+        })
+    }
+
+    #[cfg(feature = "tesseract")]
+    fn run_tesseract(&self, pixels: &[u8], width: u32, height: u32) -> Result<String, OcrError> {
+        let run = || -> Result<String, tesseract::TesseractError> {
+            let tesseract = tesseract::Tesseract::new(self.datapath.as_deref(), Some(&self.languages))?;
+            let mut tesseract = tesseract
+                .set_frame(pixels, width as i32, height as i32, 1, width as i32)?
+                .set_source_resolution(SOURCE_RESOLUTION_PPI)
+                .recognize()?;
+            Ok(tesseract.get_tsv_text(0)?)
+        };
+        run().map_err(|e| OcrError::Engine(e.to_string()))
+    }
+
+    #[cfg(not(feature = "tesseract"))]
+    fn run_tesseract(&self, _pixels: &[u8], _width: u32, _height: u32) -> Result<String, OcrError> {
+        Err(OcrError::EngineUnavailable)
+    }
+}
+
+// Accepts plain base64 or a data:...;base64, URL
+pub fn decode_base64(data: &str) -> Result<Vec<u8>, OcrError> {
+    let encoded = match data.split_once(";base64,") {
+        Some((prefix, payload)) if prefix.starts_with("data:") => payload,
+        _ => data,
+    };
+    let encoded: String = encoded.chars().filter(|c| !c.is_whitespace()).collect();
+
+    base64::engine::general_purpose::STANDARD.decode(encoded)
+        .map_err(|e| OcrError::InvalidBase64(e.to_string()))
+}
+
+// Tesseract TSV columns: level page_num block_num par_num line_num word_num
+// left top width height conf text. Level 4 rows are lines, level 5 rows words.
+#[cfg_attr(not(feature = "tesseract"), allow(dead_code))]
+fn parse_tsv(tsv: &str) -> Vec<OcrLine> {
+    let mut lines: Vec<(BoundingBox, Vec<(String, f32)>)> = Vec::new();
+
+    for row in tsv.lines() {
+        let fields: Vec<&str> = row.splitn(12, '\t').collect();
+        if fields.len() < 11 {
+            continue;
+        }
+        let number = |i: usize| fields[i].trim().parse::<i64>().ok();
+        let (Some(level), Some(left), Some(top), Some(width), Some(height)) =
+            (number(0), number(6), number(7), number(8), number(9)) else { continue };
+        let bounding_box = BoundingBox {
+            x: left.max(0) as u32,
+            y: top.max(0) as u32,
+            width: width.max(0) as u32,
+            height: height.max(0) as u32,
+        };
+
+        match level {
+            4 => lines.push((bounding_box, Vec::new())),
+            5 => {
+                let text = fields.get(11).map(|text| text.trim()).unwrap_or("");
+                let confidence = fields[10].trim().parse::<f32>().unwrap_or(-1.0);
+                if text.is_empty() || confidence < 0.0 {
+                    continue;
+                }
+                if let Some((_, words)) = lines.last_mut() {
+                    words.push((text.to_string(), (confidence / 100.0).clamp(0.0, 1.0)));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    lines.into_iter()
+        .filter(|(_, words)| !words.is_empty())
+        .map(|(bounding_box, words)| OcrLine {
+            text: words.iter().map(|(text, _)| text.as_str()).collect::<Vec<_>>().join(" "),
+            confidence: words.iter().map(|(_, confidence)| confidence).sum::<f32>() / words.len() as f32,
+            bounding_box,
+        })
+        .collect()
+}
@@ -0,0 +1,489 @@
+// OpenAI-compatible API surface (/v1/chat/completions, /v1/completions,
+// /v1/embeddings, /v1/models) so off-the-shelf SDKs can talk to the service.
+// Requests are mapped onto the same inference backends and embedding logic as
+// the native /api/v1 routes.
+
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::embeddings::EmbeddingError;
+use crate::inference::{ChatMessage, Completion, GenerationParams, InferenceError};
+use crate::{
+    context_window_violation, enforce_max_tokens, stream_text_generation,
+    validate_api_key_header, EMBEDDERS, INFERENCE_BACKENDS, TOKENIZER,
+};
+
+// Upper bound on `n`, each choice is a separate backend call
+const MAX_CHOICES: u32 = 8;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum StringOrList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl StringOrList {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            StringOrList::One(value) => vec![value],
+            StringOrList::Many(values) => values,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChatCompletionRequest {
+    model: Option<String>,
+    messages: Vec<ChatMessage>,
+    max_tokens: Option<u32>,
+    max_completion_tokens: Option<u32>,
+    temperature: Option<f32>,
+    n: Option<u32>,
+    stop: Option<StringOrList>,
+    stream: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct CompletionRequest {
+    model: Option<String>,
+    prompt: StringOrList,
+    max_tokens: Option<u32>,
+    temperature: Option<f32>,
+    n: Option<u32>,
+    stop: Option<StringOrList>,
+    stream: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct OpenAiEmbeddingsRequest {
+    model: Option<String>,
+    input: StringOrList,
+    dimensions: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct Usage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+}
+
+#[derive(Serialize)]
+struct ChatCompletionResponse {
+    id: String,
+    object: String,
+    created: i64,
+    model: String,
+    choices: Vec<ChatCompletionChoice>,
+    usage: Usage,
+}
+
+#[derive(Serialize)]
+struct ChatCompletionChoice {
+    index: u32,
+    message: ChatMessage,
+    finish_reason: String,
+}
+
+#[derive(Serialize)]
+struct CompletionResponse {
+    id: String,
+    object: String,
+    created: i64,
+    model: String,
+    choices: Vec<CompletionChoice>,
+    usage: Usage,
+}
+
+#[derive(Serialize)]
+struct CompletionChoice {
+    text: String,
+    index: u32,
+    logprobs: Option<serde_json::Value>,
+    finish_reason: String,
+}
+
+#[derive(Serialize)]
+struct EmbeddingList {
+    object: String,
+    data: Vec<EmbeddingData>,
+    model: String,
+    usage: EmbeddingUsage,
+}
+
+#[derive(Serialize)]
+struct EmbeddingData {
+    object: String,
+    index: u32,
+    embedding: Vec<f32>,
+}
+
+#[derive(Serialize)]
+struct EmbeddingUsage {
+    prompt_tokens: u32,
+    total_tokens: u32,
+}
+
+#[derive(Serialize)]
+struct OpenAiError {
+    error: OpenAiErrorBody,
+}
+
+#[derive(Serialize)]
+struct OpenAiErrorBody {
+    message: String,
+    #[serde(rename = "type")]
+    error_type: String,
+    param: Option<String>,
+    code: Option<String>,
+}
+
+fn invalid_request(message: &str, param: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(OpenAiError {
+        error: OpenAiErrorBody {
+            message: message.to_string(),
+            error_type: "invalid_request_error".to_string(),
+            param: Some(param.to_string()),
+            code: None,
+        },
+    })
+}
+
+// Request bodies that do not deserialize, in OpenAI's error shape so SDKs can report them
+pub fn json_error(error: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let response = HttpResponse::build(error.status_code()).json(OpenAiError {
+        error: OpenAiErrorBody {
+            message: error.to_string(),
+            error_type: "invalid_request_error".to_string(),
+            param: None,
+            code: None,
+        },
+    });
+    InternalError::from_response(error, response).into()
+}
+
+fn backend_error(error: &InferenceError) -> HttpResponse {
+    println!("⚠️  Inference backend error: {}", error);
+    match error {
+        InferenceError::UnknownModel(_) => HttpResponse::NotFound().json(OpenAiError {
+            error: OpenAiErrorBody {
+                message: error.to_string(),
+                error_type: "invalid_request_error".to_string(),
+                param: Some("model".to_string()),
+                code: Some("model_not_found".to_string()),
+            },
+        }),
+        _ => HttpResponse::BadGateway().json(OpenAiError {
+            error: OpenAiErrorBody {
+                message: error.to_string(),
+                error_type: "api_error".to_string(),
+                param: None,
+                code: None,
+            },
+        }),
+    }
+}
+
+fn context_length_exceeded(message: String, param: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(OpenAiError {
+        error: OpenAiErrorBody {
+            message,
+            error_type: "invalid_request_error".to_string(),
+            param: Some(param.to_string()),
+            code: Some("context_length_exceeded".to_string()),
+        },
+    })
+}
+
+// Cut the completion at the first stop sequence, for backends that ignore `stop`.
+// The upstream token count was for the uncut text, so it is recounted.
+fn apply_stop_sequences(completion: &mut Completion, stop: &[String]) {
+    let cut = stop.iter()
+        .filter(|sequence| !sequence.is_empty())
+        .filter_map(|sequence| completion.text.find(sequence.as_str()))
+        .min();
+
+    if let Some(position) = cut {
+        completion.text.truncate(position);
+        completion.completion_tokens = Some(TOKENIZER.count(&completion.text));
+        completion.finish_reason = "stop".to_string();
+    }
+}
+
+// Run `n` generations for the same params against the resolved backend
+async fn generate_choices(model: Option<&str>, params: &GenerationParams, n: u32) -> Result<(String, Vec<Completion>), InferenceError> {
+    let (backend, route) = INFERENCE_BACKENDS.resolve(model)?;
+
+    let runs = (0..n).map(|_| backend.generate(&route.upstream_model, params));
+    let mut completions = Vec::new();
+    for result in futures_util::future::join_all(runs).await {
+        let mut completion = result?;
+        apply_stop_sequences(&mut completion, &params.stop);
+        enforce_max_tokens(&mut completion, params.max_tokens);
+        completions.push(completion);
+    }
+
+    Ok((route.id, completions))
+}
+
+fn choice_count(n: Option<u32>) -> Option<u32> {
+    Some(n.unwrap_or(1)).filter(|count| (1..=MAX_CHOICES).contains(count))
+}
+
+fn invalid_choice_count() -> HttpResponse {
+    invalid_request(&format!("n must be between 1 and {}", MAX_CHOICES), "n")
+}
+
+pub async fn chat_completions(http_req: HttpRequest, req: web::Json<ChatCompletionRequest>) -> Result<HttpResponse> {
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+    let start_time = std::time::Instant::now();
+    let req = req.into_inner();
+
+    if req.messages.is_empty() {
+        return Ok(invalid_request("messages must contain at least one message", "messages"));
+    }
+    let Some(n) = choice_count(req.n) else {
+        return Ok(invalid_choice_count());
+    };
+
+    let params = GenerationParams {
+        messages: req.messages,
+        max_tokens: req.max_completion_tokens.or(req.max_tokens).unwrap_or(256),
+        temperature: req.temperature.unwrap_or(1.0),
+        stop: req.stop.map(StringOrList::into_vec).unwrap_or_default(),
+    };
+    let prompt_tokens: u32 = params.messages.iter().map(|message| TOKENIZER.count(&message.content)).sum();
+    if let Some(message) = context_window_violation(prompt_tokens, params.max_tokens) {
+        return Ok(context_length_exceeded(message, "messages"));
+    }
+
+    if req.stream.unwrap_or(false) {
+        if n > 1 {
+            return Ok(invalid_request("Streaming is only supported with n = 1", "n"));
+        }
+        return Ok(match INFERENCE_BACKENDS.resolve(req.model.as_deref()) {
+            Ok((backend, route)) => stream_text_generation(backend, route, params, None, start_time),
+            Err(e) => backend_error(&e),
+        });
+    }
+
+    let (model, completions) = match generate_choices(req.model.as_deref(), &params, n).await {
+        Ok(result) => result,
+        Err(e) => return Ok(backend_error(&e)),
+    };
+
+    let prompt_tokens = completions.iter()
+        .find_map(|completion| completion.prompt_tokens)
+        .unwrap_or(prompt_tokens);
+    let completion_tokens: u32 = completions.iter()
+        .map(|completion| completion.completion_tokens.unwrap_or_default())
+        .sum();
+
+    let choices = completions.into_iter().enumerate().map(|(index, completion)| ChatCompletionChoice {
+        index: index as u32,
+        message: ChatMessage { role: "assistant".to_string(), content: completion.text },
+        finish_reason: completion.finish_reason,
+    }).collect();
+
+    let response = ChatCompletionResponse {
+        id: format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+        object: "chat.completion".to_string(),
+        created: chrono::Utc::now().timestamp(),
+        model,
+        choices,
+        usage: Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        },
+    };
+
+    println!("Generated {} chat completion choice(s) in {}ms", n, start_time.elapsed().as_millis());
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn completions(http_req: HttpRequest, req: web::Json<CompletionRequest>) -> Result<HttpResponse> {
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+    let start_time = std::time::Instant::now();
+    let req = req.into_inner();
+
+    if req.stream.unwrap_or(false) {
+        return Ok(invalid_request("Streaming is only supported on /v1/chat/completions", "stream"));
+    }
+    let Some(n) = choice_count(req.n) else {
+        return Ok(invalid_choice_count());
+    };
+    let stop = req.stop.map(StringOrList::into_vec).unwrap_or_default();
+
+    let mut model = String::new();
+    let mut choices = Vec::new();
+    let mut prompt_tokens = 0;
+    let mut completion_tokens = 0;
+
+    // Choices are ordered prompt by prompt, n per prompt, as OpenAI does
+    for prompt in req.prompt.into_vec() {
+        let params = GenerationParams {
+            messages: vec![ChatMessage::user(&prompt)],
+            max_tokens: req.max_tokens.unwrap_or(16),
+            temperature: req.temperature.unwrap_or(1.0),
+            stop: stop.clone(),
+        };
+        let local_prompt_tokens = TOKENIZER.count(&prompt);
+        if let Some(message) = context_window_violation(local_prompt_tokens, params.max_tokens) {
+            return Ok(context_length_exceeded(message, "prompt"));
+        }
+
+        let (served_model, completions) = match generate_choices(req.model.as_deref(), &params, n).await {
+            Ok(result) => result,
+            Err(e) => return Ok(backend_error(&e)),
+        };
+        model = served_model;
+
+        prompt_tokens += completions.iter()
+            .find_map(|completion| completion.prompt_tokens)
+            .unwrap_or(local_prompt_tokens);
+        for completion in completions {
+            completion_tokens += completion.completion_tokens.unwrap_or_default();
+            choices.push(CompletionChoice {
+                text: completion.text,
+                index: choices.len() as u32,
+                logprobs: None,
+                finish_reason: completion.finish_reason,
+            });
+        }
+    }
+
+    let response = CompletionResponse {
+        id: format!("cmpl-{}", uuid::Uuid::new_v4().simple()),
+        object: "text_completion".to_string(),
+        created: chrono::Utc::now().timestamp(),
+        model,
+        choices,
+        usage: Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        },
+    };
+
+    println!("Generated {} completion choice(s) in {}ms", response.choices.len(), start_time.elapsed().as_millis());
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn embeddings(http_req: HttpRequest, req: web::Json<OpenAiEmbeddingsRequest>) -> Result<HttpResponse> {
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+    let start_time = std::time::Instant::now();
+    let req = req.into_inner();
+
+    let inputs = req.input.into_vec();
+    if inputs.is_empty() {
+        return Ok(invalid_request("input must not be empty", "input"));
+    }
+
+    let prompt_tokens: u32 = inputs.iter().map(|text| TOKENIZER.count(text)).sum();
+    let (model, dimensions) = (req.model, req.dimensions);
+    let result = web::block(move || EMBEDDERS.embed_batch(model.as_deref(), &inputs, dimensions)).await?;
+
+    let batch = match result {
+        Ok(batch) => batch,
+        Err(e @ EmbeddingError::UnknownModel(_)) => {
+            return Ok(HttpResponse::NotFound().json(OpenAiError {
+                error: OpenAiErrorBody {
+                    message: e.to_string(),
+                    error_type: "invalid_request_error".to_string(),
+                    param: Some("model".to_string()),
+                    code: Some("model_not_found".to_string()),
+                },
+            }));
+        },
+        Err(e @ EmbeddingError::InvalidDimensions { .. }) => return Ok(invalid_request(&e.to_string(), "dimensions")),
+        Err(e) => return Ok(invalid_request(&e.to_string(), "input")),
+    };
+
+    // The OpenAI schema has no per-item errors, so any failed input fails the request
+    let mut data = Vec::with_capacity(batch.results.len());
+    for (index, result) in batch.results.into_iter().enumerate() {
+        match result {
+            Ok(embedding) => data.push(EmbeddingData {
+                object: "embedding".to_string(),
+                index: index as u32,
+                embedding,
+            }),
+            Err(e) => return Ok(invalid_request(&format!("input[{}]: {}", index, e), "input")),
+        }
+    }
+    let model = batch.model;
+
+    let response = EmbeddingList {
+        object: "list".to_string(),
+        data,
+        model,
+        usage: EmbeddingUsage {
+            prompt_tokens,
+            total_tokens: prompt_tokens,
+        },
+    };
+
+    println!("Generated {} embeddings in {}ms", response.data.len(), start_time.elapsed().as_millis());
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn models(http_req: HttpRequest) -> Result<HttpResponse> {
+    if let Err(error_response) = validate_api_key_header(&http_req) {
+        return Ok(error_response);
+    }
+
+    let data: Vec<serde_json::Value> = INFERENCE_BACKENDS.routes().iter().map(|route| {
+        serde_json::json!({
+            "id": route.id,
+            "object": "model",
+            "created": 0,
+            "owned_by": route.backend,
+        })
+    }).collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "object": "list",
+        "data": data,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_sequences_recount_completion_tokens() {
+        let mut completion = Completion {
+            text: "Hei! Takk for kvitteringen.\n\nUser: og en til".to_string(),
+            prompt_tokens: Some(12),
+            completion_tokens: Some(40),
+            finish_reason: "length".to_string(),
+        };
+        apply_stop_sequences(&mut completion, &["\n\nUser:".to_string()]);
+
+        assert_eq!(completion.text, "Hei! Takk for kvitteringen.");
+        assert_eq!(completion.completion_tokens, Some(TOKENIZER.count("Hei! Takk for kvitteringen.")));
+        assert_eq!(completion.finish_reason, "stop");
+    }
+
+    #[test]
+    fn text_without_stop_sequence_keeps_upstream_count() {
+        let mut completion = Completion {
+            text: "Hei!".to_string(),
+            prompt_tokens: None,
+            completion_tokens: Some(3),
+            finish_reason: "stop".to_string(),
+        };
+        apply_stop_sequences(&mut completion, &["###".to_string(), String::new()]);
+        assert_eq!((completion.text.as_str(), completion.completion_tokens), ("Hei!", Some(3)));
+    }
+}
@@ -0,0 +1,116 @@
+// Norwegian organisation numbers (organisasjonsnummer) in document text.
+// They are nine digits printed in many ways ("Org.nr. 999 208 372 MVA",
+// "NO999208372MVA", "Foretaksregisteret 999.208.372"); the last digit is a
+// MOD11 check digit. VAT-registered sellers must print "MVA" after the number,
+// so the suffix (or the NO...MVA VAT id form) marks VAT registration.
+
+use regex::Regex;
+use serde::Serialize;
+
+const MOD11_WEIGHTS: [u32; 8] = [3, 2, 7, 6, 5, 4, 3, 2];
+
+lazy_static::lazy_static! {
+    static ref ORG_NUMBER: Regex = Regex::new(
+        r"(?i)(?:\b(?P<country>NO)\s?|\b)(?P<digits>\d{3}[ .]?\d{3}[ .]?\d{3})(?:\s*(?P<mva>MVA)\b|\b)"
+    ).unwrap();
+    static ref ORG_LABEL: Regex = Regex::new(r"(?i)\b(?:org\.?\s*nr|org\.?\s*nummer|organisasjonsnummer|foretaksregisteret|orgnr)\b").unwrap();
+}
+
+#[derive(Serialize, Clone)]
+pub struct OrgNumber {
+    pub number: String, // 9 digits
+    pub mod11_valid: bool,
+    pub vat_registered: bool, // printed with the MVA suffix
+}
+
+impl OrgNumber {
+    pub fn new(number: &str, vat_registered: bool) -> Self {
+        OrgNumber { number: number.to_string(), mod11_valid: is_valid(number), vat_registered }
+    }
+}
+
+struct Candidate {
+    org_number: OrgNumber,
+    // Preceded by "Org.nr" or similar, or written as a VAT id
+    labelled: bool,
+}
+
+// Every nine-digit number in the text, valid or not
+fn candidates(text: &str) -> Vec<Candidate> {
+    let mut found = Vec::new();
+    for line in text.lines() {
+        let label_end = ORG_LABEL.find(line).map(|label| label.end());
+        for captures in ORG_NUMBER.captures_iter(line) {
+            let digits: String = captures["digits"].chars().filter(char::is_ascii_digit).collect();
+            let vat_registered = captures.name("mva").is_some();
+            let start = captures.get(0).map(|m| m.start()).unwrap_or_default();
+            found.push(Candidate {
+                org_number: OrgNumber::new(&digits, vat_registered),
+                labelled: vat_registered || captures.name("country").is_some() || label_end.is_some_and(|end| end <= start),
+            });
+        }
+    }
+    found
+}
+
+// The seller's organisation number: a labelled number first, then any number
+// that passes MOD11. A labelled number with a bad check digit is returned as
+// such so it can be flagged; unlabelled ones are more likely phone or account
+// numbers and are ignored.
+pub fn find_org_number(text: &str) -> Option<OrgNumber> {
+    let found = candidates(text);
+    found.iter().find(|candidate| candidate.labelled && candidate.org_number.mod11_valid)
+        .or_else(|| found.iter().find(|candidate| candidate.org_number.mod11_valid))
+        .or_else(|| found.iter().find(|candidate| candidate.labelled))
+        .map(|candidate| candidate.org_number.clone())
+}
+
+// All nine-digit numbers in the text, for matching against known merchants
+pub fn org_numbers_in(text: &str) -> Vec<String> {
+    candidates(text).into_iter().map(|candidate| candidate.org_number.number).collect()
+}
+
+pub fn is_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() != 9 || number.chars().count() != 9 {
+        return false;
+    }
+    let sum: u32 = digits.iter().zip(MOD11_WEIGHTS).map(|(digit, weight)| digit * weight).sum();
+    match 11 - sum % 11 {
+        11 => digits[8] == 0,
+        10 => false, // numbers with check digit 10 are never issued
+        check => digits[8] == check,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_digit_is_mod11() {
+        assert!(is_valid("984661185"));
+        assert!(!is_valid("984661186"));
+        assert!(!is_valid("999208372"));
+    }
+
+    #[test]
+    fn remainder_zero_means_check_digit_zero() {
+        // 1*3 + 4*2 = 11
+        assert!(is_valid("100000040"));
+        assert!(!is_valid("100000041"));
+    }
+
+    #[test]
+    fn check_digit_ten_is_never_valid() {
+        // 4*3 = 12, leaving check digit 10
+        assert!((0..=9).all(|digit| !is_valid(&format!("00000040{}", digit))));
+    }
+
+    #[test]
+    fn only_nine_plain_digits_are_numbers() {
+        assert!(!is_valid("98466118"));
+        assert!(!is_valid("9846611850"));
+        assert!(!is_valid("984 661 185"));
+    }
+}
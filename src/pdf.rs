@@ -0,0 +1,171 @@
+// PDF invoices for document_processing. Text is taken from the PDF's own
+// text layer page by page; pages without one (scanned invoices) yield their
+// largest embedded image so it can go through preprocessing and OCR instead.
+//
+// Scanned pages are read from DCT (JPEG) images and from uncompressed or
+// Flate-compressed 1- and 8-bit Gray/RGB/CMYK images. JBIG2, CCITT and
+// JPEG 2000 scans are reported per page as unsupported.
+
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+use pdf_extract::{Document, Object, PlainTextOutput};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+// Larger documents are rejected rather than tying up a worker
+pub const MAX_PDF_PAGES: usize = 50;
+
+// A text layer with fewer letters or digits than this is treated as a scan
+const MIN_TEXT_CHARS: usize = 20;
+
+// An A4 page scanned at 600 dpi is 35 megapixels; larger images are not decoded
+pub const MAX_IMAGE_PIXELS: usize = 64_000_000;
+
+#[derive(Debug)]
+pub enum PdfError {
+    InvalidPdf(String),
+    Encrypted,
+    TooManyPages(usize),
+}
+
+impl fmt::Display for PdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PdfError::InvalidPdf(e) => write!(f, "pdf_data could not be read as a PDF: {}", e),
+            PdfError::Encrypted => write!(f, "pdf_data is password protected"),
+            PdfError::TooManyPages(pages) => write!(f, "PDF has {} pages, at most {} are processed", pages, MAX_PDF_PAGES),
+        }
+    }
+}
+
+pub struct PdfPage {
+    pub number: u32,
+    pub text: String,
+    // Largest embedded image of a page without a text layer
+    pub scan: Option<Result<DynamicImage, String>>,
+}
+
+pub fn read_pdf(bytes: &[u8]) -> Result<Vec<PdfPage>, PdfError> {
+    let mut document = Document::load_mem(bytes).map_err(|e| PdfError::InvalidPdf(e.to_string()))?;
+    // Many invoices are "encrypted" with an empty user password to block editing
+    if document.is_encrypted() && document.decrypt("").is_err() {
+        return Err(PdfError::Encrypted);
+    }
+
+    let pages = document.get_pages();
+    if pages.len() > MAX_PDF_PAGES {
+        return Err(PdfError::TooManyPages(pages.len()));
+    }
+
+    Ok(pages.into_iter().map(|(number, page_id)| {
+        let text = page_text(&document, number).unwrap_or_default();
+        let scan = if text.chars().filter(|c| c.is_alphanumeric()).count() < MIN_TEXT_CHARS {
+            match largest_page_image(&document, page_id) {
+                Ok(None) if !text.is_empty() => None,
+                Ok(None) => Some(Err("page has neither text nor images".to_string())),
+                Ok(Some(image)) => Some(Ok(image)),
+                Err(e) => Some(Err(e)),
+            }
+        } else {
+            None
+        };
+        PdfPage { number, text, scan }
+    }).collect())
+}
+
+// pdf-extract panics on some malformed fonts; treat that page as having no text
+fn page_text(document: &Document, number: u32) -> Option<String> {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut text = String::new();
+        pdf_extract::output_doc_page(document, &mut PlainTextOutput::new(&mut text), number).ok()?;
+        Some(text.trim().to_string())
+    })).ok().flatten()
+}
+
+fn largest_page_image(document: &Document, page_id: (u32, u16)) -> Result<Option<DynamicImage>, String> {
+    // Pages without an XObject resource dictionary have no images
+    let Ok(images) = document.get_page_images(page_id) else { return Ok(None) };
+    let Some(image) = images.iter().max_by_key(|image| image.width.saturating_mul(image.height)) else { return Ok(None) };
+
+    let filters = image.filters.clone().unwrap_or_default();
+    let (Ok(width), Ok(height)) = (u32::try_from(image.width), u32::try_from(image.height)) else {
+        return Err(format!("invalid image size {}x{}", image.width, image.height));
+    };
+
+    let decoded = match filters.last().map(String::as_str) {
+        Some("DCTDecode") => image::load_from_memory(image.content).map_err(|e| e.to_string()),
+        None | Some("FlateDecode") => {
+            let stream = document.get_object(image.id).and_then(Object::as_stream).map_err(|e| e.to_string())?;
+            let data = if filters.is_empty() {
+                stream.content.clone()
+            } else {
+                stream.decompressed_content().map_err(|e| e.to_string())?
+            };
+            raw_image(&data, width, height, image.bits_per_component.unwrap_or(8), image.color_space.as_deref())
+        },
+        Some(filter) => Err(format!("{} scans are not supported", filter)),
+    };
+    decoded.map(Some)
+}
+
+fn raw_image(data: &[u8], width: u32, height: u32, bits: i64, color_space: Option<&str>) -> Result<DynamicImage, String> {
+    let channels = match color_space {
+        Some("DeviceRGB") | Some("CalRGB") => 3,
+        Some("DeviceCMYK") => 4,
+        Some("DeviceGray") | Some("CalGray") | None => 1,
+        Some(other) => return Err(format!("{} images are not supported", other)),
+    };
+    let pixels = (width as usize).checked_mul(height as usize)
+        .filter(|pixels| *pixels <= MAX_IMAGE_PIXELS)
+        .ok_or_else(|| format!("{}x{} image is larger than {} pixels", width, height, MAX_IMAGE_PIXELS))?;
+
+    match (bits, channels) {
+        (1, 1) => {
+            // Rows are padded to whole bytes; 1 is white in DeviceGray
+            let row_bytes = width.div_ceil(8) as usize;
+            if data.len() < row_bytes * height as usize {
+                return Err("image data is truncated".to_string());
+            }
+            Ok(DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+                let byte = data[y as usize * row_bytes + x as usize / 8];
+                Luma([if byte & (0x80 >> (x % 8)) != 0 { 255 } else { 0 }])
+            })))
+        },
+        (8, 1) => ImageBuffer::<Luma<u8>, _>::from_raw(width, height, data.get(..pixels).map(<[u8]>::to_vec).unwrap_or_default())
+            .map(DynamicImage::ImageLuma8)
+            .ok_or_else(|| "image data is truncated".to_string()),
+        (8, 3) => ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, data.get(..pixels * 3).map(<[u8]>::to_vec).unwrap_or_default())
+            .map(DynamicImage::ImageRgb8)
+            .ok_or_else(|| "image data is truncated".to_string()),
+        (8, 4) => {
+            let cmyk = data.get(..pixels * 4).ok_or_else(|| "image data is truncated".to_string())?;
+            let rgb: Vec<u8> = cmyk.chunks_exact(4)
+                .flat_map(|p| {
+                    let k = 255 - p[3] as u16;
+                    [0, 1, 2].map(|i| ((255 - p[i] as u16) * k / 255) as u8)
+                })
+                .collect();
+            RgbImage::from_raw(width, height, rgb)
+                .map(DynamicImage::ImageRgb8)
+                .ok_or_else(|| "image data is truncated".to_string())
+        },
+        _ => Err(format!("{}-bit images with {} channels are not supported", bits, channels)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_images_are_rejected_before_allocating() {
+        assert!(raw_image(&[0; 16], u32::MAX, u32::MAX, 8, Some("DeviceRGB")).is_err());
+        assert!(raw_image(&[0; 16], 10_000, 10_000, 1, None).is_err());
+    }
+
+    #[test]
+    fn raw_gray_image_is_decoded() {
+        let image = raw_image(&[0, 128, 255, 64], 2, 2, 8, Some("DeviceGray")).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 2));
+        assert!(raw_image(&[0, 128], 2, 2, 8, Some("DeviceGray")).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+// Clean-up of receipt photos before OCR. Phone pictures arrive rotated, shot
+// at an angle, unevenly lit and sometimes out of focus; each step below fixes
+// one of those and records itself in `steps`:
+//   1. EXIF orientation     rotate/flip to how the camera was held
+//   2. perspective          find the paper and warp it to a flat rectangle
+//   3. deskew               straighten remaining text-line rotation
+//   4. contrast             stretch the 1st-99th percentile to the full range
+//   5. binarisation         local mean threshold, robust to shadows
+// Sharpness is measured as the variance of the Laplacian, before any
+// resampling, on a copy scaled to a fixed size so scores are comparable.
+
+use crate::ocr::OcrError;
+use crate::pdf::MAX_IMAGE_PIXELS;
+use image::imageops::{self, FilterType};
+use image::io::{Limits, Reader};
+use image::{DynamicImage, GrayImage, Luma};
+use imageproc::contrast::{otsu_level, stretch_contrast_mut, threshold};
+use imageproc::geometric_transformations::{rotate_about_center, warp_into, Interpolation, Projection};
+use imageproc::integral_image::{integral_image, sum_image_pixels};
+use imageproc::region_labelling::{connected_components, Connectivity};
+use std::io::Cursor;
+
+// Larger photos are scaled down first; more detail does not help Tesseract
+const MAX_SIDE: u32 = 3000;
+
+// Long side of the working copies used for measuring and detection
+const SHARPNESS_SIDE: u32 = 1000;
+const DETECTION_SIDE: u32 = 600;
+
+// Laplacian variance below this is blurry, above SHARP_THRESHOLD crisp
+const BLURRY_THRESHOLD: f32 = 60.0;
+const SHARP_THRESHOLD: f32 = 150.0;
+
+// The paper must cover this share of the frame to be treated as the receipt,
+// and less than the upper bound to be worth cropping
+const MIN_PAPER_AREA: f32 = 0.15;
+const MAX_PAPER_AREA: f32 = 0.92;
+
+const MAX_SKEW_DEGREES: f32 = 10.0;
+const SKEW_STEP_DEGREES: f32 = 0.25;
+const MIN_SKEW_DEGREES: f32 = 0.3;
+
+// Binarisation window (as a share of the long side) and offset below the local mean
+const BINARIZE_WINDOW: f32 = 1.0 / 40.0;
+const BINARIZE_OFFSET: f32 = 12.0;
+
+pub struct PreprocessedImage {
+    pub image: GrayImage,
+    pub steps: Vec<String>,
+    pub sharpness: f32,
+    pub original_width: u32,
+    pub original_height: u32,
+}
+
+impl PreprocessedImage {
+    pub fn quality_rating(&self) -> &'static str {
+        if self.sharpness >= SHARP_THRESHOLD {
+            "High"
+        } else if self.sharpness >= BLURRY_THRESHOLD {
+            "Medium"
+        } else {
+            "Low"
+        }
+    }
+
+    pub fn is_blurry(&self) -> bool {
+        self.sharpness < BLURRY_THRESHOLD
+    }
+}
+
+pub fn preprocess(bytes: &[u8]) -> Result<PreprocessedImage, OcrError> {
+    let decoded = decode(bytes)?;
+    let (original_width, original_height) = (decoded.width(), decoded.height());
+    let mut steps = Vec::new();
+
+    let decoded = match exif_orientation(bytes) {
+        Some(orientation) if orientation != 1 => {
+            steps.push(format!("exif_orientation({})", orientation));
+            apply_orientation(decoded, orientation)
+        },
+        _ => decoded,
+    };
+
+    Ok(clean_up(decoded, steps, original_width, original_height))
+}
+
+// Photos are held to the same size bound as scans in a PDF. The size is read
+// from the header, and the decoder is then limited to it, so an oversized
+// image is refused before its pixels are allocated
+fn decode(bytes: &[u8]) -> Result<DynamicImage, OcrError> {
+    let invalid = |e: image::ImageError| OcrError::InvalidImage(e.to_string());
+    let reader = || Reader::new(Cursor::new(bytes)).with_guessed_format().map_err(|e| OcrError::InvalidImage(e.to_string()));
+
+    let (width, height) = reader()?.into_dimensions().map_err(invalid)?;
+    if (width as usize).checked_mul(height as usize).is_none_or(|pixels| pixels > MAX_IMAGE_PIXELS) {
+        return Err(OcrError::InvalidImage(format!("{}x{} image is larger than {} pixels", width, height, MAX_IMAGE_PIXELS)));
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(width);
+    limits.max_image_height = Some(height);
+    let mut reader = reader()?;
+    reader.limits(limits);
+    reader.decode().map_err(invalid)
+}
+
+// For images that were not read from a file, such as scans embedded in a PDF
+pub fn preprocess_image(image: DynamicImage) -> PreprocessedImage {
+    let (width, height) = (image.width(), image.height());
+    clean_up(image, Vec::new(), width, height)
+}
+
+fn clean_up(image: DynamicImage, mut steps: Vec<String>, original_width: u32, original_height: u32) -> PreprocessedImage {
+    let mut gray = image.to_luma8();
+    let sharpness = laplacian_variance(&resize_to(&gray, SHARPNESS_SIDE));
+
+    if gray.width().max(gray.height()) > MAX_SIDE {
+        gray = resize_to(&gray, MAX_SIDE);
+        steps.push(format!("downscaled({}x{})", gray.width(), gray.height()));
+    }
+
+    if let Some(corrected) = correct_perspective(&gray) {
+        gray = corrected;
+        steps.push("perspective_corrected".to_string());
+    }
+
+    let angle = estimate_skew(&gray);
+    if angle.abs() >= MIN_SKEW_DEGREES {
+        gray = rotate_about_center(&gray, -angle.to_radians(), Interpolation::Bilinear, Luma([255]));
+        steps.push(format!("deskewed({:.2}°)", angle));
+    }
+
+    let (low, high) = percentile_range(&gray, 0.01, 0.99);
+    if high > low && (low > 0 || high < 255) {
+        stretch_contrast_mut(&mut gray, low, high);
+        steps.push("contrast_normalized".to_string());
+    }
+
+    gray = binarize(&gray);
+    steps.push("binarized".to_string());
+
+    PreprocessedImage {
+        image: gray,
+        steps,
+        sharpness,
+        original_width,
+        original_height,
+    }
+}
+
+fn exif_orientation(bytes: &[u8]) -> Option<u32> {
+    let exif = exif::Reader::new().read_from_container(&mut Cursor::new(bytes)).ok()?;
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?.value.get_uint(0)
+}
+
+// EXIF orientations 2-8, see the TIFF 6.0 specification
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+// Scale so the long side is `side` pixels, keeping the aspect ratio
+fn resize_to(image: &GrayImage, side: u32) -> GrayImage {
+    let (width, height) = image.dimensions();
+    let scale = side as f32 / width.max(height) as f32;
+    let target = ((width as f32 * scale).round().max(1.0) as u32, (height as f32 * scale).round().max(1.0) as u32);
+    imageops::resize(image, target.0, target.1, FilterType::Triangle)
+}
+
+fn laplacian_variance(image: &GrayImage) -> f32 {
+    let (width, height) = image.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let pixel = |x: u32, y: u32| image.get_pixel(x, y)[0] as f64;
+    let mut sum = 0.0;
+    let mut sum_squares = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = pixel(x - 1, y) + pixel(x + 1, y) + pixel(x, y - 1) + pixel(x, y + 1) - 4.0 * pixel(x, y);
+            sum += laplacian;
+            sum_squares += laplacian * laplacian;
+        }
+    }
+
+    let count = ((width - 2) * (height - 2)) as f64;
+    let mean = sum / count;
+    (sum_squares / count - mean * mean) as f32
+}
+
+// Receipts are lighter than the table they lie on: take the largest bright
+// region, use its extreme points as the corners and warp them to a rectangle
+fn correct_perspective(image: &GrayImage) -> Option<GrayImage> {
+    let small = resize_to(image, DETECTION_SIDE);
+    let scale = image.width() as f32 / small.width() as f32;
+    let blurred = imageproc::filter::gaussian_blur_f32(&small, 2.0);
+    let mask = threshold(&blurred, otsu_level(&blurred));
+    let labels = connected_components(&mask, Connectivity::Eight, Luma([0u8]));
+
+    let mut areas: std::collections::HashMap<u32, usize> = std::collections::HashMap::new();
+    for label in labels.pixels().map(|p| p[0]).filter(|&label| label != 0) {
+        *areas.entry(label).or_insert(0) += 1;
+    }
+    let (&paper, _) = areas.iter().max_by_key(|(_, &area)| area)?;
+
+    // Corners maximise/minimise x+y and x-y over the region
+    let (mut top_left, mut top_right, mut bottom_right, mut bottom_left) = ((0.0, 0.0), (0.0, 0.0), (0.0, 0.0), (0.0, 0.0));
+    let (mut min_sum, mut max_sum, mut min_diff, mut max_diff) = (f32::MAX, f32::MIN, f32::MAX, f32::MIN);
+    for (x, y, label) in labels.enumerate_pixels() {
+        if label[0] != paper {
+            continue;
+        }
+        let (x, y) = (x as f32, y as f32);
+        if x + y < min_sum { min_sum = x + y; top_left = (x, y); }
+        if x + y > max_sum { max_sum = x + y; bottom_right = (x, y); }
+        if x - y > max_diff { max_diff = x - y; top_right = (x, y); }
+        if x - y < min_diff { min_diff = x - y; bottom_left = (x, y); }
+    }
+    let corners = [top_left, top_right, bottom_right, bottom_left];
+
+    // Printed text leaves holes in the region, so judge coverage by the quad
+    let quad_area = (0..4).map(|i| {
+        let (a, b) = (corners[i], corners[(i + 1) % 4]);
+        a.0 * b.1 - b.0 * a.1
+    }).sum::<f32>().abs() / 2.0;
+    let coverage = quad_area / (small.width() * small.height()) as f32;
+    if !(MIN_PAPER_AREA..=MAX_PAPER_AREA).contains(&coverage) {
+        return None;
+    }
+    let corners = corners.map(|(x, y)| (x * scale, y * scale));
+
+    let distance = |a: (f32, f32), b: (f32, f32)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+    let width = distance(corners[0], corners[1]).max(distance(corners[3], corners[2])).round();
+    let height = distance(corners[0], corners[3]).max(distance(corners[1], corners[2])).round();
+    if width < 50.0 || height < 50.0 {
+        return None;
+    }
+
+    let target = [(0.0, 0.0), (width - 1.0, 0.0), (width - 1.0, height - 1.0), (0.0, height - 1.0)];
+    let projection = Projection::from_control_points(corners, target)?;
+    let mut out = GrayImage::new(width as u32, height as u32);
+    warp_into(image, &projection, Interpolation::Bilinear, Luma([255]), &mut out);
+    Some(out)
+}
+
+// Projection-profile skew estimate: text lines give the sharpest row
+// histogram when rotated level, so try angles and keep the peakiest profile
+fn estimate_skew(image: &GrayImage) -> f32 {
+    let small = resize_to(image, DETECTION_SIDE);
+    let level = otsu_level(&small);
+    let dark: Vec<(f32, f32)> = small.enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[0] < level)
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    if dark.len() < 100 {
+        return 0.0;
+    }
+
+    let rows = (small.width() + small.height()) as usize * 2;
+    let offset = small.width().max(small.height()) as f32;
+    let profile_score = |degrees: f32| -> f64 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut histogram = vec![0u32; rows];
+        for &(x, y) in &dark {
+            let row = (y * cos - x * sin + offset).max(0.0) as usize;
+            histogram[row.min(rows - 1)] += 1;
+        }
+        histogram.iter().map(|&count| (count as f64).powi(2)).sum()
+    };
+
+    let steps = (MAX_SKEW_DEGREES / SKEW_STEP_DEGREES) as i32;
+    (-steps..=steps)
+        .map(|step| step as f32 * SKEW_STEP_DEGREES)
+        .map(|degrees| (degrees, profile_score(degrees)))
+        .fold((0.0, f64::MIN), |best, candidate| if candidate.1 > best.1 { candidate } else { best })
+        .0
+}
+
+fn percentile_range(image: &GrayImage, low: f32, high: f32) -> (u8, u8) {
+    let mut histogram = [0usize; 256];
+    for pixel in image.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let total = image.pixels().len() as f32;
+    let level_at = |fraction: f32| {
+        let mut seen = 0;
+        for (level, &count) in histogram.iter().enumerate() {
+            seen += count;
+            if seen as f32 >= fraction * total {
+                return level as u8;
+            }
+        }
+        255
+    };
+    (level_at(low), level_at(high))
+}
+
+// Bradley-Roth adaptive threshold: a pixel is ink when it is clearly darker
+// than the mean of its neighbourhood
+fn binarize(image: &GrayImage) -> GrayImage {
+    let (width, height) = image.dimensions();
+    let radius = ((width.max(height) as f32 * BINARIZE_WINDOW) as u32).max(4);
+    let integral = integral_image::<_, u32>(image);
+
+    GrayImage::from_fn(width, height, |x, y| {
+        let (left, top) = (x.saturating_sub(radius), y.saturating_sub(radius));
+        let (right, bottom) = ((x + radius).min(width - 1), (y + radius).min(height - 1));
+        let count = ((right - left + 1) * (bottom - top + 1)) as f32;
+        let mean = sum_image_pixels(&integral, left, top, right, bottom)[0] as f32 / count;
+        if (image.get_pixel(x, y)[0] as f32) < mean - BINARIZE_OFFSET { Luma([0]) } else { Luma([255]) }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 24-bit BMP header without pixel data
+    fn bmp_header(width: i32, height: i32) -> Vec<u8> {
+        let mut bytes = b"BM".to_vec();
+        bytes.extend(54u32.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(54u32.to_le_bytes());
+        bytes.extend(40u32.to_le_bytes());
+        bytes.extend(width.to_le_bytes());
+        bytes.extend(height.to_le_bytes());
+        bytes.extend(1u16.to_le_bytes());
+        bytes.extend(24u16.to_le_bytes());
+        bytes.extend([0; 24]);
+        bytes
+    }
+
+    #[test]
+    fn oversized_photos_are_rejected_from_the_header() {
+        match decode(&bmp_header(10_000, 10_000)) {
+            Err(OcrError::InvalidImage(message)) => assert_eq!(message, "10000x10000 image is larger than 64000000 pixels"),
+            _ => panic!("a 100 megapixel image was accepted"),
+        }
+        // Within the bound the missing pixel data is what fails
+        assert!(matches!(decode(&bmp_header(100, 100)), Err(OcrError::InvalidImage(_))));
+    }
+
+    #[test]
+    fn photos_within_the_bound_are_decoded() {
+        let mut png = Vec::new();
+        GrayImage::from_pixel(40, 30, Luma([255]))
+            .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+
+        let preprocessed = preprocess(&png).map_err(|e| e.to_string()).unwrap();
+        assert_eq!((preprocessed.original_width, preprocessed.original_height), (40, 30));
+    }
+}
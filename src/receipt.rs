@@ -0,0 +1,397 @@
+// Line items from receipt text. Norwegian till receipts print one article per
+// line with its amount at the right, optionally followed or preceded by a
+// quantity line ("2 X 12,90", "0,456 kg x 39,90 kr/kg"). Discounts ("2 FOR 1",
+// MEDLEM...) and pant are separate lines; the receipt ends at TOTALT/SUM,
+// after which only payment and MVA summary lines follow.
+//
+// The line items are checked against the stated total, so OCR errors and
+// items the parser missed show up as a mismatch instead of a wrong sum.
+//
+// VAT per rate is read from the MVA summary the till prints below the total
+// ("Mva-grunnlag 15% 160,70", "Herav mva 25% 17,80" or a Sats/Grunnlag/Mva
+// table); without one it is estimated from the classified line items.
+//
+// Cash totals may be rounded to whole kroner (øresavrunding), so a printed
+// total that equals the rounded sum of the items also matches.
+
+use regex::Regex;
+use crate::amount::parse_amount;
+use crate::money::Money;
+use crate::vat_rates::EffectiveRates;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+lazy_static::lazy_static! {
+    // "MELK 1L   21,90", "SMØR 1 234,50 15%", "GRILL 1.499,00", "RABATT 12,90-", "BOLLE 25,-"
+    static ref TRAILING_AMOUNT: Regex = Regex::new(
+        r"(?i)^(?P<body>.*?)\s*(?P<amount>-?\d{1,3}(?:[ .]\d{3})+,\d{2}|-?\d+[.,]\d{2}|-?\d+,-)(?P<negative>-)?\s*(?:kr|nok)?\s*(?:(?P<vat>\d{1,2})\s*%|[a-z*#])?$"
+    ).unwrap();
+    // "2 X 12,90", "3 stk à 10,00", "0,456 kg x 39,90 kr/kg", optionally after a description
+    static ref QUANTITY: Regex = Regex::new(
+        r"(?i)^(?P<description>.*?)\s*(?P<quantity>\d+(?:[.,]\d+)?)\s*(?:kg|stk|st|x|\*)\s*(?:x|à|a|@|\*)?\s*(?P<price>\d+[.,]\d{2})\s*(?:kr)?\s*(?:/\s*(?:kg|stk))?$"
+    ).unwrap();
+    // "15%", "15,00 %"
+    static ref PERCENT: Regex = Regex::new(r"(?P<rate>\d{1,2})(?:[.,]0+)?\s*%").unwrap();
+    static ref AMOUNT: Regex = Regex::new(r"-?\d{1,3}(?:[ .]\d{3})+,\d{2}|-?\d+[.,]\d{2}").unwrap();
+    static ref MULTI_BUY: Regex = Regex::new(r"(?i)\b\d+\s*FOR\s*\d+\b").unwrap();
+    // Header fields, matched with their punctuation or number so that "ORG. EPLEJUICE"
+    // and "KASSE ØL 24X0,33" stay items: "Org.nr. 984 661 185", "Dato: 17.05.2024",
+    // "Kl 14:32", "Kasse 3", "Bong nr: 1234"
+    static ref HEADER_FIELD: Regex = Regex::new(
+        r"(?i)\b(?:ORG\.?\s*NR|ORGANISASJONS(?:NR|NUMMER)|DATO\s*[:.]?\s*\d|KL\.?\s*:?\s*\d{1,2}[:.]\d{2}|(?:KASSE|BONG)\s*(?:NR\.?)?\s*[:.]?\s*\d+(?:\s|$))"
+    ).unwrap();
+}
+
+const TOTAL_KEYWORDS: &[&str] = &["TOTALT", "TOTAL", "SUMMA", "SUM", "Å BETALE", "BELØP"];
+const SUBTOTAL_KEYWORDS: &[&str] = &["SUBTOTAL", "DELSUM", "MELLOMSUM"];
+const DISCOUNT_KEYWORDS: &[&str] = &["RABATT", "MEDLEM", "TILBUD", "KUPONG", "BONUS", "AVSLAG"];
+// Payment, MVA summary and header lines carry amounts that are not purchases
+const SKIPPED_KEYWORDS: &[&str] = &[
+    "KORT", "BANKAXEPT", "BANKKORT", "VISA", "MASTERCARD", "KONTANT", "VEKSEL", "TILBAKE", "VIPPS",
+    "MVA", "HERAV", "GRUNNLAG", "NETTO", "TLF", "KVITTERING", "AVRUNDING", "ØRESAVRUNDING",
+];
+
+// Summary lines may round each amount to the øre
+const SUMMARY_TOLERANCE: Money = Money::from_ore(5);
+
+// Item keywords per VAT category, also matched inside compounds such as GROVBRØD
+const VAT_KEYWORDS: &[(&str, &[&str])] = &[
+    ("food", &[
+        "MELK", "BRØD", "OST", "EGG", "SMØR", "YOGHURT", "FLØTE", "RØMME", "KJØTT", "KYLLING", "FISK",
+        "LAKS", "TORSK", "REKER", "PØLSE", "BACON", "SKINKE", "KARBONADE", "KJØTTDEIG", "RIBBE", "PINNEKJØTT",
+        "LAM", "FRUKT", "BANAN", "EPLE", "APPELSIN", "DRUER", "POTET", "GULROT", "LØK", "TOMAT", "AGURK",
+        "SALAT", "PASTA", "RIS", "MEL", "SUKKER", "HAVRE", "MÜSLI", "KAFFE", "TE", "JUICE", "BRUS", "COLA", "FANTA", "SOLO", "VANN",
+        "SJOKOLADE", "GODTERI", "SNACKS", "CHIPS", "PIZZA", "TACO", "BOLLE", "KAKE", "KNEKKEBRØD",
+        "PÅLEGG", "SYLTETØY", "IS", "MAT",
+    ]),
+    ("standard", &[
+        "BATTERI", "LYSPÆRE", "TOALETTPAPIR", "TØRKERULL", "SERVIETT", "SÅPE", "SJAMPO", "TANNKREM",
+        "TANNBØRSTE", "BLEIE", "VASKEMIDDEL", "OPPVASK", "ZALO", "BÆREPOSE", "PLASTPOSE", "POSE",
+        "STEARINLYS", "BLOMST", "ØL", "VIN", "CIDER", "SNUS", "SIGARETT", "TOBAKK", "BLADER", "LEKE",
+    ]),
+    ("low", &["KINO", "BILLETT", "BUSS", "TOG", "FERGE", "HOTELL", "OVERNATTING", "TAXI"]),
+    ("zero", &["AVIS", "BOK", "BØKER"]),
+];
+
+#[derive(Serialize, Clone)]
+pub struct ReceiptLineItem {
+    pub description: String,
+    pub quantity: f32,
+    pub unit_price: Money,
+    pub line_total: Money,
+    pub vat_rate: Option<u8>, // printed on the line, else the rate of the category the description implies
+}
+
+#[derive(Serialize, Clone)]
+pub struct ReceiptAdjustment {
+    pub description: String,
+    pub amount: Money, // negative for discounts and returned pant
+    pub applies_to: Option<usize>, // index into line_items of the item above it
+}
+
+#[derive(Serialize, Clone, Copy)]
+pub struct VatRateBreakdown {
+    pub rate: u8,
+    pub base_amount: Money, // excluding VAT
+    pub vat_amount: Money,
+}
+
+impl VatRateBreakdown {
+    // Split an amount including VAT into base and VAT
+    pub fn from_gross(rate: u8, gross: Money) -> Self {
+        let vat_amount = gross.vat_from_gross(rate);
+        VatRateBreakdown { rate, base_amount: gross - vat_amount, vat_amount }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct ReceiptBreakdown {
+    pub line_items: Vec<ReceiptLineItem>,
+    pub discounts: Vec<ReceiptAdjustment>,
+    pub deposits: Vec<ReceiptAdjustment>, // pant
+    pub subtotal: Money, // printed subtotal, else the sum of the line items
+    pub total: Option<Money>, // printed TOTALT/SUM
+    pub computed_total: Money, // items + discounts + pant
+    pub totals_match: Option<bool>, // None when no total was printed
+}
+
+// None when the text holds no priced lines, or lines adding up beyond
+// MAX_KRONER. Items are classified with the VAT rates in force on the
+// receipt's date.
+pub fn parse_receipt(text: &str, rates: &EffectiveRates) -> Option<ReceiptBreakdown> {
+    let mut line_items: Vec<ReceiptLineItem> = Vec::new();
+    let mut discounts = Vec::new();
+    let mut deposits = Vec::new();
+    let mut subtotal = None;
+    let mut total = None;
+    // Description printed on its own line above the quantity and amount
+    let mut pending_description: Option<String> = None;
+
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let upper = line.to_uppercase();
+
+        // "2 X 12,90" below (or above) an item: a unit price, not a line total
+        if let Some((_, quantity, unit_price)) = parse_quantity(line).filter(|(description, _, _)| description.is_empty()) {
+            if let Some(description) = pending_description.take() {
+                line_items.push(item(description, quantity, unit_price, unit_price.scale(quantity as f64), None, rates));
+            } else if let Some(last) = line_items.last_mut().filter(|last| last.line_total == unit_price.scale(quantity as f64)) {
+                last.quantity = quantity;
+                last.unit_price = unit_price;
+            }
+            continue;
+        }
+
+        let Some(captures) = TRAILING_AMOUNT.captures(line) else {
+            if line.chars().any(char::is_alphabetic) && !is_skipped(&upper) {
+                pending_description = Some(line.to_string());
+            }
+            continue;
+        };
+        let Some(mut amount) = parse_amount(&captures["amount"]) else { continue };
+        if captures.name("negative").is_some() {
+            amount = -amount.abs();
+        }
+        let body = captures["body"].trim();
+        let body_upper = body.to_uppercase();
+
+        if starts_with_keyword(&body_upper, SUBTOTAL_KEYWORDS) {
+            subtotal = Some(amount);
+        } else if starts_with_keyword(&body_upper, TOTAL_KEYWORDS) {
+            total = Some(amount);
+            break;
+        } else if is_skipped(&upper) {
+            continue;
+        } else if body_upper.split_whitespace().any(|word| word.starts_with("PANT")) {
+            deposits.push(adjustment(body, amount, &line_items));
+        } else if amount < Money::ZERO || DISCOUNT_KEYWORDS.iter().any(|keyword| body_upper.contains(keyword)) || MULTI_BUY.is_match(&body_upper) {
+            discounts.push(adjustment(body, -amount.abs(), &line_items));
+        } else {
+            let printed_rate = captures.name("vat").and_then(|rate| rate.as_str().parse().ok());
+            let (description, quantity, unit_price) = parse_quantity(body).unwrap_or((body.to_string(), 1.0, amount));
+            let description = if description.is_empty() {
+                pending_description.take().unwrap_or_default()
+            } else {
+                description
+            };
+            line_items.push(item(description, quantity, unit_price, amount, printed_rate, rates));
+        }
+        pending_description = None;
+    }
+
+    if line_items.is_empty() {
+        return None;
+    }
+    // Amounts adding up to more than MAX_KRONER are OCR noise, not a purchase
+    let amounts = line_items.iter().map(|item| item.line_total)
+        .chain(discounts.iter().chain(&deposits).map(|adjustment| adjustment.amount));
+    Money::checked_sum(amounts.map(Money::abs))?;
+
+    let items_sum: Money = line_items.iter().map(|item| item.line_total).sum();
+    let computed_total = items_sum
+        + discounts.iter().map(|discount| discount.amount).sum()
+        + deposits.iter().map(|deposit| deposit.amount).sum();
+
+    Some(ReceiptBreakdown {
+        line_items,
+        discounts,
+        deposits,
+        subtotal: subtotal.unwrap_or(items_sum),
+        total,
+        computed_total,
+        totals_match: total.map(|total| total == computed_total || total == computed_total.round_to_krone()),
+    })
+}
+
+impl ReceiptBreakdown {
+    // Gross amounts per VAT rate from the classified items. Discounts reduce the
+    // rate of the item they follow, pant is outside the VAT system (0%), and
+    // unclassified items fall back to `default_rate`.
+    pub fn vat_by_item_rate(&self, default_rate: u8) -> Vec<VatRateBreakdown> {
+        let mut gross: BTreeMap<u8, Money> = BTreeMap::new();
+        let item_rate = |index: Option<usize>| index
+            .and_then(|index| self.line_items.get(index))
+            .and_then(|item| item.vat_rate)
+            .unwrap_or(default_rate);
+
+        for item in &self.line_items {
+            *gross.entry(item.vat_rate.unwrap_or(default_rate)).or_default() += item.line_total;
+        }
+        for discount in &self.discounts {
+            *gross.entry(item_rate(discount.applies_to)).or_default() += discount.amount;
+        }
+        for deposit in &self.deposits {
+            *gross.entry(0).or_default() += deposit.amount;
+        }
+
+        gross.into_iter()
+            .filter(|(_, gross)| !gross.is_zero())
+            .map(|(rate, gross)| VatRateBreakdown::from_gross(rate, gross))
+            .collect()
+    }
+}
+
+// The MVA summary printed on the receipt, one entry per rate; empty when there is none
+pub fn parse_vat_summary(text: &str) -> Vec<VatRateBreakdown> {
+    let mut rates: BTreeMap<u8, (Option<Money>, Option<Money>)> = BTreeMap::new();
+    // Inside a table whose header names the columns (Sats, Grunnlag, Mva, Sum)
+    let mut in_table = false;
+
+    for line in text.lines().map(str::trim) {
+        let upper = line.to_uppercase();
+        let is_vat_line = ["MVA", "MOMS", "GRUNNLAG", "HERAV"].iter().any(|keyword| upper.contains(keyword));
+        let Some(percent) = PERCENT.captures(&upper) else {
+            in_table = (upper.contains("GRUNNLAG") || upper.contains("SATS")) && !AMOUNT.is_match(&upper);
+            continue;
+        };
+        if !is_vat_line && !in_table {
+            continue;
+        }
+        let Some(rate) = percent["rate"].parse::<u8>().ok().filter(|rate| *rate <= 25) else { continue };
+
+        let without_percent = PERCENT.replace_all(&upper, " ");
+        let amounts: Vec<Money> = AMOUNT.find_iter(&without_percent).filter_map(|amount| parse_amount(amount.as_str())).collect();
+        let entry = rates.entry(rate).or_default();
+        match amounts.as_slice() {
+            [] => {},
+            [amount] if upper.contains("GRUNNLAG") => entry.0 = Some(*amount),
+            [amount] => entry.1 = Some(*amount),
+            [first, vat, ..] => {
+                // Base then VAT, or gross then VAT as in "15% av 184,80 = 24,10"
+                let is_gross = rate > 0 && !amounts_within(first.vat_from_net(rate), *vat)
+                    && amounts_within(first.vat_from_gross(rate), *vat);
+                entry.0 = Some(if is_gross { *first - *vat } else { *first });
+                entry.1 = Some(*vat);
+            },
+        }
+    }
+
+    rates.into_iter()
+        .filter_map(|(rate, amounts)| {
+            let (base_amount, vat_amount) = match amounts {
+                (Some(base), Some(vat)) => (base, vat),
+                (Some(base), None) => (base, base.vat_from_net(rate)),
+                (None, Some(vat)) => (vat.base_from_vat(rate)?, vat),
+                _ => return None,
+            };
+            Some(VatRateBreakdown { rate, base_amount, vat_amount })
+        })
+        .collect()
+}
+
+// VAT category an item description implies, if any keyword matches
+fn classify_item_vat_category(description: &str) -> Option<&'static str> {
+    let upper = description.to_uppercase();
+    VAT_KEYWORDS.iter()
+        .find(|(_, keywords)| keywords.iter().any(|keyword| {
+            upper.split(|c: char| !c.is_alphanumeric()).any(|word| {
+                // Short keywords such as IS or TE only match whole words
+                if keyword.chars().count() < 4 { word == *keyword } else { word.contains(keyword) }
+            })
+        }))
+        .map(|(category, _)| *category)
+}
+
+fn item(description: String, quantity: f32, unit_price: Money, line_total: Money, printed_rate: Option<u8>, rates: &EffectiveRates) -> ReceiptLineItem {
+    let vat_rate = printed_rate.or_else(|| classify_item_vat_category(&description).and_then(|category| rates.rate(category)));
+    ReceiptLineItem { description, quantity, unit_price, line_total, vat_rate }
+}
+
+fn adjustment(description: &str, amount: Money, line_items: &[ReceiptLineItem]) -> ReceiptAdjustment {
+    ReceiptAdjustment {
+        description: description.to_string(),
+        amount,
+        applies_to: line_items.len().checked_sub(1),
+    }
+}
+
+fn parse_quantity(text: &str) -> Option<(String, f32, Money)> {
+    let captures = QUANTITY.captures(text)?;
+    Some((
+        captures["description"].trim().to_string(),
+        parse_number(&captures["quantity"])?,
+        parse_amount(&captures["price"])?,
+    ))
+}
+
+// Quantities such as "2" or "0,456" (kg)
+fn parse_number(text: &str) -> Option<f32> {
+    text.replace(',', ".").parse().ok()
+}
+
+fn amounts_within(a: Money, b: Money) -> bool {
+    (a - b).abs() <= SUMMARY_TOLERANCE
+}
+
+// "SUM 245,00" but not "SUMMER DRINK" or "TOTALRENOVERING"
+fn starts_with_keyword(text: &str, keywords: &[&str]) -> bool {
+    keywords.iter().any(|keyword| {
+        text.strip_prefix(keyword).is_some_and(|rest| !rest.starts_with(char::is_alphanumeric))
+    })
+}
+
+fn is_skipped(upper: &str) -> bool {
+    has_keyword(upper, SKIPPED_KEYWORDS) || HEADER_FIELD.is_match(upper)
+}
+
+fn has_keyword(text: &str, keywords: &[&str]) -> bool {
+    text.split(|c: char| !c.is_alphanumeric()).any(|word| keywords.contains(&word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vat_rates::built_in_rates;
+
+    fn parse(text: &str) -> ReceiptBreakdown {
+        parse_receipt(text, &built_in_rates("2024-06-01".parse().unwrap())).unwrap()
+    }
+
+    fn descriptions(receipt: &ReceiptBreakdown) -> Vec<&str> {
+        receipt.line_items.iter().map(|item| item.description.as_str()).collect()
+    }
+
+    #[test]
+    fn total_keywords_need_a_word_boundary() {
+        let receipt = parse("SUMMER DRINK 25,00\nTOTALRENOVERING 100,00\nMELK 21,90\nSUM 146,90");
+        assert_eq!(descriptions(&receipt), ["SUMMER DRINK", "TOTALRENOVERING", "MELK"]);
+        assert_eq!(receipt.total, Some(Money::from_ore(14690)));
+        assert_eq!(receipt.totals_match, Some(true));
+    }
+
+    #[test]
+    fn receipts_beyond_the_amount_cap_are_not_parsed() {
+        let text = "VARE 9 000 000 000 000,00\n".repeat(10_000);
+        assert!(parse_receipt(&text, &built_in_rates("2024-06-01".parse().unwrap())).is_none());
+    }
+
+    #[test]
+    fn header_fields_are_skipped_but_look_alike_items_kept() {
+        let receipt = parse(
+            "REMA 1000\nOrg.nr. 984 661 185 MVA\nDato: 17.05.2024 Kl 14:32\nKasse 3 Bong 1234\n\
+             KASSE ØL 0,33L 399,00\nORG. EPLEJUICE 34,90\nTOTALT 433,90",
+        );
+        assert_eq!(descriptions(&receipt), ["KASSE ØL 0,33L", "ORG. EPLEJUICE"]);
+        assert_eq!(receipt.totals_match, Some(true));
+    }
+
+    fn summary(text: &str) -> Vec<(u8, i64, i64)> {
+        parse_vat_summary(text).iter().map(|rate| (rate.rate, rate.base_amount.ore(), rate.vat_amount.ore())).collect()
+    }
+
+    #[test]
+    fn vat_summary_lines_fill_in_the_missing_amount() {
+        assert_eq!(summary("Mva-grunnlag 15% 160,70\nHerav mva 25% 17,80"), [(15, 16070, 2411), (25, 7120, 1780)]);
+    }
+
+    #[test]
+    fn vat_summary_table_is_read_by_its_header() {
+        let text = "Sats Grunnlag Mva Sum\n15% 160,70 24,10 184,80\n25% 71,20 17,80 89,00\nKORT 273,80";
+        assert_eq!(summary(text), [(15, 16070, 2410), (25, 7120, 1780)]);
+    }
+
+    #[test]
+    fn vat_summary_of_gross_amount_is_split() {
+        assert_eq!(summary("MVA 15% av 184,80 = 24,10"), [(15, 16070, 2410)]);
+        assert!(summary("RABATT 15% 12,00\nSUM 184,80").is_empty());
+    }
+}
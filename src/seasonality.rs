@@ -0,0 +1,292 @@
+// Seasonal insights computed from an organisation's own transaction history.
+//
+// Each transaction is placed in the holiday calendar's shopping window it
+// falls in (påske, 17. mai, jul, ...) or in the baseline: the days of the
+// history outside every window. An event's uplift is its spending per day in
+// its windows over the baseline spending per day, so 2.0 means twice the usual
+// daily spend. Only windows the history covers from first to last day count,
+// and every covered year is compared with the year before.
+//
+// Only the last MAX_HISTORY_YEARS of the history count: older spending says
+// little about the coming season, and the days are walked one by one.
+//
+// A history shorter than MIN_HISTORY_DAYS, or without spending outside the
+// windows, has no baseline; every event is then reported as "no_data".
+
+use crate::calendar::{self, Event};
+use crate::money::Money;
+use crate::HistoricalTransaction;
+use chrono::{Months, NaiveDate};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// About a quarter, so the baseline is more than a few weeks of ordinary spending
+const MIN_HISTORY_DAYS: i64 = 90;
+const MAX_HISTORY_YEARS: u32 = 10;
+const KEY_CATEGORIES: usize = 3;
+
+#[derive(Serialize)]
+pub struct SeasonalInsight {
+    pub season: String,
+    pub cultural_event: Option<String>,
+    pub data_status: &'static str, // "computed", or "no_data" when the history cannot show the event
+    pub expected_spending_increase: Option<f32>, // spending per day in the window over the baseline
+    pub key_categories: Vec<String>, // most spent on in the windows, largest first
+    pub window_spending: Money, // over all covered windows
+    pub year_over_year: Vec<SeasonalYear>,
+    pub historical_pattern: String,
+}
+
+#[derive(Serialize)]
+pub struct SeasonalYear {
+    pub year: i32,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub spending: Money,
+    pub uplift: f32,
+    pub change_percent: Option<f32>, // spending against the year before, when that was covered too
+}
+
+#[derive(Default)]
+struct WindowTotals {
+    days: i64, // days of the window not claimed by a nearer event
+    spending: Money,
+    categories: HashMap<String, Money>,
+}
+
+// One insight per calendar event: computed ones by uplift, largest first,
+// then the events without data
+pub fn seasonal_insights(history: &[HistoricalTransaction]) -> Vec<SeasonalInsight> {
+    let mut dated: Vec<(NaiveDate, &HistoricalTransaction)> = history.iter()
+        .filter_map(|transaction| {
+            NaiveDate::parse_from_str(&transaction.date, "%Y-%m-%d").ok().map(|date| (date, transaction))
+        })
+        .collect();
+    let Some(last) = dated.iter().map(|(date, _)| *date).max() else {
+        return no_data("No transactions with a YYYY-MM-DD date");
+    };
+    let cutoff = last.checked_sub_months(Months::new(12 * MAX_HISTORY_YEARS)).unwrap_or(NaiveDate::MIN);
+    dated.retain(|(date, _)| *date > cutoff);
+    let first = dated.iter().map(|(date, _)| *date).min().unwrap_or(last);
+    let history_days = (last - first).num_days() + 1;
+    if history_days < MIN_HISTORY_DAYS {
+        return no_data(&format!("{} days of history; at least {} are needed for a baseline", history_days, MIN_HISTORY_DAYS));
+    }
+
+    // Split the days of the history between the baseline and the windows
+    let mut baseline_days = 0;
+    let mut baseline_spending = Money::ZERO;
+    let mut windows: HashMap<(Event, i32), WindowTotals> = HashMap::new();
+    for day in first.iter_days().take_while(|day| *day <= last) {
+        match calendar::window_containing(day) {
+            Some(window) => windows.entry(window).or_default().days += 1,
+            None => baseline_days += 1,
+        }
+    }
+    for (date, transaction) in &dated {
+        match calendar::window_containing(*date) {
+            Some(window) => {
+                let totals = windows.entry(window).or_default();
+                totals.spending += transaction.amount;
+                *totals.categories.entry(transaction.category.clone()).or_default() += transaction.amount;
+            },
+            None => baseline_spending += transaction.amount,
+        }
+    }
+    if baseline_days == 0 || baseline_spending <= Money::ZERO {
+        return no_data("No spending outside the holiday windows to compare with");
+    }
+    let baseline_per_day = baseline_spending.kroner() / baseline_days as f64;
+
+    // Windows cut off by the start or end of the history would understate the event
+    windows.retain(|(event, year), _| {
+        let (from, to) = event.window(*year);
+        first <= from && to <= last
+    });
+
+    let mut computed = Vec::new();
+    let mut missing = Vec::new();
+    for event in calendar::EVENTS {
+        let years: BTreeMap<i32, &WindowTotals> = windows.iter()
+            .filter(|((window_event, _), _)| *window_event == event)
+            .map(|((_, year), totals)| (*year, totals))
+            .collect();
+        if years.is_empty() {
+            missing.push(insight(event, "The history does not cover a full shopping window for this event"));
+            continue;
+        }
+        computed.push(event_insight(event, &years, baseline_per_day));
+    }
+
+    computed.sort_by(|a, b| b.expected_spending_increase.partial_cmp(&a.expected_spending_increase).unwrap_or(std::cmp::Ordering::Equal));
+    computed.extend(missing);
+    computed
+}
+
+fn event_insight(event: Event, years: &BTreeMap<i32, &WindowTotals>, baseline_per_day: f64) -> SeasonalInsight {
+    let uplift = |spending: Money, days: i64| round(spending.kroner() / days.max(1) as f64 / baseline_per_day, 100.0);
+
+    let spending: Money = years.values().map(|totals| totals.spending).sum();
+    let days: i64 = years.values().map(|totals| totals.days).sum();
+    let expected_spending_increase = uplift(spending, days);
+
+    let mut categories: HashMap<&str, Money> = HashMap::new();
+    for (category, amount) in years.values().flat_map(|totals| totals.categories.iter()) {
+        *categories.entry(category.as_str()).or_default() += *amount;
+    }
+    let mut categories: Vec<(&str, Money)> = categories.into_iter().collect();
+    categories.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let key_categories: Vec<String> = categories.iter().take(KEY_CATEGORIES).map(|(category, _)| category.to_string()).collect();
+
+    let year_over_year: Vec<SeasonalYear> = years.iter().map(|(year, totals)| {
+        let (from, to) = event.window(*year);
+        let previous = years.get(&(year - 1)).map(|previous| previous.spending).filter(|previous| *previous > Money::ZERO);
+        SeasonalYear {
+            year: *year,
+            from,
+            to,
+            spending: totals.spending,
+            uplift: uplift(totals.spending, totals.days),
+            change_percent: previous.map(|previous| round((totals.spending - previous).kroner() / previous.kroner() * 100.0, 10.0)),
+        }
+    }).collect();
+
+    let mut pattern = format!(
+        "{:.2}x the baseline daily spending over {} {}",
+        expected_spending_increase,
+        years.len(),
+        if years.len() == 1 { "window" } else { "windows" },
+    );
+    if !key_categories.is_empty() {
+        pattern.push_str(&format!(", mostly {}", key_categories.join(", ")));
+    }
+    if let Some(latest) = year_over_year.last() {
+        if let Some(change) = latest.change_percent {
+            pattern.push_str(&format!("; {} {:+.1}% on {}", latest.year, change, latest.year - 1));
+        }
+    }
+
+    SeasonalInsight {
+        data_status: "computed",
+        expected_spending_increase: Some(expected_spending_increase),
+        key_categories,
+        window_spending: spending,
+        year_over_year,
+        historical_pattern: pattern,
+        ..insight(event, "")
+    }
+}
+
+fn no_data(reason: &str) -> Vec<SeasonalInsight> {
+    calendar::EVENTS.iter().map(|event| insight(*event, reason)).collect()
+}
+
+// An event without data
+fn insight(event: Event, reason: &str) -> SeasonalInsight {
+    let info = event.info();
+    SeasonalInsight {
+        season: info.season.to_string(),
+        cultural_event: Some(info.cultural_event.to_string()),
+        data_status: "no_data",
+        expected_spending_increase: None,
+        key_categories: Vec::new(),
+        window_spending: Money::ZERO,
+        year_over_year: Vec::new(),
+        historical_pattern: reason.to_string(),
+    }
+}
+
+fn round(value: f64, scale: f64) -> f32 {
+    ((value * scale).round() / scale) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn transaction(date: NaiveDate, kroner: i64, category: &str) -> HistoricalTransaction {
+        HistoricalTransaction {
+            date: date.to_string(),
+            merchant: "REMA 1000".to_string(),
+            amount: Money::nok(kroner),
+            category: category.to_string(),
+            season: None,
+            cultural_event: None,
+        }
+    }
+
+    // 100 kroner of groceries every day, and 300 of gifts a day from 1. to 26. desember
+    fn year_of_spending(year: i32) -> Vec<HistoricalTransaction> {
+        let first = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+        let last = NaiveDate::from_ymd_opt(year, 12, 31).unwrap();
+        first.iter_days().take_while(|day| *day <= last).map(|day| {
+            let (christmas_start, christmas_end) = Event::Christmas.window(year);
+            if christmas_start <= day && day <= christmas_end {
+                transaction(day, 300, "Gaver")
+            } else {
+                transaction(day, 100, "Dagligvarer")
+            }
+        }).collect()
+    }
+
+    fn event(insights: &[SeasonalInsight], event: Event) -> &SeasonalInsight {
+        insights.iter().find(|insight| insight.cultural_event.as_deref() == Some(event.info().cultural_event)).unwrap()
+    }
+
+    #[test]
+    fn uplift_is_window_spending_per_day_over_the_baseline() {
+        let insights = seasonal_insights(&year_of_spending(2023));
+        let christmas = event(&insights, Event::Christmas);
+        assert_eq!(christmas.data_status, "computed");
+        assert_eq!(christmas.expected_spending_increase, Some(3.0));
+        assert_eq!(christmas.key_categories, ["Gaver"]);
+        assert_eq!(christmas.window_spending, Money::nok(26 * 300));
+        assert_eq!(event(&insights, Event::Easter).expected_spending_increase, Some(1.0));
+        assert_eq!(insights[0].cultural_event.as_deref(), Some(Event::Christmas.info().cultural_event));
+    }
+
+    #[test]
+    fn windows_cut_off_by_the_history_are_left_out() {
+        // The history starts on 1. januar and ends on nyttårsaften, so neither New Year window is whole
+        let insights = seasonal_insights(&year_of_spending(2023));
+        let new_year = event(&insights, Event::NewYear);
+        assert_eq!(new_year.data_status, "no_data");
+        assert_eq!(new_year.expected_spending_increase, None);
+    }
+
+    #[test]
+    fn years_are_compared_with_the_year_before() {
+        let mut history = year_of_spending(2022);
+        history.extend(year_of_spending(2023).into_iter().map(|mut transaction| {
+            transaction.amount = transaction.amount.scale(1.1);
+            transaction
+        }));
+        let insights = seasonal_insights(&history);
+        let years = &event(&insights, Event::Christmas).year_over_year;
+        assert_eq!(years.iter().map(|year| year.year).collect::<Vec<_>>(), [2022, 2023]);
+        assert_eq!(years[0].change_percent, None);
+        assert_eq!(years[1].change_percent, Some(10.0));
+    }
+
+    #[test]
+    fn short_histories_have_no_baseline() {
+        let start = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let history: Vec<_> = (0..30).map(|day| transaction(start + Duration::days(day), 100, "Dagligvarer")).collect();
+        let insights = seasonal_insights(&history);
+        assert_eq!(insights.len(), calendar::EVENTS.len());
+        assert!(insights.iter().all(|insight| insight.data_status == "no_data"));
+        assert_eq!(insights[0].historical_pattern, "30 days of history; at least 90 are needed for a baseline");
+    }
+
+    #[test]
+    fn only_the_last_ten_years_are_walked() {
+        let mut history = year_of_spending(2023);
+        history.push(transaction(NaiveDate::from_ymd_opt(1, 1, 1).unwrap(), 100, "Dagligvarer"));
+        history.push(transaction(NaiveDate::from_ymd_opt(2013, 12, 31).unwrap(), 100, "Dagligvarer"));
+        let insights = seasonal_insights(&history);
+        let christmas = event(&insights, Event::Christmas);
+        assert_eq!(christmas.expected_spending_increase, Some(3.0));
+        assert_eq!(christmas.year_over_year.len(), 1);
+    }
+}
@@ -0,0 +1,456 @@
+// Persistence for everything the service learns at runtime: user corrections,
+// per-merchant confidence, training examples, fine-tuned model records and
+// seasonal transaction history.
+//
+// DATABASE_PATH=/data/rust-llm.sqlite  embedded SQLite file, migrated on startup
+//
+// Without DATABASE_PATH the data is kept in memory and lost on restart.
+
+use crate::{HistoricalTransaction, ModelMetrics, TrainingExample, UserCorrection};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::Mutex;
+
+// Only the most recent training examples are kept
+pub const MAX_TRAINING_EXAMPLES: usize = 10_000;
+
+#[derive(Debug)]
+pub struct StoreError(String);
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(error: rusqlite::Error) -> Self {
+        StoreError(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(error: serde_json::Error) -> Self {
+        StoreError(error.to_string())
+    }
+}
+
+pub trait Store: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn add_correction(&self, correction: &UserCorrection) -> Result<(), StoreError>;
+    fn count_corrections_for_merchant(&self, merchant: Option<&str>) -> Result<u32, StoreError>;
+
+    fn merchant_confidence(&self, merchant: &str) -> Result<Option<f32>, StoreError>;
+    fn set_merchant_confidence(&self, merchant: &str, confidence: f32) -> Result<(), StoreError>;
+
+    fn add_training_example(&self, example: &TrainingExample) -> Result<(), StoreError>;
+
+    fn save_fine_tuned_model(&self, model_id: &str, model_type: &str, metrics: &ModelMetrics) -> Result<(), StoreError>;
+    fn fine_tuned_models(&self) -> Result<Vec<FineTunedModel>, StoreError>;
+
+    fn save_seasonal_patterns(&self, organization_type: &str, transactions: &[HistoricalTransaction]) -> Result<(), StoreError>;
+}
+
+#[derive(Clone)]
+pub struct FineTunedModel {
+    pub model_id: String,
+    pub model_type: String,
+    pub metrics: ModelMetrics,
+    pub created_at: String,
+}
+
+pub fn from_env() -> Box<dyn Store> {
+    let Ok(path) = env::var("DATABASE_PATH") else {
+        println!("⚠️  No DATABASE_PATH set, learning data is kept in memory and lost on restart");
+        return Box::new(MemoryStore::default());
+    };
+
+    // A configured database that cannot be opened must stop the boot rather
+    // than silently dropping corrections
+    match SqliteStore::open(&path) {
+        Ok(store) => {
+            println!("   - Opened SQLite store at {} (schema version {})", path, MIGRATIONS.len());
+            Box::new(store)
+        },
+        Err(e) => panic!("❌ Could not open database at {}: {}", path, e),
+    }
+}
+
+// In-memory store, used when no database is configured and in tests
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<MemoryData>,
+}
+
+#[derive(Default)]
+struct MemoryData {
+    corrections: Vec<UserCorrection>,
+    merchant_confidence: HashMap<String, f32>,
+    training_examples: Vec<TrainingExample>,
+    fine_tuned_models: Vec<FineTunedModel>,
+    seasonal_patterns: HashMap<String, Vec<HistoricalTransaction>>,
+}
+
+impl MemoryStore {
+    fn data(&self) -> Result<std::sync::MutexGuard<'_, MemoryData>, StoreError> {
+        self.data.lock().map_err(|_| StoreError("memory store lock poisoned".to_string()))
+    }
+}
+
+impl Store for MemoryStore {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn add_correction(&self, correction: &UserCorrection) -> Result<(), StoreError> {
+        self.data()?.corrections.push(correction.clone());
+        Ok(())
+    }
+
+    fn count_corrections_for_merchant(&self, merchant: Option<&str>) -> Result<u32, StoreError> {
+        Ok(self.data()?.corrections.iter()
+            .filter(|correction| correction.corrected_merchant.as_deref() == merchant)
+            .count() as u32)
+    }
+
+    fn merchant_confidence(&self, merchant: &str) -> Result<Option<f32>, StoreError> {
+        Ok(self.data()?.merchant_confidence.get(merchant).copied())
+    }
+
+    fn set_merchant_confidence(&self, merchant: &str, confidence: f32) -> Result<(), StoreError> {
+        self.data()?.merchant_confidence.insert(merchant.to_string(), confidence);
+        Ok(())
+    }
+
+    fn add_training_example(&self, example: &TrainingExample) -> Result<(), StoreError> {
+        let mut data = self.data()?;
+        data.training_examples.push(example.clone());
+        if data.training_examples.len() > MAX_TRAINING_EXAMPLES {
+            let excess = data.training_examples.len() - MAX_TRAINING_EXAMPLES;
+            data.training_examples.drain(0..excess);
+        }
+        Ok(())
+    }
+
+    fn save_fine_tuned_model(&self, model_id: &str, model_type: &str, metrics: &ModelMetrics) -> Result<(), StoreError> {
+        self.data()?.fine_tuned_models.push(FineTunedModel {
+            model_id: model_id.to_string(),
+            model_type: model_type.to_string(),
+            metrics: metrics.clone(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        });
+        Ok(())
+    }
+
+    fn fine_tuned_models(&self) -> Result<Vec<FineTunedModel>, StoreError> {
+        Ok(self.data()?.fine_tuned_models.clone())
+    }
+
+    fn save_seasonal_patterns(&self, organization_type: &str, transactions: &[HistoricalTransaction]) -> Result<(), StoreError> {
+        self.data()?.seasonal_patterns.insert(organization_type.to_string(), transactions.to_vec());
+        Ok(())
+    }
+}
+
+// Schema migrations, applied in order. The number applied so far is kept in
+// SQLite's user_version, so only append to this list
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE user_corrections (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        original_analysis TEXT NOT NULL,
+        corrected_merchant TEXT,
+        corrected_amount REAL,
+        corrected_vat_rate INTEGER,
+        corrected_category TEXT,
+        user_feedback TEXT,
+        confidence_rating INTEGER,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX user_corrections_merchant ON user_corrections (corrected_merchant);
+
+    CREATE TABLE merchant_confidence (
+        merchant TEXT PRIMARY KEY,
+        confidence REAL NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+
+    CREATE TABLE training_examples (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        input_text TEXT NOT NULL,
+        expected_merchant TEXT,
+        expected_amount REAL,
+        expected_vat_rate INTEGER,
+        expected_category TEXT,
+        context_metadata TEXT,
+        quality_score REAL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE fine_tuned_models (
+        model_id TEXT PRIMARY KEY,
+        model_type TEXT NOT NULL,
+        accuracy REAL NOT NULL,
+        precision REAL NOT NULL,
+        recall REAL NOT NULL,
+        f1_score REAL NOT NULL,
+        norwegian_merchant_accuracy REAL NOT NULL,
+        vat_compliance_accuracy REAL NOT NULL,
+        seasonal_pattern_accuracy REAL NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE seasonal_patterns (
+        organization_type TEXT PRIMARY KEY,
+        transactions TEXT NOT NULL, -- JSON array of HistoricalTransaction
+        updated_at TEXT NOT NULL
+    );",
+    // Amounts as whole øre plus their currency instead of REAL kroner
+    "ALTER TABLE user_corrections RENAME COLUMN corrected_amount TO corrected_amount_kroner;
+    ALTER TABLE user_corrections ADD COLUMN corrected_amount INTEGER;
+    ALTER TABLE user_corrections ADD COLUMN corrected_currency TEXT;
+    UPDATE user_corrections SET corrected_amount = CAST(ROUND(corrected_amount_kroner * 100) AS INTEGER), corrected_currency = 'NOK'
+        WHERE corrected_amount_kroner IS NOT NULL;
+    ALTER TABLE user_corrections DROP COLUMN corrected_amount_kroner;
+
+    ALTER TABLE training_examples RENAME COLUMN expected_amount TO expected_amount_kroner;
+    ALTER TABLE training_examples ADD COLUMN expected_amount INTEGER;
+    ALTER TABLE training_examples ADD COLUMN expected_currency TEXT;
+    UPDATE training_examples SET expected_amount = CAST(ROUND(expected_amount_kroner * 100) AS INTEGER), expected_currency = 'NOK'
+        WHERE expected_amount_kroner IS NOT NULL;
+    ALTER TABLE training_examples DROP COLUMN expected_amount_kroner;",
+];
+
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self, StoreError> {
+        let mut connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "foreign_keys", "ON")?;
+        migrate(&mut connection)?;
+        Ok(SqliteStore { connection: Mutex::new(connection) })
+    }
+
+    fn connection(&self) -> Result<std::sync::MutexGuard<'_, Connection>, StoreError> {
+        self.connection.lock().map_err(|_| StoreError("database connection lock poisoned".to_string()))
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<(), StoreError> {
+    let applied: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if applied > MIGRATIONS.len() {
+        return Err(StoreError(format!(
+            "database schema version {} is newer than this build supports ({})", applied, MIGRATIONS.len()
+        )));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+        println!("   - Applied database migration {}", index + 1);
+    }
+    Ok(())
+}
+
+impl Store for SqliteStore {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn add_correction(&self, correction: &UserCorrection) -> Result<(), StoreError> {
+        self.connection()?.execute(
+            "INSERT INTO user_corrections (original_analysis, corrected_merchant, corrected_amount, corrected_currency,
+                corrected_vat_rate, corrected_category, user_feedback, confidence_rating, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                correction.original_analysis,
+                correction.corrected_merchant,
+                correction.corrected_amount.map(|amount| amount.ore()),
+                correction.corrected_amount.map(|amount| amount.currency()),
+                correction.corrected_vat_rate,
+                correction.corrected_category,
+                correction.user_feedback,
+                correction.confidence_rating,
+                chrono::Utc::now().to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn count_corrections_for_merchant(&self, merchant: Option<&str>) -> Result<u32, StoreError> {
+        let count = self.connection()?.query_row(
+            "SELECT COUNT(*) FROM user_corrections WHERE corrected_merchant IS ?1",
+            params![merchant],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    fn merchant_confidence(&self, merchant: &str) -> Result<Option<f32>, StoreError> {
+        let confidence = self.connection()?.query_row(
+            "SELECT confidence FROM merchant_confidence WHERE merchant = ?1",
+            params![merchant],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(confidence)
+    }
+
+    fn set_merchant_confidence(&self, merchant: &str, confidence: f32) -> Result<(), StoreError> {
+        self.connection()?.execute(
+            "INSERT INTO merchant_confidence (merchant, confidence, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (merchant) DO UPDATE SET confidence = excluded.confidence, updated_at = excluded.updated_at",
+            params![merchant, confidence, chrono::Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    fn add_training_example(&self, example: &TrainingExample) -> Result<(), StoreError> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO training_examples (input_text, expected_merchant, expected_amount, expected_currency,
+                expected_vat_rate, expected_category, context_metadata, quality_score, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                example.input_text,
+                example.expected_merchant,
+                example.expected_amount.map(|amount| amount.ore()),
+                example.expected_amount.map(|amount| amount.currency()),
+                example.expected_vat_rate,
+                example.expected_category,
+                example.context_metadata,
+                example.quality_score,
+                chrono::Utc::now().to_rfc3339(),
+            ],
+        )?;
+        transaction.execute(
+            "DELETE FROM training_examples WHERE id <= (SELECT MAX(id) FROM training_examples) - ?1",
+            params![MAX_TRAINING_EXAMPLES as i64],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn save_fine_tuned_model(&self, model_id: &str, model_type: &str, metrics: &ModelMetrics) -> Result<(), StoreError> {
+        self.connection()?.execute(
+            "INSERT OR REPLACE INTO fine_tuned_models (model_id, model_type, accuracy, precision, recall, f1_score,
+                norwegian_merchant_accuracy, vat_compliance_accuracy, seasonal_pattern_accuracy, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                model_id,
+                model_type,
+                metrics.accuracy,
+                metrics.precision,
+                metrics.recall,
+                metrics.f1_score,
+                metrics.norwegian_merchant_accuracy,
+                metrics.vat_compliance_accuracy,
+                metrics.seasonal_pattern_accuracy,
+                chrono::Utc::now().to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn fine_tuned_models(&self) -> Result<Vec<FineTunedModel>, StoreError> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(
+            "SELECT model_id, model_type, accuracy, precision, recall, f1_score, norwegian_merchant_accuracy,
+                vat_compliance_accuracy, seasonal_pattern_accuracy, created_at
+             FROM fine_tuned_models ORDER BY created_at",
+        )?;
+        let models = statement.query_map([], |row| {
+            Ok(FineTunedModel {
+                model_id: row.get(0)?,
+                model_type: row.get(1)?,
+                metrics: ModelMetrics {
+                    accuracy: row.get(2)?,
+                    precision: row.get(3)?,
+                    recall: row.get(4)?,
+                    f1_score: row.get(5)?,
+                    norwegian_merchant_accuracy: row.get(6)?,
+                    vat_compliance_accuracy: row.get(7)?,
+                    seasonal_pattern_accuracy: row.get(8)?,
+                },
+                created_at: row.get(9)?,
+            })
+        })?.collect::<Result<Vec<_>, _>>()?;
+        Ok(models)
+    }
+
+    fn save_seasonal_patterns(&self, organization_type: &str, transactions: &[HistoricalTransaction]) -> Result<(), StoreError> {
+        let transactions = serde_json::to_string(transactions)?;
+        self.connection()?.execute(
+            "INSERT INTO seasonal_patterns (organization_type, transactions, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (organization_type) DO UPDATE SET transactions = excluded.transactions, updated_at = excluded.updated_at",
+            params![organization_type, transactions, chrono::Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::{Currency, Money};
+
+    fn stored_amount(store: &SqliteStore, sql: &str) -> Money {
+        store.connection().unwrap().query_row(sql, [], |row| {
+            let ore: i64 = row.get(0)?;
+            let currency: Currency = row.get(1)?;
+            Ok(Money::from_ore(ore).in_currency(currency))
+        }).unwrap()
+    }
+
+    #[test]
+    fn amounts_are_stored_as_ore_with_their_currency() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        store.add_correction(&UserCorrection {
+            original_analysis: "{}".to_string(),
+            corrected_merchant: Some("ICA".to_string()),
+            corrected_amount: Some("SEK 209,90".parse().unwrap()),
+            corrected_vat_rate: None,
+            corrected_category: None,
+            user_feedback: None,
+            confidence_rating: None,
+        }).unwrap();
+        store.add_training_example(&TrainingExample {
+            input_text: "REMA 1000".to_string(),
+            expected_merchant: None,
+            expected_amount: Some(Money::from_ore(6340)),
+            expected_vat_rate: None,
+            expected_category: None,
+            context_metadata: None,
+            quality_score: None,
+        }).unwrap();
+
+        let corrected = stored_amount(&store, "SELECT corrected_amount, corrected_currency FROM user_corrections");
+        assert_eq!(corrected, Money::from_ore(20990).in_currency(Currency::SEK));
+        let expected = stored_amount(&store, "SELECT expected_amount, expected_currency FROM training_examples");
+        assert_eq!(expected, Money::from_ore(6340));
+    }
+
+    #[test]
+    fn migration_converts_kroner_to_ore() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0]).unwrap();
+        connection.pragma_update(None, "user_version", 1).unwrap();
+        connection.execute(
+            "INSERT INTO user_corrections (original_analysis, corrected_amount, created_at) VALUES ('{}', 63.4, '')",
+            [],
+        ).unwrap();
+
+        migrate(&mut connection).unwrap();
+        let (ore, currency): (i64, String) = connection.query_row(
+            "SELECT corrected_amount, corrected_currency FROM user_corrections", [], |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!((ore, currency.as_str()), (6340, "NOK"));
+    }
+}
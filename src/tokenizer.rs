@@ -0,0 +1,375 @@
+// Token counting for prompts and completions. A vocabulary is loaded from a
+// local file at startup (TOKENIZER_PATH):
+//   *.tiktoken / *.bpe  - byte-level BPE ranks, one "<base64 token> <rank>" per line
+//   *.vocab / *.tsv     - SentencePiece unigram vocab, one "<piece>\t<score>" per line
+// TOKENIZER_TYPE=bpe|sentencepiece overrides the extension check. Without a
+// vocabulary file a character-based heuristic (~4 characters per token) is used.
+
+use base64::Engine;
+use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::env;
+use std::fs;
+use std::ops::Range;
+
+// Context window advertised for the served models unless MODEL_CONTEXT_WINDOW is set
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 4096;
+
+// GPT-2 style pre-tokenization (the regex crate has no lookaround, so the
+// trailing-whitespace rule of newer tiktoken patterns is left out)
+const PRE_TOKENIZE_PATTERN: &str = r"'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+";
+
+const SENTENCEPIECE_SPACE: char = '\u{2581}'; // ▁
+
+pub fn context_window() -> u32 {
+    env::var("MODEL_CONTEXT_WINDOW")
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+pub struct Tokenizer {
+    name: String,
+    model: TokenizerModel,
+    pre_tokenizer: Regex,
+}
+
+enum TokenizerModel {
+    BytePair(HashMap<Vec<u8>, u32>),
+    Unigram(UnigramModel),
+    Heuristic,
+}
+
+struct UnigramModel {
+    scores: HashMap<String, f32>,
+    max_piece_chars: usize,
+    unknown_score: f32,
+}
+
+impl Tokenizer {
+    pub fn from_env() -> Self {
+        let Ok(path) = env::var("TOKENIZER_PATH") else {
+            println!("   - No TOKENIZER_PATH set, using heuristic token counts");
+            return Tokenizer::heuristic();
+        };
+
+        let kind = env::var("TOKENIZER_TYPE").unwrap_or_else(|_| {
+            if path.ends_with(".vocab") || path.ends_with(".tsv") { "sentencepiece" } else { "bpe" }.to_string()
+        });
+
+        let loaded = match kind.as_str() {
+            "sentencepiece" => Tokenizer::load_sentencepiece(&path),
+            _ => Tokenizer::load_bpe(&path),
+        };
+
+        match loaded {
+            Ok(tokenizer) => {
+                println!("   - Loaded {} tokenizer from {}", tokenizer.name, path);
+                tokenizer
+            },
+            Err(e) => {
+                println!("⚠️  Could not load tokenizer from {}: {}. Using heuristic token counts", path, e);
+                Tokenizer::heuristic()
+            },
+        }
+    }
+
+    fn new(name: &str, model: TokenizerModel) -> Self {
+        Tokenizer {
+            name: name.to_string(),
+            model,
+            pre_tokenizer: Regex::new(PRE_TOKENIZE_PATTERN).expect("valid pre-tokenizer pattern"),
+        }
+    }
+
+    pub fn heuristic() -> Self {
+        Tokenizer::new("heuristic", TokenizerModel::Heuristic)
+    }
+
+    pub fn load_bpe(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut ranks = HashMap::new();
+
+        for (line_number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(token), Some(rank)) = (fields.next(), fields.next()) else {
+                return Err(format!("line {}: expected '<base64 token> <rank>'", line_number + 1));
+            };
+            let token = base64::engine::general_purpose::STANDARD.decode(token)
+                .map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+            let rank = rank.parse::<u32>().map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+            ranks.insert(token, rank);
+        }
+
+        if ranks.is_empty() {
+            return Err("vocabulary file is empty".to_string());
+        }
+        Ok(Tokenizer::new("bpe", TokenizerModel::BytePair(ranks)))
+    }
+
+    pub fn load_sentencepiece(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut scores = HashMap::new();
+        let mut max_piece_chars = 1;
+        let mut min_score = 0.0f32;
+
+        for (line_number, line) in content.lines().enumerate() {
+            let Some((piece, score)) = line.split_once('\t') else { continue };
+            let score = score.trim().parse::<f32>().map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+            // Control symbols such as <s>, </s> and <unk> never match plain text
+            if piece.starts_with('<') && piece.ends_with('>') {
+                continue;
+            }
+            max_piece_chars = max_piece_chars.max(piece.chars().count());
+            min_score = min_score.min(score);
+            scores.insert(piece.to_string(), score);
+        }
+
+        if scores.is_empty() {
+            return Err("vocabulary file is empty".to_string());
+        }
+        Ok(Tokenizer::new("sentencepiece", TokenizerModel::Unigram(UnigramModel {
+            scores,
+            max_piece_chars,
+            unknown_score: min_score - 10.0,
+        })))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn count(&self, text: &str) -> u32 {
+        self.token_spans(text).len() as u32
+    }
+
+    // The longest prefix of `text` that fits in `max_tokens`, or None when the
+    // whole text already fits
+    pub fn truncate<'a>(&self, text: &'a str, max_tokens: u32) -> Option<&'a str> {
+        let spans = self.token_spans(text);
+        if spans.len() <= max_tokens as usize {
+            return None;
+        }
+
+        let mut end = if max_tokens == 0 { 0 } else { spans[max_tokens as usize - 1].end };
+        // Byte-level tokens can end inside a multi-byte character
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        Some(&text[..end])
+    }
+
+    // Byte range in `text` covered by each token
+    fn token_spans(&self, text: &str) -> Vec<Range<usize>> {
+        match &self.model {
+            TokenizerModel::BytePair(ranks) => self.pre_tokenizer.find_iter(text)
+                .flat_map(|piece| byte_pair_spans(ranks, piece.as_str().as_bytes())
+                    .into_iter()
+                    .map(move |span| piece.start() + span.start..piece.start() + span.end))
+                .collect(),
+            TokenizerModel::Unigram(model) => unigram_spans(model, text),
+            TokenizerModel::Heuristic => self.pre_tokenizer.find_iter(text)
+                .flat_map(|piece| heuristic_spans(piece.as_str())
+                    .into_iter()
+                    .map(move |span| piece.start() + span.start..piece.start() + span.end))
+                .collect(),
+        }
+    }
+}
+
+// Merge adjacent parts by lowest rank, leftmost first, until no pair is in
+// the vocabulary (the tiktoken algorithm). Candidate pairs wait in a heap, so
+// long pieces cost O(n log n) rather than a rescan after every merge.
+fn byte_pair_spans(ranks: &HashMap<Vec<u8>, u32>, piece: &[u8]) -> Vec<Range<usize>> {
+    let n = piece.len();
+    if n == 0 || ranks.contains_key(piece) {
+        return std::iter::once(0..n).collect();
+    }
+
+    // Parts are keyed by their start byte: next[start] is where the part ends
+    // and prev[start] where the part before it starts
+    let mut next: Vec<usize> = (1..=n).collect();
+    let mut prev: Vec<Option<usize>> = (0..n).map(|start| start.checked_sub(1)).collect();
+    let mut merged = vec![false; n];
+
+    let mut candidates = BinaryHeap::new();
+    let push = |candidates: &mut BinaryHeap<_>, start: usize, end: usize| {
+        if let Some(&rank) = ranks.get(&piece[start..end]) {
+            candidates.push(Reverse((rank, start, end)));
+        }
+    };
+    for start in 0..n - 1 {
+        push(&mut candidates, start, start + 2);
+    }
+
+    while let Some(Reverse((_, start, end))) = candidates.pop() {
+        // Earlier merges leave stale candidates behind; a pair is still there
+        // while its left part survives and the part after it ends at `end`
+        let middle = next[start];
+        if merged[start] || middle >= n || next[middle] != end {
+            continue;
+        }
+
+        merged[middle] = true;
+        next[start] = end;
+        if end < n {
+            prev[end] = Some(start);
+            push(&mut candidates, start, next[end]);
+        }
+        if let Some(before) = prev[start] {
+            push(&mut candidates, before, end);
+        }
+    }
+
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while start < n {
+        spans.push(start..next[start]);
+        start = next[start];
+    }
+    spans
+}
+
+// Viterbi segmentation over whitespace-separated words, each prefixed with ▁
+// as SentencePiece does
+fn unigram_spans(model: &UnigramModel, text: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+
+    for (word_start, word) in split_words(text) {
+        let normalized: Vec<char> = std::iter::once(SENTENCEPIECE_SPACE).chain(word.chars()).collect();
+        // Byte offset in the original text for each normalized char boundary;
+        // the ▁ maps onto the start of the word
+        let mut offsets = vec![word_start, word_start];
+        offsets.extend(word.char_indices().map(|(i, c)| word_start + i + c.len_utf8()));
+
+        let n = normalized.len();
+        let mut best: Vec<(f32, usize)> = vec![(f32::NEG_INFINITY, 0); n + 1];
+        best[0] = (0.0, 0);
+
+        for end in 1..=n {
+            let first = end.saturating_sub(model.max_piece_chars);
+            for start in first..end {
+                if best[start].0 == f32::NEG_INFINITY {
+                    continue;
+                }
+                let piece: String = normalized[start..end].iter().collect();
+                let score = match model.scores.get(&piece) {
+                    Some(&score) => score,
+                    None if end - start == 1 => model.unknown_score,
+                    None => continue,
+                };
+                if best[start].0 + score > best[end].0 {
+                    best[end] = (best[start].0 + score, start);
+                }
+            }
+        }
+
+        let mut cuts = vec![n];
+        let mut position = n;
+        while position > 0 {
+            position = best[position].1;
+            cuts.push(position);
+        }
+        cuts.reverse();
+
+        spans.extend(cuts.windows(2).map(|pair| offsets[pair[0]]..offsets[pair[1]]));
+    }
+
+    spans
+}
+
+fn split_words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                words.push((s, &text[s..i]));
+                start = None;
+            },
+            (false, None) => start = Some(i),
+            _ => {},
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, &text[s..]));
+    }
+
+    words
+}
+
+// Four characters per token, split on character boundaries
+fn heuristic_spans(piece: &str) -> Vec<Range<usize>> {
+    let boundaries: Vec<usize> = piece.char_indices().map(|(i, _)| i).step_by(4).chain(std::iter::once(piece.len())).collect();
+    boundaries.windows(2).map(|pair| pair[0]..pair[1]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranks(tokens: &[&str]) -> HashMap<Vec<u8>, u32> {
+        tokens.iter().enumerate().map(|(rank, token)| (token.as_bytes().to_vec(), rank as u32)).collect()
+    }
+
+    #[test]
+    fn byte_pairs_merge_lowest_rank_first() {
+        assert_eq!(byte_pair_spans(&ranks(&["bc", "ab"]), b"abc"), vec![0..1, 1..3]);
+        assert_eq!(byte_pair_spans(&ranks(&["ab", "bc"]), b"abc"), vec![0..2, 2..3]);
+        // Equal ranks merge from the left
+        assert_eq!(byte_pair_spans(&ranks(&["aa"]), b"aaa"), vec![0..2, 2..3]);
+        // Merged parts go on to merge with their neighbours
+        assert_eq!(byte_pair_spans(&ranks(&["ab", "cd", "abcd", "e"]), b"abcde"), vec![0..4, 4..5]);
+        assert_eq!(byte_pair_spans(&ranks(&["xy"]), b"abc"), vec![0..1, 1..2, 2..3]);
+    }
+
+    #[test]
+    fn long_pieces_merge_like_a_rescan() {
+        let ranks = ranks(&["aa", "aaaa", "ab", "aaaaaaaa", "ba"]);
+        let piece = "aab".repeat(300) + &"a".repeat(1_000);
+
+        // The merge loop this replaced, kept here as the reference
+        let mut boundaries: Vec<usize> = (0..=piece.len()).collect();
+        while let Some((_, i)) = (0..boundaries.len().saturating_sub(2))
+            .filter_map(|i| ranks.get(&piece.as_bytes()[boundaries[i]..boundaries[i + 2]]).map(|&rank| (rank, i)))
+            .min()
+        {
+            boundaries.remove(i + 1);
+        }
+        let expected: Vec<Range<usize>> = boundaries.windows(2).map(|pair| pair[0]..pair[1]).collect();
+
+        assert_eq!(byte_pair_spans(&ranks, piece.as_bytes()), expected);
+    }
+
+    #[test]
+    fn unigram_words_take_the_best_scoring_pieces() {
+        let model = UnigramModel {
+            scores: [("▁hei", -1.0), ("▁he", -0.5), ("i", -0.6), ("▁p", -2.0)]
+                .into_iter()
+                .map(|(piece, score)| (piece.to_string(), score))
+                .collect(),
+            max_piece_chars: 4,
+            unknown_score: -12.0,
+        };
+
+        // ▁ maps onto the start of each word and unknown characters are single pieces
+        assert_eq!(unigram_spans(&model, "hei  på"), vec![0..3, 5..6, 6..8]);
+        assert!(unigram_spans(&model, " \n ").is_empty());
+    }
+
+    #[test]
+    fn truncation_backs_off_to_a_character_boundary() {
+        // No merges, so every byte of "æøå" is its own token
+        let tokenizer = Tokenizer::new("bpe", TokenizerModel::BytePair(ranks(&["xy"])));
+        assert_eq!(tokenizer.count("æøå"), 6);
+        assert_eq!(tokenizer.truncate("æøå", 3), Some("æ"));
+        assert_eq!(tokenizer.truncate("æøå", 4), Some("æø"));
+        assert_eq!(tokenizer.truncate("æøå", 0), Some(""));
+        assert_eq!(tokenizer.truncate("æøå", 6), None);
+    }
+}
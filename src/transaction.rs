@@ -0,0 +1,197 @@
+// When and where a receipt was issued, read from its text: transaction date,
+// time of day, kvitteringsnummer/bilagsnummer, cashier and terminal.
+//
+// Dates are accepted as dd.mm.yyyy, dd.mm.yy (also with / or -), ISO
+// yyyy-mm-dd and written out as "17. mai 2024". A date on a "Dato" line wins
+// over others; return deadlines, best-before and due dates are skipped.
+// Dates without a year, and two-digit years, are read relative to the
+// reference date the caller passes in (usually today).
+
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use regex::{Captures, Regex};
+use serde::Serialize;
+
+lazy_static::lazy_static! {
+    static ref NUMERIC_DATE: Regex = Regex::new(r"\b(?P<day>\d{1,2})[./-](?P<month>\d{1,2})[./-](?P<year>\d{4}|\d{2})\b").unwrap();
+    static ref ISO_DATE: Regex = Regex::new(r"\b(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})(?:T|\b)").unwrap();
+    static ref WRITTEN_DATE: Regex = Regex::new(
+        r"(?i)\b(?P<day>\d{1,2})\.?\s+(?P<month>januar|februar|mars|april|mai|juni|juli|august|september|oktober|november|desember|jan|feb|mar|apr|jun|jul|aug|sept|sep|okt|nov|des)\.?(?:\s+(?P<year>\d{4}))?\b"
+    ).unwrap();
+    // "14:32", "14:32:05", "2024-05-17T14:32" or "kl 14.32" where the dot would otherwise read as an amount
+    static ref TIME: Regex = Regex::new(
+        r"(?i)(?:\bkl\.?\s*(?P<kl_hour>[01]?\d|2[0-3])[.:](?P<kl_minute>[0-5]\d)|(?:\b|T)(?P<hour>[01]?\d|2[0-3]):(?P<minute>[0-5]\d)(?::(?P<second>[0-5]\d))?)\b"
+    ).unwrap();
+    static ref RECEIPT_NUMBER: Regex = Regex::new(
+        r"(?i)\b(?:kvittering|kvitt|bilag|bong|receipt)s?\.?\s*(?:(?:nr|nummer|no)\.?\s*[:#]?|[:#])\s*(?P<number>[A-Z0-9][A-Z0-9/-]*\d[A-Z0-9/-]*)"
+    ).unwrap();
+    static ref CASHIER: Regex = Regex::new(
+        r"(?i)\b(?:kasserer|ekspeditør|ekspedert av|betjent av|operatør|selger)\s*(?:nr\.?)?\s*[:#]?\s*(?P<cashier>[\p{L}\d][\p{L}\d.-]*(?: [\p{L}\d][\p{L}\d.-]*)?)"
+    ).unwrap();
+    static ref TERMINAL: Regex = Regex::new(
+        r"(?i)\b(?:terminal(?:\s*id)?|term\.?\s*id|term|kasse\s*nr|kassenr|kasse)\.?\s*[:#]?\s*(?P<terminal>[A-Z0-9-]*\d[A-Z0-9-]*)"
+    ).unwrap();
+}
+
+// Lines whose dates are not the purchase date
+const IGNORED_DATE_KEYWORDS: &[&str] = &["BYTT", "INNEN", "RETUR", "GYLDIG", "BEST FØR", "FORFALL", "UTLØP", "TIL OG MED"];
+
+#[derive(Serialize, Clone, Default)]
+pub struct TransactionDetails {
+    pub date: Option<NaiveDate>,
+    pub time: Option<NaiveTime>,
+    pub receipt_number: Option<String>,
+    pub cashier: Option<String>,
+    pub terminal_id: Option<String>,
+}
+
+impl TransactionDetails {
+    pub fn is_empty(&self) -> bool {
+        self.date.is_none() && self.time.is_none() && self.receipt_number.is_none()
+            && self.cashier.is_none() && self.terminal_id.is_none()
+    }
+}
+
+pub fn extract_transaction_details(text: &str, today: NaiveDate) -> TransactionDetails {
+    let mut details = TransactionDetails::default();
+    let mut dated_line = false;
+
+    for line in text.lines() {
+        let upper = line.to_uppercase();
+
+        if !dated_line && !IGNORED_DATE_KEYWORDS.iter().any(|keyword| upper.contains(keyword)) {
+            if let Some(date) = find_date(line, today) {
+                // An explicit "Dato" line overrides a date found earlier
+                dated_line = upper.contains("DATO");
+                if details.date.is_none() || dated_line {
+                    details.date = Some(date);
+                }
+            }
+        }
+
+        if details.time.is_none() {
+            details.time = TIME.captures(line).and_then(|captures| parse_time(&captures));
+        }
+        if details.receipt_number.is_none() {
+            details.receipt_number = RECEIPT_NUMBER.captures(line).map(|captures| captures["number"].to_string());
+        }
+        if details.cashier.is_none() {
+            details.cashier = CASHIER.captures(line).map(|captures| captures["cashier"].trim().to_string());
+        }
+        if details.terminal_id.is_none() {
+            details.terminal_id = TERMINAL.captures(line).map(|captures| captures["terminal"].to_string());
+        }
+    }
+
+    details
+}
+
+fn find_date(line: &str, today: NaiveDate) -> Option<NaiveDate> {
+    ISO_DATE.captures_iter(line)
+        .chain(NUMERIC_DATE.captures_iter(line))
+        .find_map(|captures| {
+            let year = parse_year(&captures["year"], today.year())?;
+            NaiveDate::from_ymd_opt(year, captures["month"].parse().ok()?, captures["day"].parse().ok()?)
+        })
+        .or_else(|| WRITTEN_DATE.captures_iter(line).find_map(|captures| {
+            // "17. mai" without a year is the most recent 17 May
+            let month = month_number(&captures["month"])?;
+            let day = captures["day"].parse().ok()?;
+            match captures.name("year") {
+                Some(year) => NaiveDate::from_ymd_opt(year.as_str().parse().ok()?, month, day),
+                None => NaiveDate::from_ymd_opt(today.year(), month, day)
+                    .filter(|date| *date <= today)
+                    .or_else(|| NaiveDate::from_ymd_opt(today.year() - 1, month, day)),
+            }
+        }))
+}
+
+// Two-digit years are read as this century unless that lies in the future
+fn parse_year(text: &str, current: i32) -> Option<i32> {
+    let year: i32 = text.parse().ok()?;
+    if text.len() == 4 {
+        return Some(year);
+    }
+    let year = 2000 + year;
+    Some(if year > current + 1 { year - 100 } else { year })
+}
+
+fn month_number(name: &str) -> Option<u32> {
+    let month = match &name.to_lowercase()[..3] {
+        "jan" => 1, "feb" => 2, "mar" => 3, "apr" => 4, "mai" => 5, "jun" => 6,
+        "jul" => 7, "aug" => 8, "sep" => 9, "okt" => 10, "nov" => 11, "des" => 12,
+        _ => return None,
+    };
+    Some(month)
+}
+
+fn parse_time(captures: &Captures) -> Option<NaiveTime> {
+    let (hour, minute) = match (captures.name("kl_hour"), captures.name("kl_minute")) {
+        (Some(hour), Some(minute)) => (hour, minute),
+        _ => (captures.name("hour")?, captures.name("minute")?),
+    };
+    let second = captures.name("second").and_then(|second| second.as_str().parse().ok()).unwrap_or(0);
+    NaiveTime::from_hms_opt(hour.as_str().parse().ok()?, minute.as_str().parse().ok()?, second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn date_in(text: &str) -> Option<NaiveDate> {
+        extract_transaction_details(text, date(2024, 6, 1)).date
+    }
+
+    #[test]
+    fn numeric_and_iso_dates_are_read() {
+        assert_eq!(date_in("REMA 1000\n17.05.2024 14:32"), Some(date(2024, 5, 17)));
+        assert_eq!(date_in("17/05/24"), Some(date(2024, 5, 17)));
+        assert_eq!(date_in("3-1-2024"), Some(date(2024, 1, 3)));
+        assert_eq!(date_in("Kjøpt 2024-05-17T14:32:05"), Some(date(2024, 5, 17)));
+        assert_eq!(date_in("31.02.2024"), None);
+    }
+
+    #[test]
+    fn two_digit_years_are_never_far_in_the_future() {
+        assert_eq!(date_in("01.01.25"), Some(date(2025, 1, 1)));
+        assert_eq!(date_in("01.01.26"), Some(date(1926, 1, 1)));
+        assert_eq!(date_in("01.01.99"), Some(date(1999, 1, 1)));
+    }
+
+    #[test]
+    fn written_dates_without_a_year_are_the_most_recent_one() {
+        assert_eq!(date_in("17. mai 2023"), Some(date(2023, 5, 17)));
+        assert_eq!(date_in("17. mai"), Some(date(2024, 5, 17)));
+        assert_eq!(date_in("1 juni"), Some(date(2024, 6, 1)));
+        assert_eq!(date_in("24. des."), Some(date(2023, 12, 24)));
+    }
+
+    #[test]
+    fn a_dato_line_overrides_other_dates() {
+        let text = "Åpningstider fra 01.01.2024\nDato: 17.05.2024\nSkrevet ut 18.05.2024";
+        assert_eq!(date_in(text), Some(date(2024, 5, 17)));
+    }
+
+    #[test]
+    fn deadlines_and_best_before_dates_are_ignored() {
+        let text = "Bytt innen 17.06.2024\nBest før: 01.07.2024\nForfall 30.05.2024\nKvittering 15.05.2024";
+        assert_eq!(date_in(text), Some(date(2024, 5, 15)));
+        assert_eq!(date_in("Retur til og med 17.06.2024"), None);
+    }
+
+    #[test]
+    fn time_receipt_number_cashier_and_terminal_are_read() {
+        let details = extract_transaction_details(
+            "Kvittering nr: 12345\nKasserer: Kari N\nTerminal ID: T-0042\nkl 14.32",
+            date(2024, 6, 1),
+        );
+        assert_eq!(details.receipt_number.as_deref(), Some("12345"));
+        assert_eq!(details.cashier.as_deref(), Some("Kari N"));
+        assert_eq!(details.terminal_id.as_deref(), Some("T-0042"));
+        assert_eq!(details.time, NaiveTime::from_hms_opt(14, 32, 0));
+        assert!(details.date.is_none());
+    }
+}
@@ -0,0 +1,262 @@
+// Norwegian VAT rates by category and effective period, so a receipt is
+// analysed with the rates in force on its transaction date (23% standard rate
+// before 2001, 14% on food 2007-2011, the 6% COVID-19 low rate and so on).
+//
+// VAT_RATES_FILE=/path/vat_rates.toml  rate table replacing the built-in one
+//     (.json files are read as JSON); re-read whenever the file changes
+//
+// The built-in table is src/vat_rates.toml.
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+const BUILT_IN_RATES: &str = include_str!("vat_rates.toml");
+
+#[derive(Debug)]
+pub struct VatRateError(String);
+
+impl fmt::Display for VatRateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Deserialize)]
+struct RateTable {
+    rates: Vec<RatePeriodEntry>,
+}
+
+#[derive(Deserialize)]
+struct RatePeriodEntry {
+    category: String,
+    rate: u8,
+    from: DateEntry,
+    to: Option<DateEntry>,
+    description: Option<String>,
+}
+
+// Dates may be quoted strings or bare TOML dates
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DateEntry {
+    Text(String),
+    Toml(toml::value::Datetime),
+}
+
+impl DateEntry {
+    fn parse(&self) -> Result<NaiveDate, VatRateError> {
+        let text = match self {
+            DateEntry::Text(text) => text.clone(),
+            DateEntry::Toml(datetime) => datetime.to_string(),
+        };
+        NaiveDate::parse_from_str(&text, "%Y-%m-%d")
+            .map_err(|_| VatRateError(format!("'{}' is not a YYYY-MM-DD date", text)))
+    }
+}
+
+#[derive(Clone)]
+pub struct VatRatePeriod {
+    pub category: String,
+    pub rate: u8,
+    pub from: NaiveDate,
+    pub to: Option<NaiveDate>, // inclusive, None while in force
+    pub description: Option<String>,
+}
+
+impl VatRatePeriod {
+    fn covers(&self, date: NaiveDate) -> bool {
+        self.from <= date && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+// The rate of each category on one day
+pub struct EffectiveRates {
+    pub date: NaiveDate,
+    periods: Vec<VatRatePeriod>,
+}
+
+impl EffectiveRates {
+    pub fn rate(&self, category: &str) -> Option<u8> {
+        self.period(category).map(|period| period.rate)
+    }
+
+    // Category a rate belongs to on this day, e.g. 14 -> food in 2010
+    pub fn category_of(&self, rate: u8) -> Option<&str> {
+        self.periods.iter().find(|period| period.rate == rate).map(|period| period.category.as_str())
+    }
+
+    pub fn explanation(&self, rate: u8) -> String {
+        let Some(period) = self.periods.iter().find(|period| period.rate == rate) else {
+            return format!("{}% is not a Norwegian VAT rate on {}", rate, self.date);
+        };
+        if let Some(description) = &period.description {
+            return description.clone();
+        }
+        match period.category.as_str() {
+            "standard" => "Standard VAT rate for general goods and services".to_string(),
+            "food" => "Reduced VAT rate for food and non-alcoholic beverages".to_string(),
+            "low" => "Low VAT rate for passenger transport, cinema and hotel accommodation".to_string(),
+            "zero" => "VAT-exempt goods (books, newspapers, medicine)".to_string(),
+            category => format!("VAT rate for {}", category),
+        }
+    }
+
+    fn period(&self, category: &str) -> Option<&VatRatePeriod> {
+        self.periods.iter().find(|period| period.category == category)
+    }
+}
+
+pub struct VatRateRegistry {
+    path: Option<String>,
+    state: RwLock<RegistryState>,
+}
+
+struct RegistryState {
+    periods: Vec<VatRatePeriod>,
+    modified: Option<SystemTime>,
+}
+
+impl VatRateRegistry {
+    pub fn from_env() -> Self {
+        let path = env::var("VAT_RATES_FILE").ok();
+        let (periods, modified) = match &path {
+            Some(path) => match read_file(path) {
+                Ok(loaded) => loaded,
+                // Wrong rates would silently misstate VAT, so a broken file stops the boot
+                Err(e) => panic!("❌ Could not load VAT rates from {}: {}", path, e),
+            },
+            None => (parse_table(BUILT_IN_RATES, false).expect("built-in VAT rate table is valid"), None),
+        };
+
+        let registry = VatRateRegistry { path, state: RwLock::new(RegistryState { periods, modified }) };
+        let today = registry.on(chrono::Utc::now().date_naive());
+        let in_force: Vec<String> = ["standard", "food", "low"].iter()
+            .filter_map(|category| today.rate(category).map(|rate| format!("{} {}%", category, rate)))
+            .collect();
+        println!("   - {} rate periods from {}; today: {}",
+                 registry.periods().len(),
+                 registry.path.as_deref().unwrap_or("the built-in table"),
+                 in_force.join(", "));
+        registry
+    }
+
+    // Rates in force on `date`
+    pub fn on(&self, date: NaiveDate) -> EffectiveRates {
+        self.reload_if_changed();
+        EffectiveRates {
+            date,
+            periods: self.periods().into_iter().filter(|period| period.covers(date)).collect(),
+        }
+    }
+
+    fn periods(&self) -> Vec<VatRatePeriod> {
+        self.state.read().map(|state| state.periods.clone()).unwrap_or_default()
+    }
+
+    fn reload_if_changed(&self) {
+        let Some(path) = &self.path else { return };
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let current = self.state.read().ok().and_then(|state| state.modified);
+        if modified.is_none() || modified == current {
+            return;
+        }
+
+        match read_file(path) {
+            Ok((periods, modified)) => {
+                println!("🧾 Reloaded {} VAT rate periods from {}", periods.len(), path);
+                if let Ok(mut state) = self.state.write() {
+                    *state = RegistryState { periods, modified };
+                }
+            },
+            Err(e) => {
+                println!("⚠️  Keeping the previous VAT rates, {} is invalid: {}", path, e);
+                // Remember the broken version so it is not re-read on every request
+                if let Ok(mut state) = self.state.write() {
+                    state.modified = modified;
+                }
+            },
+        }
+    }
+}
+
+// The built-in table's rates on `date`, for tests elsewhere
+#[cfg(test)]
+pub fn built_in_rates(date: NaiveDate) -> EffectiveRates {
+    let periods = parse_table(BUILT_IN_RATES, false).expect("built-in VAT rate table is valid");
+    EffectiveRates { date, periods: periods.into_iter().filter(|period| period.covers(date)).collect() }
+}
+
+fn read_file(path: &str) -> Result<(Vec<VatRatePeriod>, Option<SystemTime>), VatRateError> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let text = fs::read_to_string(path).map_err(|e| VatRateError(e.to_string()))?;
+    Ok((parse_table(&text, path.ends_with(".json"))?, modified))
+}
+
+fn parse_table(text: &str, json: bool) -> Result<Vec<VatRatePeriod>, VatRateError> {
+    let table: RateTable = if json {
+        serde_json::from_str(text).map_err(|e| VatRateError(e.to_string()))?
+    } else {
+        toml::from_str(text).map_err(|e| VatRateError(e.to_string()))?
+    };
+
+    let mut periods = Vec::new();
+    for entry in table.rates {
+        let period = VatRatePeriod {
+            category: entry.category.trim().to_lowercase(),
+            rate: entry.rate,
+            from: entry.from.parse()?,
+            to: entry.to.as_ref().map(DateEntry::parse).transpose()?,
+            description: entry.description,
+        };
+        if period.rate > 100 || period.to.is_some_and(|to| to < period.from) {
+            return Err(VatRateError(format!("invalid {} period of {}% from {}", period.category, period.rate, period.from)));
+        }
+        periods.push(period);
+    }
+
+    // Two rates for the same category on one day would make lookups ambiguous
+    let mut by_category: BTreeMap<&str, Vec<&VatRatePeriod>> = BTreeMap::new();
+    for period in &periods {
+        by_category.entry(period.category.as_str()).or_default().push(period);
+    }
+    for (category, mut category_periods) in by_category {
+        category_periods.sort_by_key(|period| period.from);
+        for pair in category_periods.windows(2) {
+            if pair[0].to.is_none_or(|to| to >= pair[1].from) {
+                return Err(VatRateError(format!("{} periods starting {} and {} overlap", category, pair[0].from, pair[1].from)));
+            }
+        }
+    }
+
+    Ok(periods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standard_rate_on(date: &str) -> Option<u8> {
+        built_in_rates(date.parse().unwrap()).rate("standard")
+    }
+
+    #[test]
+    fn standard_rate_follows_the_date() {
+        assert_eq!(standard_rate_on("1998-06-15"), Some(23));
+        assert_eq!(standard_rate_on("2000-12-31"), Some(23));
+        assert_eq!(standard_rate_on("2001-01-01"), Some(24));
+        assert_eq!(standard_rate_on("2004-12-31"), Some(24));
+        assert_eq!(standard_rate_on("2005-01-01"), Some(25));
+    }
+
+    #[test]
+    fn overlapping_periods_are_rejected() {
+        let table = "[[rates]]\ncategory = \"low\"\nrate = 6\nfrom = \"2020-01-01\"\n\n[[rates]]\ncategory = \"low\"\nrate = 12\nfrom = \"2020-06-01\"\n";
+        assert!(parse_table(table, false).is_err());
+    }
+}